@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use mini_lsm::block::{Block, BlockBuilder, BlockIterator};
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("user_{:08}_profile", idx).into_bytes()
+}
+
+/// Build a 64KB block of 16-byte values, and return it with the number of entries.
+fn build_block() -> (Arc<Block>, usize) {
+    let mut builder = BlockBuilder::new(64 << 10);
+    let mut num_entries = 0;
+    while builder.add(&key_of(num_entries), &[b'v'; 16]) {
+        num_entries += 1;
+    }
+    (Arc::new(builder.build()), num_entries)
+}
+
+fn bench_scan(c: &mut Criterion) {
+    let (block, _) = build_block();
+    c.bench_function("block_iterator_scan", |b| {
+        b.iter(|| {
+            let mut iter = BlockIterator::create_and_seek_to_first(block.clone());
+            let mut total_len = 0;
+            while iter.is_valid() {
+                total_len += iter.key().len() + iter.value().len();
+                iter.next();
+            }
+            black_box(total_len)
+        })
+    });
+}
+
+fn bench_seek(c: &mut Criterion) {
+    let (block, num_entries) = build_block();
+    let keys: Vec<_> = (0..num_entries).step_by(7).map(key_of).collect();
+    c.bench_function("block_iterator_seek", |b| {
+        b.iter(|| {
+            for key in &keys {
+                let iter = BlockIterator::create_and_seek_to_key(block.clone(), key);
+                black_box(iter.value());
+            }
+        })
+    });
+}
+
+criterion_group!(benches, bench_scan, bench_seek);
+criterion_main!(benches);
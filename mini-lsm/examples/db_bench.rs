@@ -0,0 +1,535 @@
+//! A benchmark of the storage in the style of RocksDB's `db_bench`. It runs a list of workloads
+//! in order on one storage, and reports their throughput and latency, followed by the write and
+//! space amplification of the storage.
+//!
+//! ```text
+//! cargo run --release --example db_bench -- --benchmarks fillrandom,readrandom --num 1000000
+//! ```
+
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use clap::{Parser, ValueEnum};
+use mini_lsm::compact::{CompactionOptions, LeveledCompactionOptions, TieredCompactionOptions};
+use mini_lsm::env::{
+    FileBackend, FileLock, FileSystem, MemFileSystem, PosixFileSystem, RandomAccessFile,
+    WritableFile,
+};
+use mini_lsm::iterators::StorageIterator;
+use mini_lsm::lsm_storage::{ColumnFamilyOptions, LsmStorage, LsmStorageOptions};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+
+const BENCHMARKS: &[&str] = &[
+    "fillseq",
+    "fillrandom",
+    "overwrite",
+    "readrandom",
+    "readseq",
+    "seekrandom",
+    "readwhilewriting",
+    "deleterandom",
+];
+
+#[derive(Parser)]
+struct Args {
+    /// The benchmarks to run in order: fillseq, fillrandom, overwrite, readrandom, readseq,
+    /// seekrandom, readwhilewriting and deleterandom.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "fillseq,fillrandom,overwrite,readrandom,readseq,seekrandom,readwhilewriting,deleterandom"
+    )]
+    benchmarks: Vec<String>,
+    /// The directory of the storage, which must be empty. A temporary directory by default.
+    #[arg(long)]
+    db: Option<PathBuf>,
+    /// Keep all files in memory instead of on disk.
+    #[arg(long)]
+    in_memory: bool,
+    /// The number of keys, and of writes of each write benchmark.
+    #[arg(long, default_value_t = 100_000)]
+    num: u64,
+    /// The number of reads or seeks of each read benchmark. `num` by default.
+    #[arg(long)]
+    reads: Option<u64>,
+    #[arg(long, default_value_t = 16)]
+    key_size: usize,
+    #[arg(long, default_value_t = 100)]
+    value_size: usize,
+    /// The number of threads of each benchmark. readwhilewriting runs a writer thread on top.
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+    /// The number of keys read after each seek of seekrandom.
+    #[arg(long, default_value_t = 10)]
+    seek_nexts: usize,
+    #[arg(long, default_value_t = 4096)]
+    block_size: usize,
+    #[arg(long, default_value_t = 2 << 20)]
+    target_sst_size: usize,
+    /// Flush the memtable once this many bytes of keys and values were written to it.
+    #[arg(long, default_value_t = 4 << 20)]
+    write_buffer_size: u64,
+    #[arg(long, value_enum, default_value_t = Compaction::Tiered)]
+    compaction: Compaction,
+    /// Bits per key of the bloom filters of SSTs, or zero for no filters.
+    #[arg(long, default_value_t = 10)]
+    bloom_bits: usize,
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Compaction {
+    None,
+    Leveled,
+    Tiered,
+}
+
+/// Wraps a file system to count the bytes written to files.
+struct CountingFileSystem {
+    base: Arc<dyn FileSystem>,
+    bytes_written: Arc<AtomicU64>,
+}
+
+impl FileSystem for CountingFileSystem {
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        self.base.create_dir_all(path)
+    }
+
+    fn create(&self, path: &Path) -> Result<Box<dyn WritableFile>> {
+        Ok(Box::new(CountingWritableFile {
+            base: self.base.create(path)?,
+            bytes_written: self.bytes_written.clone(),
+        }))
+    }
+
+    fn open(&self, path: &Path, backend: FileBackend) -> Result<Box<dyn RandomAccessFile>> {
+        self.base.open(path, backend)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.base.rename(from, to)
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+        self.base.delete(path)
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<String>> {
+        self.base.list(dir)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.base.exists(path)
+    }
+
+    fn lock(&self, path: &Path) -> Result<Box<dyn FileLock>> {
+        self.base.lock(path)
+    }
+}
+
+struct CountingWritableFile {
+    base: Box<dyn WritableFile>,
+    bytes_written: Arc<AtomicU64>,
+}
+
+impl WritableFile for CountingWritableFile {
+    fn append(&mut self, data: &[u8]) -> Result<()> {
+        self.base.append(data)?;
+        self.bytes_written
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.base.sync()
+    }
+}
+
+/// What a thread of a benchmark measured.
+#[derive(Default)]
+struct ThreadStats {
+    /// The latency of every operation, in nanoseconds.
+    latencies: Vec<u64>,
+    /// The bytes of keys and values read or written.
+    bytes: u64,
+    /// The number of reads that found their key.
+    found: u64,
+}
+
+impl ThreadStats {
+    fn record(&mut self, start: Instant, bytes: usize) {
+        self.latencies.push(start.elapsed().as_nanos() as u64);
+        self.bytes += bytes as u64;
+    }
+
+    fn merge(&mut self, other: ThreadStats) {
+        self.latencies.extend(other.latencies);
+        self.bytes += other.bytes;
+        self.found += other.found;
+    }
+}
+
+struct Bench {
+    args: Args,
+    path: PathBuf,
+    storage: Arc<LsmStorage>,
+    fs: Arc<dyn FileSystem>,
+    /// The bytes written to files by the storage.
+    disk_bytes_written: Arc<AtomicU64>,
+    /// The bytes of keys and values written by the benchmarks.
+    user_bytes_written: AtomicU64,
+    /// The bytes written to the memtable since it was last flushed.
+    unflushed_bytes: AtomicU64,
+    /// Random data that values are sliced from.
+    value_data: Bytes,
+}
+
+impl Bench {
+    fn new(args: Args, path: PathBuf) -> Result<Self> {
+        let base: Arc<dyn FileSystem> = if args.in_memory {
+            Arc::new(MemFileSystem::new())
+        } else {
+            Arc::new(PosixFileSystem)
+        };
+        if base.exists(&path) && !base.list(&path)?.is_empty() {
+            bail!("{} is not empty", path.display());
+        }
+        let disk_bytes_written = Arc::new(AtomicU64::new(0));
+        let fs: Arc<dyn FileSystem> = Arc::new(CountingFileSystem {
+            base,
+            bytes_written: disk_bytes_written.clone(),
+        });
+        let compaction = match args.compaction {
+            Compaction::None => CompactionOptions::NoCompaction,
+            Compaction::Leveled => CompactionOptions::Leveled(LeveledCompactionOptions::default()),
+            Compaction::Tiered => CompactionOptions::Tiered(TieredCompactionOptions::default()),
+        };
+        let options = LsmStorageOptions {
+            default_cf: ColumnFamilyOptions {
+                block_size: args.block_size,
+                target_sst_size: args.target_sst_size,
+                compaction,
+                bloom_bits_per_key: args.bloom_bits,
+                write_buffer_size: args.write_buffer_size as usize,
+                ..Default::default()
+            },
+            file_system: fs.clone(),
+            ..Default::default()
+        };
+        let storage = LsmStorage::open_with_options(&path, options)?;
+        let mut value_data = vec![0; (args.value_size * 2).max(1 << 20)];
+        StdRng::seed_from_u64(args.seed).fill_bytes(&mut value_data);
+        Ok(Self {
+            args,
+            path,
+            storage,
+            fs,
+            disk_bytes_written,
+            user_bytes_written: AtomicU64::new(0),
+            unflushed_bytes: AtomicU64::new(0),
+            value_data: value_data.into(),
+        })
+    }
+
+    /// The key of the idx-th key: its big-endian index padded with zeros, so that keys are ordered
+    /// by index.
+    fn key(&self, idx: u64) -> Vec<u8> {
+        let mut key = vec![0; self.args.key_size.max(8)];
+        key[..8].copy_from_slice(&idx.to_be_bytes());
+        key
+    }
+
+    fn value(&self, rng: &mut StdRng) -> Bytes {
+        let start = rng.gen_range(0..=self.value_data.len() - self.args.value_size);
+        self.value_data.slice(start..start + self.args.value_size)
+    }
+
+    /// Account for a write to the memtable, and flush the memtable if it is full.
+    fn written(&self, bytes: usize) -> Result<()> {
+        self.user_bytes_written
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        let unflushed = self
+            .unflushed_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        // the memtable is only flushed on sync, so the writer that fills it up flushes it
+        if unflushed + bytes as u64 >= self.args.write_buffer_size
+            && self.unflushed_bytes.swap(0, Ordering::Relaxed) >= self.args.write_buffer_size
+        {
+            self.storage.sync()?;
+        }
+        Ok(())
+    }
+
+    fn put(&self, idx: u64, rng: &mut StdRng, stats: &mut ThreadStats) -> Result<()> {
+        let key = self.key(idx);
+        let value = self.value(rng);
+        let start = Instant::now();
+        self.storage.put(&key, &value)?;
+        self.written(key.len() + value.len())?;
+        stats.record(start, key.len() + value.len());
+        Ok(())
+    }
+
+    fn delete(&self, idx: u64, stats: &mut ThreadStats) -> Result<()> {
+        let key = self.key(idx);
+        let start = Instant::now();
+        self.storage.delete(&key)?;
+        self.written(key.len())?;
+        stats.record(start, key.len());
+        Ok(())
+    }
+
+    fn get(&self, idx: u64, stats: &mut ThreadStats) -> Result<()> {
+        let key = self.key(idx);
+        let start = Instant::now();
+        let value = self.storage.get(&key)?;
+        let bytes = match value {
+            Some(value) => {
+                stats.found += 1;
+                key.len() + value.len()
+            }
+            None => 0,
+        };
+        stats.record(start, bytes);
+        Ok(())
+    }
+
+    fn seek(&self, idx: u64, stats: &mut ThreadStats) -> Result<()> {
+        let key = self.key(idx);
+        let start = Instant::now();
+        let mut iter = self.storage.scan(Bound::Included(&key), Bound::Unbounded)?;
+        let mut bytes = 0;
+        if iter.is_valid() {
+            stats.found += 1;
+        }
+        for _ in 0..self.args.seek_nexts {
+            if !iter.is_valid() {
+                break;
+            }
+            bytes += iter.key().len() + iter.value().len();
+            iter.next()?;
+        }
+        stats.record(start, bytes);
+        Ok(())
+    }
+
+    fn read_seq(&self, stats: &mut ThreadStats) -> Result<()> {
+        let mut iter = self.storage.scan(Bound::Unbounded, Bound::Unbounded)?;
+        while iter.is_valid() {
+            let start = Instant::now();
+            let bytes = iter.key().len() + iter.value().len();
+            iter.next()?;
+            stats.record(start, bytes);
+        }
+        Ok(())
+    }
+
+    /// Run `f` on each of `threads` threads with its index and its own random generator.
+    fn run_threads(
+        &self,
+        threads: usize,
+        f: impl Fn(usize, &mut StdRng, &mut ThreadStats) -> Result<()> + Sync,
+    ) -> Result<(Duration, ThreadStats)> {
+        let start = Instant::now();
+        let results = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..threads)
+                .map(|tid| {
+                    let f = &f;
+                    s.spawn(move || {
+                        let mut rng = StdRng::seed_from_u64(self.args.seed + 1 + tid as u64);
+                        let mut stats = ThreadStats::default();
+                        f(tid, &mut rng, &mut stats).map(|_| stats)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|x| x.join().expect("benchmark thread panicked"))
+                .collect::<Vec<_>>()
+        });
+        let elapsed = start.elapsed();
+        let mut stats = ThreadStats::default();
+        for result in results {
+            stats.merge(result?);
+        }
+        Ok((elapsed, stats))
+    }
+
+    fn run(&self, name: &str) -> Result<()> {
+        let args = &self.args;
+        let threads = args.threads.max(1);
+        let num = args.num;
+        let reads = args.reads.unwrap_or(num);
+        // each thread does its share of the operations
+        let share = |tid: usize, total: u64| {
+            total * tid as u64 / threads as u64..total * (tid as u64 + 1) / threads as u64
+        };
+        let (elapsed, stats) = match name {
+            "fillseq" => self.run_threads(threads, |tid, rng, stats| {
+                share(tid, num).try_for_each(|idx| self.put(idx, rng, stats))
+            })?,
+            "fillrandom" | "overwrite" => self.run_threads(threads, |tid, rng, stats| {
+                share(tid, num).try_for_each(|_| self.put(rng.gen_range(0..num), rng, stats))
+            })?,
+            "deleterandom" => self.run_threads(threads, |tid, rng, stats| {
+                share(tid, num).try_for_each(|_| self.delete(rng.gen_range(0..num), stats))
+            })?,
+            "readrandom" => self.run_threads(threads, |tid, rng, stats| {
+                share(tid, reads).try_for_each(|_| self.get(rng.gen_range(0..num), stats))
+            })?,
+            "seekrandom" => self.run_threads(threads, |tid, rng, stats| {
+                share(tid, reads).try_for_each(|_| self.seek(rng.gen_range(0..num), stats))
+            })?,
+            "readseq" => self.run_threads(threads, |_, _, stats| self.read_seq(stats))?,
+            "readwhilewriting" => {
+                let done = AtomicBool::new(false);
+                let writes = AtomicU64::new(0);
+                let result = std::thread::scope(|s| {
+                    let writer = s.spawn(|| -> Result<()> {
+                        let mut rng = StdRng::seed_from_u64(args.seed);
+                        let mut stats = ThreadStats::default();
+                        while !done.load(Ordering::Relaxed) {
+                            self.put(rng.gen_range(0..num), &mut rng, &mut stats)?;
+                            writes.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Ok(())
+                    });
+                    let result = self.run_threads(threads, |tid, rng, stats| {
+                        share(tid, reads).try_for_each(|_| self.get(rng.gen_range(0..num), stats))
+                    });
+                    done.store(true, Ordering::Relaxed);
+                    writer.join().expect("writer thread panicked")?;
+                    result
+                })?;
+                println!(
+                    "{:<18} : {} writes alongside the reads",
+                    "",
+                    writes.load(Ordering::Relaxed)
+                );
+                result
+            }
+            _ => bail!("unknown benchmark {}", name),
+        };
+        report(name, elapsed, stats);
+        Ok(())
+    }
+
+    /// Flush the memtable and wait for compaction to finish, so that all data is in SSTs.
+    fn settle(&self) -> Result<()> {
+        self.storage.sync()?;
+        let deadline = Instant::now() + Duration::from_secs(60);
+        while self.storage.stats()?.pending_compaction_bytes > 0 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        Ok(())
+    }
+
+    /// Print the bytes written to files per byte written by users, and the size of the SSTs per
+    /// byte of live data.
+    fn report_amplification(&self) -> Result<()> {
+        let user_bytes = self.user_bytes_written.load(Ordering::Relaxed);
+        let disk_bytes = self.disk_bytes_written.load(Ordering::Relaxed);
+        println!(
+            "write amplification: {:.2} ({} bytes written to files, {} bytes written by users)",
+            disk_bytes as f64 / user_bytes.max(1) as f64,
+            disk_bytes,
+            user_bytes
+        );
+
+        let mut sst_bytes = 0;
+        for name in self.fs.list(&self.path)? {
+            if name.ends_with(".sst") {
+                sst_bytes += self
+                    .fs
+                    .open(&self.path.join(name), FileBackend::Pread)?
+                    .size();
+            }
+        }
+        let mut live_bytes = 0;
+        let mut iter = self.storage.scan(Bound::Unbounded, Bound::Unbounded)?;
+        while iter.is_valid() {
+            live_bytes += (iter.key().len() + iter.value().len()) as u64;
+            iter.next()?;
+        }
+        println!(
+            "space amplification: {:.2} ({} bytes of SSTs, {} bytes of live keys and values)",
+            sst_bytes as f64 / live_bytes.max(1) as f64,
+            sst_bytes,
+            live_bytes
+        );
+        Ok(())
+    }
+}
+
+fn report(name: &str, elapsed: Duration, mut stats: ThreadStats) {
+    let ops = stats.latencies.len();
+    let secs = elapsed.as_secs_f64();
+    let mut line = format!(
+        "{:<18} : {:>10.3} micros/op {:>10.0} ops/sec {:>8.1} MB/s",
+        name,
+        secs * 1e6 / ops.max(1) as f64,
+        ops as f64 / secs,
+        stats.bytes as f64 / secs / (1 << 20) as f64,
+    );
+    if stats.found > 0 {
+        line += &format!(" ({} of {} found)", stats.found, ops);
+    }
+    println!("{}", line);
+    if ops == 0 {
+        return;
+    }
+    stats.latencies.sort_unstable();
+    let percentile =
+        |p: f64| stats.latencies[((ops - 1) as f64 * p / 100.0).round() as usize] as f64 / 1e3;
+    println!(
+        "{:<18} : latency (micros) p50 {:.2} p95 {:.2} p99 {:.2} p99.9 {:.2} max {:.2}",
+        "",
+        percentile(50.0),
+        percentile(95.0),
+        percentile(99.0),
+        percentile(99.9),
+        percentile(100.0),
+    );
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    if args.value_size == 0 {
+        bail!("values cannot be empty");
+    }
+    for name in &args.benchmarks {
+        if !BENCHMARKS.contains(&name.as_str()) {
+            bail!("unknown benchmark {}", name);
+        }
+    }
+    let temp_dir;
+    let path = match &args.db {
+        Some(path) => path.clone(),
+        None if args.in_memory => PathBuf::from("/db"),
+        None => {
+            temp_dir = tempfile::tempdir()?;
+            temp_dir.path().to_path_buf()
+        }
+    };
+    let benchmarks = args.benchmarks.clone();
+    println!(
+        "keys: {} bytes, values: {} bytes, entries: {}, threads: {}",
+        args.key_size.max(8),
+        args.value_size,
+        args.num,
+        args.threads.max(1)
+    );
+    let bench = Bench::new(args, path)?;
+    for name in &benchmarks {
+        bench.run(name)?;
+    }
+    bench.settle()?;
+    bench.report_amplification()
+}
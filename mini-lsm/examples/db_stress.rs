@@ -0,0 +1,476 @@
+//! A stress test of the storage. Threads run random puts, deletes, write batches, gets and scans
+//! on a small keyspace while background threads flush and compact. An expected state records what
+//! every key must hold, and every read is checked against it.
+//!
+//! ```text
+//! cargo run --release --example db_stress -- --threads 16 --duration-secs 600
+//! ```
+//!
+//! Every key has a lock in the expected state. An operation holds the locks of the keys it touches
+//! while it runs, so a read must see exactly the expected values of its keys. Batches and scans
+//! take their locks in key order.
+//!
+//! With `--reopen-interval-ms`, the storage is killed without a sync now and then, opened again,
+//! and checked against the expected state, since a killed process does not lose the writes in its
+//! WAL.
+
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+use clap::Parser;
+use mini_lsm::compact::{CompactionOptions, TieredCompactionOptions};
+use mini_lsm::env::{FileSystem, MemFileSystem, PosixFileSystem};
+use mini_lsm::iterators::StorageIterator;
+use mini_lsm::lsm_storage::{ColumnFamilyOptions, LsmStorage, LsmStorageOptions};
+use mini_lsm::write_batch::WriteBatch;
+use parking_lot::{Mutex, MutexGuard, RwLock};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[derive(Parser)]
+struct Args {
+    /// The directory of the storage, which must be empty. A temporary directory by default.
+    #[arg(long)]
+    db: Option<PathBuf>,
+    /// Keep all files in memory instead of on disk.
+    #[arg(long)]
+    in_memory: bool,
+    #[arg(long, default_value_t = 8)]
+    threads: usize,
+    /// How long to run.
+    #[arg(long, default_value_t = 60)]
+    duration_secs: u64,
+    /// The number of keys. A smaller keyspace makes threads contend on the same keys.
+    #[arg(long, default_value_t = 10_000)]
+    num_keys: usize,
+    #[arg(long, default_value_t = 64)]
+    value_size: usize,
+    /// The maximum number of keys written by a batch.
+    #[arg(long, default_value_t = 8)]
+    max_batch_size: usize,
+    /// The maximum number of keys covered by a scan.
+    #[arg(long, default_value_t = 32)]
+    max_scan_len: usize,
+    /// Flush the memtable this often.
+    #[arg(long, default_value_t = 100)]
+    flush_interval_ms: u64,
+    /// Compact a random key range this often.
+    #[arg(long, default_value_t = 1000)]
+    compact_range_interval_ms: u64,
+    /// Kill and reopen the storage this often. Never by default.
+    #[arg(long, default_value_t = 0)]
+    reopen_interval_ms: u64,
+    #[arg(long, default_value_t = 1024)]
+    block_size: usize,
+    #[arg(long, default_value_t = 64 << 10)]
+    target_sst_size: usize,
+    #[arg(long, default_value_t = 2)]
+    max_subcompactions: usize,
+    #[arg(long, default_value_t = 10)]
+    bloom_bits: usize,
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+/// What every key must hold: the seed of its value, or `None` if it is deleted.
+struct ExpectedState {
+    keys: Vec<Mutex<Option<u32>>>,
+}
+
+impl ExpectedState {
+    fn new(num_keys: usize) -> Self {
+        Self {
+            keys: (0..num_keys).map(|_| Mutex::new(None)).collect(),
+        }
+    }
+
+    /// Lock the keys in `range`, in order.
+    fn lock_range(&self, range: std::ops::Range<usize>) -> Vec<MutexGuard<'_, Option<u32>>> {
+        self.keys[range].iter().map(|x| x.lock()).collect()
+    }
+}
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:010}", idx).into_bytes()
+}
+
+/// The length of the prefix of values made of the key index and the seed.
+const VALUE_PREFIX_LEN: usize = 20;
+
+/// The value written to a key with a seed. It starts with the key index and the seed, so that a
+/// value read from the wrong key or from an older write is told apart.
+fn value_of(idx: usize, seed: u32, value_size: usize) -> Vec<u8> {
+    let mut value = format!("{:010}:{:08x}:", idx, seed).into_bytes();
+    let fill = seed.to_le_bytes();
+    while value.len() < value_size {
+        value.push(fill[value.len() % fill.len()]);
+    }
+    value.truncate(value_size);
+    value
+}
+
+/// The number of operations of each kind, shared by all threads.
+#[derive(Default)]
+struct Counters {
+    puts: AtomicU64,
+    deletes: AtomicU64,
+    batches: AtomicU64,
+    gets: AtomicU64,
+    scans: AtomicU64,
+    flushes: AtomicU64,
+    compactions: AtomicU64,
+    reopens: AtomicU64,
+}
+
+impl Counters {
+    fn total(&self) -> u64 {
+        [
+            &self.puts,
+            &self.deletes,
+            &self.batches,
+            &self.gets,
+            &self.scans,
+        ]
+        .iter()
+        .map(|x| x.load(Ordering::Relaxed))
+        .sum()
+    }
+
+    fn print(&self) {
+        println!(
+            "puts: {}, deletes: {}, batches: {}, gets: {}, scans: {}, flushes: {}, compactions: {}, \
+             reopens: {}",
+            self.puts.load(Ordering::Relaxed),
+            self.deletes.load(Ordering::Relaxed),
+            self.batches.load(Ordering::Relaxed),
+            self.gets.load(Ordering::Relaxed),
+            self.scans.load(Ordering::Relaxed),
+            self.flushes.load(Ordering::Relaxed),
+            self.compactions.load(Ordering::Relaxed),
+            self.reopens.load(Ordering::Relaxed),
+        );
+    }
+}
+
+struct Stress {
+    args: Args,
+    path: PathBuf,
+    options: LsmStorageOptions,
+    /// Every operation holds the read lock while it runs, and takes it before the locks of its
+    /// keys. Reopening the storage holds the write lock, and takes the storage out meanwhile.
+    storage: RwLock<Option<Arc<LsmStorage>>>,
+    expected: ExpectedState,
+    counters: Counters,
+    /// Set when the run is over or has failed.
+    stop: AtomicBool,
+}
+
+impl Stress {
+    fn new(args: Args, path: &Path) -> Result<Self> {
+        let fs: Arc<dyn FileSystem> = if args.in_memory {
+            Arc::new(MemFileSystem::new())
+        } else {
+            Arc::new(PosixFileSystem)
+        };
+        if fs.exists(path) && !fs.list(path)?.is_empty() {
+            bail!("{} is not empty", path.display());
+        }
+        let options = LsmStorageOptions {
+            default_cf: ColumnFamilyOptions {
+                block_size: args.block_size,
+                target_sst_size: args.target_sst_size,
+                compaction: CompactionOptions::Tiered(TieredCompactionOptions::default()),
+                max_subcompactions: args.max_subcompactions,
+                bloom_bits_per_key: args.bloom_bits,
+                ..Default::default()
+            },
+            file_system: fs,
+            ..Default::default()
+        };
+        Ok(Self {
+            storage: RwLock::new(Some(LsmStorage::open_with_options(path, options.clone())?)),
+            path: path.to_path_buf(),
+            options,
+            expected: ExpectedState::new(args.num_keys),
+            counters: Counters::default(),
+            stop: AtomicBool::new(false),
+            args,
+        })
+    }
+
+    /// Check a value read from the storage against the expected state of the key.
+    fn verify(&self, idx: usize, expected: Option<u32>, actual: Option<&[u8]>) -> Result<()> {
+        let expected = expected.map(|seed| value_of(idx, seed, self.args.value_size));
+        if expected.as_deref() != actual {
+            bail!(
+                "key {}: expected {:?}, found {:?}",
+                idx,
+                expected.map(|x| String::from_utf8_lossy(&x).into_owned()),
+                actual.map(|x| String::from_utf8_lossy(x).into_owned()),
+            );
+        }
+        Ok(())
+    }
+
+    fn put(&self, storage: &LsmStorage, rng: &mut StdRng) -> Result<()> {
+        let idx = rng.gen_range(0..self.args.num_keys);
+        let seed = rng.gen();
+        let mut state = self.expected.keys[idx].lock();
+        storage.put(&key_of(idx), &value_of(idx, seed, self.args.value_size))?;
+        *state = Some(seed);
+        self.counters.puts.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn delete(&self, storage: &LsmStorage, rng: &mut StdRng) -> Result<()> {
+        let idx = rng.gen_range(0..self.args.num_keys);
+        let mut state = self.expected.keys[idx].lock();
+        storage.delete(&key_of(idx))?;
+        *state = None;
+        self.counters.deletes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn batch(&self, storage: &LsmStorage, rng: &mut StdRng) -> Result<()> {
+        let len = rng.gen_range(1..=self.args.max_batch_size.max(1));
+        let mut keys: Vec<_> = (0..len)
+            .map(|_| rng.gen_range(0..self.args.num_keys))
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        let mut states: Vec<_> = keys
+            .iter()
+            .map(|&idx| self.expected.keys[idx].lock())
+            .collect();
+        let mut batch = WriteBatch::new();
+        let mut new_states = Vec::with_capacity(keys.len());
+        for &idx in &keys {
+            if rng.gen_bool(0.8) {
+                let seed = rng.gen();
+                batch.put(&key_of(idx), &value_of(idx, seed, self.args.value_size));
+                new_states.push(Some(seed));
+            } else {
+                batch.delete(&key_of(idx));
+                new_states.push(None);
+            }
+        }
+        storage.write(&batch)?;
+        for (state, new_state) in states.iter_mut().zip(new_states) {
+            **state = new_state;
+        }
+        self.counters.batches.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn get(&self, storage: &LsmStorage, rng: &mut StdRng) -> Result<()> {
+        let idx = rng.gen_range(0..self.args.num_keys);
+        let state = self.expected.keys[idx].lock();
+        let value = storage.get(&key_of(idx))?;
+        self.verify(idx, *state, value.as_deref())?;
+        self.counters.gets.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn scan(&self, storage: &LsmStorage, rng: &mut StdRng) -> Result<()> {
+        let start = rng.gen_range(0..self.args.num_keys);
+        let len = rng.gen_range(1..=self.args.max_scan_len.max(1));
+        let end = (start + len).min(self.args.num_keys);
+        let states = self.expected.lock_range(start..end);
+        let mut iter = storage.scan(
+            Bound::Included(&key_of(start)),
+            Bound::Excluded(&key_of(end)),
+        )?;
+        for (idx, state) in (start..end).zip(&states) {
+            let Some(seed) = **state else {
+                continue;
+            };
+            if !iter.is_valid() {
+                bail!("scan of [{}, {}) ended before key {}", start, end, idx);
+            }
+            if iter.key() != key_of(idx) {
+                bail!(
+                    "scan of [{}, {}) returned {} instead of key {}",
+                    start,
+                    end,
+                    String::from_utf8_lossy(iter.key()),
+                    idx
+                );
+            }
+            self.verify(idx, Some(seed), Some(iter.value()))?;
+            iter.next()?;
+        }
+        if iter.is_valid() {
+            bail!(
+                "scan of [{}, {}) returned deleted key {}",
+                start,
+                end,
+                String::from_utf8_lossy(iter.key())
+            );
+        }
+        self.counters.scans.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn run_worker(&self, tid: usize) -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(self.args.seed + 1 + tid as u64);
+        while !self.stop.load(Ordering::Relaxed) {
+            let storage = self.storage.read();
+            let storage = storage.as_ref().unwrap();
+            match rng.gen_range(0..100) {
+                0..=29 => self.put(storage, &mut rng)?,
+                30..=39 => self.delete(storage, &mut rng)?,
+                40..=49 => self.batch(storage, &mut rng)?,
+                50..=84 => self.get(storage, &mut rng)?,
+                _ => self.scan(storage, &mut rng)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush the memtable often, and compact a random range now and then. Compaction of the
+    /// flushed SSTs also runs in the background.
+    fn run_flusher(&self) -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(self.args.seed);
+        let flush_interval = Duration::from_millis(self.args.flush_interval_ms);
+        let compact_interval = Duration::from_millis(self.args.compact_range_interval_ms);
+        let mut last_compaction = Instant::now();
+        while !self.stop.load(Ordering::Relaxed) {
+            std::thread::sleep(flush_interval);
+            let storage = self.storage.read();
+            let storage = storage.as_ref().unwrap();
+            storage.sync()?;
+            self.counters.flushes.fetch_add(1, Ordering::Relaxed);
+            if last_compaction.elapsed() >= compact_interval {
+                let start = rng.gen_range(0..self.args.num_keys);
+                let end = rng.gen_range(start..=self.args.num_keys);
+                storage.compact_range(
+                    Bound::Included(&key_of(start)),
+                    Bound::Excluded(&key_of(end)),
+                )?;
+                self.counters.compactions.fetch_add(1, Ordering::Relaxed);
+                last_compaction = Instant::now();
+            }
+        }
+        Ok(())
+    }
+
+    /// Kill the storage without syncing it now and then, open it again, and check that it holds
+    /// every acknowledged write.
+    fn run_reopener(&self) -> Result<()> {
+        let interval = Duration::from_millis(self.args.reopen_interval_ms);
+        loop {
+            std::thread::sleep(interval);
+            if self.stop.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            let mut storage = self.storage.write();
+            // Wait until the compaction thread lets go of the storage, so that it no longer
+            // touches the files.
+            let mut old = storage.take().unwrap();
+            while let Err(x) = Arc::try_unwrap(old) {
+                old = x;
+                std::thread::yield_now();
+            }
+            let new = storage.insert(LsmStorage::open_with_options(
+                &self.path,
+                self.options.clone(),
+            )?);
+            self.verify_all(new)?;
+            self.counters.reopens.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Run a thread, and stop all others if it fails.
+    fn run_thread(&self, f: impl FnOnce() -> Result<()>) -> Result<()> {
+        let result = f();
+        if result.is_err() {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn run(&self) -> Result<()> {
+        let start = Instant::now();
+        let duration = Duration::from_secs(self.args.duration_secs);
+        let results = std::thread::scope(|s| {
+            let mut handles = vec![s.spawn(|| self.run_thread(|| self.run_flusher()))];
+            if self.args.reopen_interval_ms > 0 {
+                handles.push(s.spawn(|| self.run_thread(|| self.run_reopener())));
+            }
+            for tid in 0..self.args.threads {
+                handles.push(s.spawn(move || self.run_thread(|| self.run_worker(tid))));
+            }
+            let mut last_report = Instant::now();
+            while !self.stop.load(Ordering::Relaxed) && start.elapsed() < duration {
+                std::thread::sleep(Duration::from_millis(100));
+                if last_report.elapsed() >= Duration::from_secs(10) {
+                    println!(
+                        "{:>5}s: {} operations",
+                        start.elapsed().as_secs(),
+                        self.counters.total()
+                    );
+                    last_report = Instant::now();
+                }
+            }
+            self.stop.store(true, Ordering::Relaxed);
+            handles
+                .into_iter()
+                .map(|x| x.join().map_err(|_| anyhow!("thread panicked"))?)
+                .collect::<Vec<_>>()
+        });
+        self.counters.print();
+        for result in results {
+            result?;
+        }
+        self.verify_all(self.storage.read().as_ref().unwrap())
+    }
+
+    /// Check every key with a full scan while no operation runs.
+    fn verify_all(&self, storage: &LsmStorage) -> Result<()> {
+        let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded)?;
+        for (idx, state) in self.expected.keys.iter().enumerate() {
+            let Some(seed) = *state.lock() else {
+                continue;
+            };
+            if !iter.is_valid() || iter.key() != key_of(idx) {
+                bail!("full scan is missing key {}", idx);
+            }
+            self.verify(idx, Some(seed), Some(iter.value()))?;
+            iter.next()?;
+        }
+        if iter.is_valid() {
+            bail!(
+                "full scan returned deleted key {}",
+                String::from_utf8_lossy(iter.key())
+            );
+        }
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    if args.num_keys == 0 {
+        bail!("the keyspace cannot be empty");
+    }
+    if args.value_size < VALUE_PREFIX_LEN {
+        bail!("values must be at least {} bytes", VALUE_PREFIX_LEN);
+    }
+    let temp_dir;
+    let path = match &args.db {
+        Some(path) => path.clone(),
+        None if args.in_memory => PathBuf::from("/db"),
+        None => {
+            temp_dir = tempfile::tempdir()?;
+            temp_dir.path().to_path_buf()
+        }
+    };
+    let stress = Stress::new(args, &path)?;
+    stress.run()?;
+    println!("verification passed");
+    Ok(())
+}
@@ -0,0 +1,28 @@
+//! Decode a block from arbitrary bytes, and read all of it if it decodes.
+
+#![no_main]
+
+use std::sync::Arc;
+
+use libfuzzer_sys::fuzz_target;
+use mini_lsm::block::{Block, BlockIterator};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(block) = Block::try_decode(data) else {
+        return;
+    };
+    let block = Arc::new(block);
+    let mut iter = BlockIterator::create_and_seek_to_first(block.clone());
+    while iter.is_valid() {
+        iter.key();
+        iter.value();
+        iter.next();
+    }
+    // the keys of a corrupted block may be out of order, which must not break the binary search
+    for key in [&[][..], &data[..data.len().min(8)], &[0xff; 8][..]] {
+        let iter = BlockIterator::create_and_seek_to_key(block.clone(), key);
+        if iter.is_valid() {
+            iter.key();
+        }
+    }
+});
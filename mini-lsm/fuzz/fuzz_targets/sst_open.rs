@@ -0,0 +1,39 @@
+//! Open an SST from arbitrary bytes, and read all of it if it opens.
+
+#![no_main]
+
+use std::path::Path;
+use std::sync::Arc;
+
+use libfuzzer_sys::fuzz_target;
+use mini_lsm::env::{write_file, FileBackend, MemFileSystem};
+use mini_lsm::iterators::StorageIterator;
+use mini_lsm::table::{FileObject, SsTable, SsTableIterator};
+
+fuzz_target!(|data: &[u8]| {
+    let fs = MemFileSystem::new();
+    let path = Path::new("1.sst");
+    write_file(&fs, path, data).unwrap();
+    let file = FileObject::open_in(&fs, path, FileBackend::Pread).unwrap();
+    let Ok(sst) = SsTable::open(1, None, file) else {
+        return;
+    };
+    let sst = Arc::new(sst);
+    if let Ok(mut iter) = SsTableIterator::create_and_seek_to_first(sst.clone()) {
+        while iter.is_valid() {
+            iter.key();
+            iter.value();
+            if iter.next().is_err() {
+                break;
+            }
+        }
+    }
+    for key in [&[][..], sst.first_key(), sst.last_key(), &[0xff; 8][..]] {
+        sst.may_contain_key(key);
+        if let Ok(iter) = SsTableIterator::create_and_seek_to_key(sst.clone(), key) {
+            if iter.is_valid() {
+                iter.key();
+            }
+        }
+    }
+});
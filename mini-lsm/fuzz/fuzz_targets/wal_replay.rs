@@ -0,0 +1,67 @@
+//! Replay arbitrary bytes as a WAL file and as a manifest, both directly and by opening a storage
+//! on them, and read all of the storage if it opens.
+
+#![no_main]
+
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+
+use libfuzzer_sys::fuzz_target;
+use mini_lsm::env::{write_file, FileSystem, MemFileSystem};
+use mini_lsm::iterators::StorageIterator;
+use mini_lsm::lsm_storage::{LsmStorage, LsmStorageOptions};
+use mini_lsm::manifest::Manifest;
+use mini_lsm::wal::Wal;
+
+/// Split `data` into payloads, each prefixed by a length byte, and frame them as log records with
+/// valid checksums. Arbitrary bytes almost never pass the checksum of a record, so this is how the
+/// decoding of record payloads is reached.
+fn frame_records(mut data: &[u8]) -> Vec<u8> {
+    let mut log = Vec::new();
+    while let Some((&len, rest)) = data.split_first() {
+        let payload = &rest[..rest.len().min(len as usize)];
+        log.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        log.extend_from_slice(payload);
+        log.extend_from_slice(&crc32fast::hash(payload).to_be_bytes());
+        data = &rest[payload.len()..];
+    }
+    log
+}
+
+/// Open a storage whose file `name` holds `log`, and read and write it if it opens.
+fn open_with(name: &str, log: &[u8]) {
+    let fs = Arc::new(MemFileSystem::new());
+    let options = LsmStorageOptions {
+        file_system: fs.clone(),
+        ..Default::default()
+    };
+    // the first open creates the files of an empty storage, including an empty WAL file
+    drop(LsmStorage::open_with_options("/db", options.clone()).unwrap());
+    let path = Path::new("/db").join(name);
+    assert!(fs.exists(&path));
+    write_file(fs.as_ref(), &path, log).unwrap();
+    let Ok(storage) = LsmStorage::open_with_options("/db", options) else {
+        return;
+    };
+    if let Ok(mut iter) = storage.scan(Bound::Unbounded, Bound::Unbounded) {
+        while iter.is_valid() {
+            iter.key();
+            iter.value();
+            if iter.next().is_err() {
+                break;
+            }
+        }
+    }
+    let _ = storage.put(b"key", b"value").and_then(|()| storage.sync());
+}
+
+fuzz_target!(|data: &[u8]| {
+    let framed = frame_records(data);
+    for log in [data, &framed[..]] {
+        let _ = Wal::replay(log);
+        let _ = Manifest::replay(log);
+        open_with("00001.wal", log);
+        open_with("MANIFEST", log);
+    }
+});
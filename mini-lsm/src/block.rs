@@ -1,6 +1,9 @@
 mod builder;
 mod iterator;
 
+use std::ops::Range;
+
+use anyhow::{bail, Result};
 pub use builder::BlockBuilder;
 use bytes::{Buf, BufMut, Bytes};
 pub use iterator::BlockIterator;
@@ -10,13 +13,13 @@ pub const SIZEOF_U16: usize = std::mem::size_of::<u16>();
 /// A block is the smallest unit of read and caching in LSM tree. It is a collection of sorted
 /// key-value pairs.
 pub struct Block {
-    data: Vec<u8>,
+    data: Bytes,
     offsets: Vec<u16>,
 }
 
 impl Block {
     pub fn encode(&self) -> Bytes {
-        let mut buf = self.data.clone();
+        let mut buf = self.data.to_vec();
         let offsets_len = self.offsets.len();
         for offset in &self.offsets {
             buf.put_u16(*offset);
@@ -25,16 +28,94 @@ impl Block {
         buf.into()
     }
 
+    /// Get the number of entries in the block.
+    pub(crate) fn num_entries(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Get the key and the value of the idx-th entry.
+    pub(crate) fn entry(&self, idx: usize) -> (&[u8], &[u8]) {
+        let (key, value) = self.entry_ranges(idx);
+        (&self.data[key], &self.data[value])
+    }
+
+    /// Get the key of the idx-th entry without decoding its value.
+    pub(crate) fn key_at(&self, idx: usize) -> &[u8] {
+        let offset = self.offsets[idx] as usize;
+        let key_len = (&self.data[offset..]).get_u16() as usize;
+        &self.data[offset + SIZEOF_U16..offset + SIZEOF_U16 + key_len]
+    }
+
+    /// Get where the key and the value of the idx-th entry are in `data`.
+    pub(super) fn entry_ranges(&self, idx: usize) -> (Range<usize>, Range<usize>) {
+        let key_start = self.offsets[idx] as usize + SIZEOF_U16;
+        let key_len = (&self.data[key_start - SIZEOF_U16..]).get_u16() as usize;
+        let value_start = key_start + key_len + SIZEOF_U16;
+        let value_len = (&self.data[value_start - SIZEOF_U16..]).get_u16() as usize;
+        (
+            key_start..key_start + key_len,
+            value_start..value_start + value_len,
+        )
+    }
+
+    /// Decode a block.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the block is malformed. Use [`Block::try_decode`] for data that is not trusted.
     pub fn decode(data: &[u8]) -> Self {
+        Self::try_decode(data).expect("malformed block")
+    }
+
+    /// Decode a block. Fails if the block is malformed, so that every entry of a decoded block can
+    /// be read without checks.
+    pub fn try_decode(data: &[u8]) -> Result<Self> {
+        Self::decode_bytes(Bytes::copy_from_slice(data))
+    }
+
+    /// Decode a block that keeps sharing `data`, which may be part of a memory-mapped file.
+    pub fn decode_bytes(data: Bytes) -> Result<Self> {
+        if data.len() < SIZEOF_U16 {
+            bail!("block too short");
+        }
         let entry_offsets_len = (&data[data.len() - SIZEOF_U16..]).get_u16() as usize;
-        let data_end = data.len() - SIZEOF_U16 - entry_offsets_len * SIZEOF_U16;
+        let Some(data_end) = (data.len() - SIZEOF_U16).checked_sub(entry_offsets_len * SIZEOF_U16)
+        else {
+            bail!("block too short for {} entries", entry_offsets_len);
+        };
         let offsets_raw = &data[data_end..data.len() - SIZEOF_U16];
         let offsets = offsets_raw
             .chunks(SIZEOF_U16)
             .map(|mut x| x.get_u16())
             .collect();
-        let data = data[0..data_end].to_vec();
-        Self { data, offsets }
+        let block = Self {
+            data: data.slice(0..data_end),
+            offsets,
+        };
+        for idx in 0..block.num_entries() {
+            block.check_entry(idx)?;
+        }
+        Ok(block)
+    }
+
+    /// Check that the idx-th entry lies within `data`.
+    fn check_entry(&self, idx: usize) -> Result<()> {
+        let mut rest = match self.data.get(self.offsets[idx] as usize..) {
+            Some(rest) => rest,
+            None => bail!("entry {} starts past the end of the block", idx),
+        };
+        for _ in 0..2 {
+            // a key, then a value
+            if rest.len() < SIZEOF_U16 {
+                bail!("entry {} is truncated", idx);
+            }
+            let len = rest.get_u16() as usize;
+            if rest.len() < len {
+                bail!("entry {} is truncated", idx);
+            }
+            rest = &rest[len..];
+        }
+        Ok(())
     }
 }
 
@@ -54,7 +54,7 @@ impl BlockBuilder {
             panic!("block should not be empty");
         }
         Block {
-            data: self.data,
+            data: self.data.into(),
             offsets: self.offsets,
         }
     }
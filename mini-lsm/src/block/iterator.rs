@@ -1,51 +1,73 @@
+use std::ops::Range;
 use std::sync::Arc;
 
-use bytes::Buf;
-
 use super::Block;
+use crate::comparator::{BytewiseComparator, Comparator};
 
-/// Iterates on a block.
+/// Iterates on a block. Keys and values are borrowed from the block without copying.
 pub struct BlockIterator {
     block: Arc<Block>,
-    key: Vec<u8>,
-    value: Vec<u8>,
+    /// Where the current key is in the block data, or an empty range if the iterator is invalid.
+    key: Range<usize>,
+    /// Where the current value is in the block data.
+    value: Range<usize>,
     idx: usize,
+    comparator: &'static dyn Comparator,
 }
 
 impl BlockIterator {
-    fn new(block: Arc<Block>) -> Self {
+    fn new(block: Arc<Block>, comparator: &'static dyn Comparator) -> Self {
         Self {
             block,
-            key: Vec::new(),
-            value: Vec::new(),
+            key: 0..0,
+            value: 0..0,
             idx: 0,
+            comparator,
         }
     }
 
     /// Creates a block iterator and seek to the first entry.
     pub fn create_and_seek_to_first(block: Arc<Block>) -> Self {
-        let mut iter = Self::new(block);
+        Self::create_and_seek_to_first_with_comparator(block, &BytewiseComparator)
+    }
+
+    /// Creates a block iterator over keys ordered by `comparator` and seek to the first entry.
+    pub fn create_and_seek_to_first_with_comparator(
+        block: Arc<Block>,
+        comparator: &'static dyn Comparator,
+    ) -> Self {
+        let mut iter = Self::new(block, comparator);
         iter.seek_to_first();
         iter
     }
 
     /// Creates a block iterator and seek to the first key that >= `key`.
     pub fn create_and_seek_to_key(block: Arc<Block>, key: &[u8]) -> Self {
-        let mut iter = Self::new(block);
+        Self::create_and_seek_to_key_with_comparator(block, key, &BytewiseComparator)
+    }
+
+    /// Creates a block iterator over keys ordered by `comparator` and seek to the first key that
+    /// >= `key`.
+    pub fn create_and_seek_to_key_with_comparator(
+        block: Arc<Block>,
+        key: &[u8],
+        comparator: &'static dyn Comparator,
+    ) -> Self {
+        let mut iter = Self::new(block, comparator);
         iter.seek_to_key(key);
         iter
     }
 
     /// Returns the key of the current entry.
     pub fn key(&self) -> &[u8] {
-        debug_assert!(!self.key.is_empty(), "invalid iterator");
-        &self.key
+        debug_assert!(self.is_valid(), "invalid iterator");
+        &self.block.data[self.key.clone()]
     }
 
     /// Returns the value of the current entry.
     pub fn value(&self) -> &[u8] {
-        debug_assert!(!self.key.is_empty(), "invalid iterator");
-        &self.value
+        debug_assert!(self.is_valid(), "invalid iterator");
+        &self.block.data[self.value.clone()]
     }
 
     /// Returns true if the iterator is valid.
@@ -60,48 +82,34 @@ impl BlockIterator {
 
     /// Seeks to the idx-th key in the block.
     fn seek_to(&mut self, idx: usize) {
-        if idx >= self.block.offsets.len() {
-            self.key.clear();
-            self.value.clear();
+        self.idx = idx;
+        if idx >= self.block.num_entries() {
+            self.key = 0..0;
+            self.value = 0..0;
             return;
         }
-        let offset = self.block.offsets[idx] as usize;
-        self.seek_to_offset(offset);
-        self.idx = idx;
+        (self.key, self.value) = self.block.entry_ranges(idx);
     }
 
     /// Move to the next key in the block.
     pub fn next(&mut self) {
-        self.idx += 1;
-        self.seek_to(self.idx);
-    }
-
-    fn seek_to_offset(&mut self, offset: usize) {
-        let mut entry = &self.block.data[offset..];
-        let key_len = entry.get_u16() as usize;
-        let key = entry[..key_len].to_vec();
-        entry.advance(key_len);
-        self.key.clear();
-        self.key.extend(key);
-        let value_len = entry.get_u16() as usize;
-        let value = entry[..value_len].to_vec();
-        entry.advance(value_len);
-        self.value.clear();
-        self.value.extend(value);
+        self.seek_to(self.idx + 1);
     }
 
     /// Seek to the first key that >= `key`.
     pub fn seek_to_key(&mut self, key: &[u8]) {
+        // only keys are decoded while searching
         let mut low = 0;
-        let mut high = self.block.offsets.len();
+        let mut high = self.block.num_entries();
         while low < high {
             let mid = low + (high - low) / 2;
-            self.seek_to(mid);
-            assert!(self.is_valid());
-            match self.key().cmp(key) {
+            match self.comparator.compare(self.block.key_at(mid), key) {
                 std::cmp::Ordering::Less => low = mid + 1,
                 std::cmp::Ordering::Greater => high = mid,
-                std::cmp::Ordering::Equal => return,
+                std::cmp::Ordering::Equal => {
+                    low = mid;
+                    break;
+                }
             }
         }
         self.seek_to(low);
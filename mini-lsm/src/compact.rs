@@ -0,0 +1,620 @@
+mod fifo;
+mod leveled;
+mod tiered;
+
+use std::borrow::Cow;
+use std::ops::Bound;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use anyhow::Result;
+pub use fifo::{FifoCompactionOptions, FifoCompactionStrategy};
+pub use leveled::{LeveledCompactionOptions, LeveledCompactionStrategy};
+pub use tiered::{TieredCompactionOptions, TieredCompactionStrategy};
+
+use crate::compaction_filter::CompactionDecision;
+use crate::comparator::Comparator;
+use crate::iterators::merge_iterator::MergeIterator;
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{ColumnFamily, LsmStorage, LsmStorageInner, DEFAULT_CF};
+use crate::rate_limiter::IoPriority;
+use crate::table::{SsTable, SsTableBuilder, SsTableIterator};
+use crate::ttl::{decode_value, encode_value, is_deleted_or_expired, Clock};
+
+/// How the SSTs of a column family are compacted.
+#[derive(Clone, Debug)]
+pub enum CompactionOptions {
+    /// No compaction. All SSTs stay in L0.
+    NoCompaction,
+    /// Leveled compaction into levels of growing target sizes.
+    Leveled(LeveledCompactionOptions),
+    /// Tiered (universal) compaction of sorted runs.
+    Tiered(TieredCompactionOptions),
+    /// Delete the oldest L0 SSTs once a size or time limit is exceeded.
+    Fifo(FifoCompactionOptions),
+}
+
+impl CompactionOptions {
+    pub(crate) fn strategy(&self, clock: &Arc<dyn Clock>) -> Option<Box<dyn CompactionStrategy>> {
+        match self {
+            CompactionOptions::NoCompaction => None,
+            CompactionOptions::Leveled(options) => {
+                Some(Box::new(LeveledCompactionStrategy::new(options.clone())))
+            }
+            CompactionOptions::Tiered(options) => {
+                Some(Box::new(TieredCompactionStrategy::new(options.clone())))
+            }
+            CompactionOptions::Fifo(options) => Some(Box::new(FifoCompactionStrategy::new(
+                options.clone(),
+                clock.clone(),
+            ))),
+        }
+    }
+}
+
+/// A unit of work picked by a [`CompactionStrategy`].
+pub enum CompactionTask {
+    /// Merge adjacent sorted runs of `levels`, newest first, into one sorted run.
+    Tiered {
+        runs: Vec<Vec<Arc<SsTable>>>,
+        /// Whether the oldest run of the column family is included. Tombstones and expired values
+        /// can only be dropped in this case.
+        bottommost: bool,
+    },
+    /// Merge `upper`, the L0 SSTs newest first or an SST of `levels[upper_level]`, with the SSTs
+    /// of `levels[lower_level]` they overlap, into `levels[lower_level]`.
+    Leveled {
+        upper_level: Option<usize>,
+        upper: Vec<Arc<SsTable>>,
+        lower_level: usize,
+        lower: Vec<Arc<SsTable>>,
+        bottommost: bool,
+    },
+    /// Merge adjacent L0 SSTs, newest first, into new SSTs at the same place in L0.
+    IntraL0 {
+        tables: Vec<Arc<SsTable>>,
+        bottommost: bool,
+    },
+    /// Delete L0 SSTs without rewriting anything. Like every compaction, the new state is recorded
+    /// in the manifest before the files are deleted, so the SSTs stay deleted after a crash.
+    Delete { tables: Vec<Arc<SsTable>> },
+}
+
+impl CompactionTask {
+    /// The number of bytes the task rewrites.
+    pub(crate) fn input_size(&self) -> u64 {
+        match self {
+            CompactionTask::Tiered { runs, .. } => {
+                runs.iter().flatten().map(|x| x.table_size()).sum()
+            }
+            CompactionTask::Leveled { upper, lower, .. } => {
+                upper.iter().chain(lower).map(|x| x.table_size()).sum()
+            }
+            CompactionTask::IntraL0 { tables, .. } => tables.iter().map(|x| x.table_size()).sum(),
+            CompactionTask::Delete { .. } => 0,
+        }
+    }
+}
+
+/// Decides what the background compaction worker should do next for a column family.
+pub trait CompactionStrategy: Send + Sync {
+    /// Whether flushed memtables go to L0, or become a new sorted run at the front of `levels`.
+    fn flush_to_l0(&self) -> bool;
+
+    /// The number of sorted runs in `levels` if the strategy keeps a fixed number of them, some of
+    /// which may be empty. Otherwise, empty runs are removed.
+    fn num_fixed_levels(&self) -> Option<usize> {
+        None
+    }
+
+    /// Pick a compaction task for the current state, if one is needed.
+    fn pick_compaction(&self, snapshot: &LsmStorageInner) -> Option<CompactionTask>;
+}
+
+/// How often the background worker checks whether compaction is needed.
+const COMPACTION_INTERVAL: Duration = Duration::from_millis(50);
+
+impl LsmStorage {
+    /// Start the background compaction worker. It stops once the storage is dropped, which closes
+    /// the other end of `stop_rx`.
+    pub(crate) fn spawn_compaction_thread(this: Weak<Self>, stop_rx: Receiver<()>) {
+        std::thread::spawn(move || loop {
+            match stop_rx.recv_timeout(COMPACTION_INTERVAL) {
+                Err(RecvTimeoutError::Timeout) => {}
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => return,
+            }
+            let Some(storage) = this.upgrade() else {
+                return;
+            };
+            // Errors are recorded in the column families, and reported by `stats`.
+            let _ = storage.trigger_compaction();
+        });
+    }
+
+    /// Run at most one compaction task on every column family that needs it. A failure is
+    /// recorded as the background error of its column family, and does not stop the others.
+    /// Returns the first failure.
+    pub(crate) fn trigger_compaction(&self) -> Result<()> {
+        let mut result = Ok(());
+        for cf in self.all_column_families() {
+            if let Err(e) = self.compact_column_family(&cf) {
+                *cf.background_error.lock() = Some(format!("{:#}", e));
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+
+    /// Pick and run a compaction task on a column family. Returns whether a task was run.
+    pub(crate) fn compact_column_family(&self, cf: &ColumnFamily) -> Result<bool> {
+        let Some(strategy) = cf.strategy.as_ref() else {
+            return Ok(false);
+        };
+        // Only one compaction job runs on a column family at a time, so no two jobs can pick the
+        // same SSTs.
+        let _compaction_lock = cf.compaction_lock.lock();
+        if cf.is_dropped() {
+            return Ok(false);
+        }
+        let Some(task) = strategy.pick_compaction(&cf.snapshot()) else {
+            return Ok(false);
+        };
+        let removed = match task {
+            CompactionTask::Tiered { runs, bottommost } => {
+                let tables: Vec<_> = runs.iter().flatten().cloned().collect();
+                // The output takes the place of the first run.
+                let level = position_of(&cf.snapshot().levels, |run| &run[0], &runs[0][0]) + 1;
+                let output = self.move_or_compact_tables(cf, &tables, bottommost, level as u32)?;
+                // Moved SSTs are part of the output, and their files are kept.
+                let removed = tables
+                    .iter()
+                    .filter(|x| !output.iter().any(|y| x.sst_id() == y.sst_id()))
+                    .cloned()
+                    .collect();
+                self.update_state(cf, |snapshot| {
+                    // Flushes may have added newer runs in front since the task was picked.
+                    let start = position_of(&snapshot.levels, |run| &run[0], &runs[0][0]);
+                    // All entries may have been dropped by a bottommost compaction.
+                    let output = if output.is_empty() {
+                        None
+                    } else {
+                        Some(output)
+                    };
+                    snapshot.levels.splice(start..start + runs.len(), output);
+                })?;
+                removed
+            }
+            CompactionTask::Leveled {
+                upper_level,
+                upper,
+                lower_level,
+                lower,
+                bottommost,
+            } => {
+                let tables: Vec<_> = upper.iter().chain(&lower).cloned().collect();
+                let output =
+                    self.move_or_compact_tables(cf, &tables, bottommost, lower_level as u32 + 1)?;
+                let is_input =
+                    |table: &Arc<SsTable>| tables.iter().any(|x| x.sst_id() == table.sst_id());
+                self.update_state(cf, |snapshot| {
+                    // Flushes only add SSTs to the newest end of L0 meanwhile.
+                    match upper_level {
+                        Some(level) => snapshot.levels[level].retain(|x| !is_input(x)),
+                        None => snapshot.l0_sstables.retain(|x| !is_input(x)),
+                    }
+                    let run = &mut snapshot.levels[lower_level];
+                    run.retain(|x| !is_input(x));
+                    run.extend(output.iter().cloned());
+                    run.sort_by(|a, b| cf.comparator.compare(a.first_key(), b.first_key()));
+                })?;
+                // Moved SSTs are part of the output, and their files are kept.
+                tables
+                    .iter()
+                    .filter(|x| !output.iter().any(|y| x.sst_id() == y.sst_id()))
+                    .cloned()
+                    .collect()
+            }
+            CompactionTask::IntraL0 { tables, bottommost } => {
+                let output = self.compact_tables(cf, &tables, bottommost, 0)?;
+                self.update_state(cf, |snapshot| {
+                    let oldest = tables.last().unwrap();
+                    let start = position_of(&snapshot.l0_sstables, |x| x, oldest);
+                    snapshot
+                        .l0_sstables
+                        .splice(start..start + tables.len(), output);
+                })?;
+                tables
+            }
+            CompactionTask::Delete { tables } => {
+                self.update_state(cf, |snapshot| {
+                    snapshot
+                        .l0_sstables
+                        .retain(|x| !tables.iter().any(|y| x.sst_id() == y.sst_id()));
+                })?;
+                tables
+            }
+        };
+
+        for table in removed {
+            self.options
+                .file_system
+                .delete(&self.path_of_sst(table.sst_id()))?;
+        }
+        Ok(true)
+    }
+
+    /// Compact all data in a key range down to the bottommost sorted run, so that deleted and
+    /// expired entries in the range no longer take up space. Blocks until the compaction is done.
+    pub fn compact_range(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> Result<()> {
+        self.compact_range_cf(DEFAULT_CF, lower, upper)
+    }
+
+    /// Compact all data in a key range of a column family down to the bottommost sorted run.
+    ///
+    /// The memtable is flushed first. Then the SSTs overlapping the range, from the newest one
+    /// down to the bottommost sorted run, are merged into the bottommost sorted run, which is
+    /// always a bottommost compaction. SSTs that do not overlap the range keep their place, unless
+    /// they overlap the key range of an input: the output must not overlap the SSTs it is placed
+    /// next to, so those are merged too. A single SST spanning far beyond the range can therefore
+    /// pull in many more SSTs. Background compaction of the column family waits until this is
+    /// done.
+    pub fn compact_range_cf(
+        &self,
+        cf: &str,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<()> {
+        let cf = self.column_family(cf)?;
+        self.flush_column_family(&cf)?;
+        let _compaction_lock = cf.compaction_lock.lock();
+        if cf.is_dropped() {
+            return Ok(());
+        }
+        let snapshot = cf.snapshot();
+        let overlaps = |table: &Arc<SsTable>| overlaps_range(cf.comparator, table, lower, upper);
+
+        // Only SSTs as old as the newest one overlapping the range are picked, so that everything
+        // newer than the output stays newer.
+        let (num_l0, num_runs) = match snapshot.l0_sstables.iter().rposition(overlaps) {
+            Some(idx) => (idx + 1, snapshot.levels.len()),
+            None => match snapshot
+                .levels
+                .iter()
+                .position(|run| run.iter().any(overlaps))
+            {
+                Some(idx) => (0, snapshot.levels.len() - idx),
+                None => return Ok(()),
+            },
+        };
+        let runs = &snapshot.levels[snapshot.levels.len() - num_runs..];
+        let candidates: Vec<_> = snapshot.l0_sstables[..num_l0]
+            .iter()
+            .rev()
+            .chain(runs.iter().flatten())
+            .collect();
+        let tables = expand_inputs(cf.comparator, &candidates, lower, upper);
+        let level = snapshot.levels.len();
+        let output = self.compact_tables(&cf, &tables, true, level as u32)?;
+
+        self.update_state(&cf, |snapshot| {
+            let is_input =
+                |table: &Arc<SsTable>| tables.iter().any(|x| x.sst_id() == table.sst_id());
+            snapshot.l0_sstables.retain(|x| !is_input(x));
+            // Flushes only add SSTs to the newest end of L0 and runs to the front of `levels`, and
+            // nothing older overlaps the output.
+            if runs.is_empty() {
+                snapshot.l0_sstables.splice(..0, output);
+            } else {
+                for run in &mut snapshot.levels {
+                    run.retain(|x| !is_input(x));
+                }
+                let bottommost = snapshot.levels.last_mut().unwrap();
+                bottommost.extend(output);
+                bottommost.sort_by(|a, b| cf.comparator.compare(a.first_key(), b.first_key()));
+                if cf
+                    .strategy
+                    .as_ref()
+                    .map_or(true, |x| x.num_fixed_levels().is_none())
+                {
+                    snapshot.levels.retain(|run| !run.is_empty());
+                }
+            }
+        })?;
+        for table in tables {
+            self.options
+                .file_system
+                .delete(&self.path_of_sst(table.sst_id()))?;
+        }
+        Ok(())
+    }
+
+    /// Like [`Self::compact_tables`], but SSTs whose key range overlaps no other input are moved
+    /// to the output without being rewritten. In a bottommost compaction, an SST is still
+    /// rewritten if it has tombstones or expired values, or if the column family has a compaction
+    /// filter.
+    fn move_or_compact_tables(
+        &self,
+        cf: &ColumnFamily,
+        tables: &[Arc<SsTable>],
+        bottommost: bool,
+        level: u32,
+    ) -> Result<Vec<Arc<SsTable>>> {
+        let now = self.options.clock.now();
+        let can_move = |table: &SsTable| {
+            let properties = table.properties();
+            !bottommost
+                || (properties.num_deletions == 0
+                    && properties.earliest_expire_at > now
+                    && cf.options.compaction_filter.is_none())
+        };
+        let mut output = Vec::new();
+        for group in overlapping_groups(cf.comparator, tables) {
+            match &group[..] {
+                [table] if can_move(table) => {
+                    cf.compaction_bytes_moved
+                        .fetch_add(table.table_size(), Ordering::Relaxed);
+                    output.push(table.clone());
+                }
+                _ => output.extend(self.compact_tables(cf, &group, bottommost, level)?),
+            }
+        }
+        Ok(output)
+    }
+
+    /// Merge `tables`, ordered from newest to oldest, into a sorted run of new SSTs.
+    ///
+    /// The key range is split into up to `max_subcompactions` subranges, which are merged in
+    /// parallel.
+    fn compact_tables(
+        &self,
+        cf: &ColumnFamily,
+        tables: &[Arc<SsTable>],
+        bottommost: bool,
+        level: u32,
+    ) -> Result<Vec<Arc<SsTable>>> {
+        cf.compaction_bytes_rewritten.fetch_add(
+            tables.iter().map(|x| x.table_size()).sum(),
+            Ordering::Relaxed,
+        );
+        let boundaries = subcompaction_boundaries(cf, tables);
+        if boundaries.is_empty() {
+            return self.compact_subrange(cf, tables, None, None, bottommost, level);
+        }
+        let lowers = std::iter::once(None).chain(boundaries.iter().copied().map(Some));
+        let uppers = boundaries
+            .iter()
+            .copied()
+            .map(Some)
+            .chain(std::iter::once(None));
+        let outputs = std::thread::scope(|scope| {
+            let handles: Vec<_> = lowers
+                .zip(uppers)
+                .map(|(lower, upper)| {
+                    scope.spawn(move || {
+                        self.compact_subrange(cf, tables, lower, upper, bottommost, level)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Result<Vec<_>>>()
+        })?;
+        Ok(outputs.into_iter().flatten().collect())
+    }
+
+    /// Merge the keys of `tables` from `lower` (inclusive) to `upper` (exclusive).
+    fn compact_subrange(
+        &self,
+        cf: &ColumnFamily,
+        tables: &[Arc<SsTable>],
+        lower: Option<&[u8]>,
+        upper: Option<&[u8]>,
+        bottommost: bool,
+        level: u32,
+    ) -> Result<Vec<Arc<SsTable>>> {
+        let lower_bound = lower.map_or(Bound::Unbounded, Bound::Included);
+        let upper_bound = upper.map_or(Bound::Unbounded, Bound::Excluded);
+        let mut iters = Vec::with_capacity(tables.len());
+        for table in tables {
+            if !overlaps_range(cf.comparator, table, lower_bound, upper_bound) {
+                continue;
+            }
+            let iter = match lower {
+                Some(key) => SsTableIterator::create_and_seek_to_key(table.clone(), key)?,
+                None => SsTableIterator::create_and_seek_to_first(table.clone())?,
+            };
+            iters.push(Box::new(iter));
+        }
+        let mut iter = MergeIterator::create_with_comparator(iters, cf.comparator);
+        let now = self.options.clock.now();
+        let creation_time = tables.iter().map(|x| x.creation_time()).max().unwrap_or(0);
+
+        let mut output = Vec::new();
+        let mut builder = None;
+        while iter.is_valid() {
+            if let Some(upper) = upper {
+                if cf.comparator.compare(iter.key(), upper).is_ge() {
+                    break;
+                }
+            }
+            let mut value = Cow::Borrowed(iter.value());
+            if is_deleted_or_expired(&value, now)? {
+                value = Cow::Borrowed(b"");
+            } else if let Some(filter) = &cf.options.compaction_filter {
+                let (user_value, expire_at) = decode_value(&value)?;
+                match filter.filter(level, iter.key(), user_value, bottommost) {
+                    CompactionDecision::Keep => {}
+                    CompactionDecision::Remove => value = Cow::Borrowed(b""),
+                    // An empty value would be read back as a tombstone, so it removes the key.
+                    CompactionDecision::ChangeValue(new_value) if new_value.is_empty() => {
+                        value = Cow::Borrowed(b"")
+                    }
+                    CompactionDecision::ChangeValue(new_value) => {
+                        value = Cow::Owned(encode_value(&new_value, expire_at));
+                    }
+                }
+            }
+            if value.is_empty() && bottommost {
+                // Nothing older can be hidden by this entry anymore.
+                iter.next()?;
+                continue;
+            }
+            // Otherwise a tombstone is kept to hide older versions, and an expired or removed
+            // value is dropped.
+            let builder_inner = builder.get_or_insert_with(|| {
+                self.new_sst_builder(cf, creation_time, level, IoPriority::Low)
+            });
+            builder_inner.add(iter.key(), &value);
+            if builder_inner.estimated_size() >= cf.options.target_sst_size {
+                output.push(self.build_sst(builder.take().unwrap())?);
+            }
+            iter.next()?;
+        }
+        if let Some(builder) = builder {
+            output.push(self.build_sst(builder)?);
+        }
+        Ok(output)
+    }
+
+    fn build_sst(&self, builder: SsTableBuilder) -> Result<Arc<SsTable>> {
+        let sst_id = self.next_sst_id();
+        Ok(Arc::new(builder.build(
+            sst_id,
+            Some(self.block_cache.clone()),
+            self.path_of_sst(sst_id),
+        )?))
+    }
+}
+
+/// Group `tables` whose key ranges overlap, directly or through other tables. The groups are
+/// ordered by key range, and each group keeps the order of `tables`.
+fn overlapping_groups(
+    comparator: &dyn Comparator,
+    tables: &[Arc<SsTable>],
+) -> Vec<Vec<Arc<SsTable>>> {
+    let mut order: Vec<_> = (0..tables.len()).collect();
+    order.sort_by(|&a, &b| comparator.compare(tables[a].first_key(), tables[b].first_key()));
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_last_key: &[u8] = &[];
+    for idx in order {
+        let table = &tables[idx];
+        match groups.last_mut() {
+            Some(group)
+                if comparator
+                    .compare(table.first_key(), group_last_key)
+                    .is_le() =>
+            {
+                group.push(idx);
+            }
+            _ => {
+                groups.push(vec![idx]);
+                group_last_key = table.last_key();
+                continue;
+            }
+        }
+        if comparator.compare(table.last_key(), group_last_key).is_gt() {
+            group_last_key = table.last_key();
+        }
+    }
+    groups
+        .into_iter()
+        .map(|mut group| {
+            group.sort_unstable();
+            group.into_iter().map(|idx| tables[idx].clone()).collect()
+        })
+        .collect()
+}
+
+/// Split the key range of a compaction job at the first keys of its SSTs, into at most
+/// `max_subcompactions` subranges. Returns where each subrange but the first one starts.
+fn subcompaction_boundaries<'a>(cf: &ColumnFamily, tables: &'a [Arc<SsTable>]) -> Vec<&'a [u8]> {
+    let mut keys: Vec<_> = tables.iter().map(|x| x.first_key()).collect();
+    keys.sort_by(|a, b| cf.comparator.compare(a, b));
+    keys.dedup_by(|a, b| cf.comparator.compare(a, b).is_eq());
+    let num_subranges = cf.options.max_subcompactions.clamp(1, keys.len().max(1));
+    (1..num_subranges)
+        .map(|i| keys[i * keys.len() / num_subranges])
+        .collect()
+}
+
+/// Whether the keys of `table` may fall into the range between `lower` and `upper`.
+fn overlaps_range(
+    comparator: &dyn Comparator,
+    table: &SsTable,
+    lower: Bound<&[u8]>,
+    upper: Bound<&[u8]>,
+) -> bool {
+    let after_lower = match lower {
+        Bound::Included(key) => comparator.compare(table.last_key(), key).is_ge(),
+        Bound::Excluded(key) => comparator.compare(table.last_key(), key).is_gt(),
+        Bound::Unbounded => true,
+    };
+    let before_upper = match upper {
+        Bound::Included(key) => comparator.compare(table.first_key(), key).is_le(),
+        Bound::Excluded(key) => comparator.compare(table.first_key(), key).is_lt(),
+        Bound::Unbounded => true,
+    };
+    after_lower && before_upper
+}
+
+/// Pick the `candidates`, ordered from newest to oldest, that overlap the range between `lower`
+/// and `upper`. Then add the candidates overlapping the key range of the picked ones until no more
+/// are added, so that no candidate left out overlaps the output. Keeps the order of `candidates`.
+fn expand_inputs(
+    comparator: &dyn Comparator,
+    candidates: &[&Arc<SsTable>],
+    lower: Bound<&[u8]>,
+    upper: Bound<&[u8]>,
+) -> Vec<Arc<SsTable>> {
+    let mut picked: Vec<_> = candidates
+        .iter()
+        .map(|x| overlaps_range(comparator, x, lower, upper))
+        .collect();
+    loop {
+        let picked_tables = || candidates.iter().zip(&picked).filter(|(_, &x)| x);
+        let first_key = picked_tables()
+            .map(|(x, _)| x.first_key())
+            .min_by(|a, b| comparator.compare(a, b))
+            .unwrap();
+        let last_key = picked_tables()
+            .map(|(x, _)| x.last_key())
+            .max_by(|a, b| comparator.compare(a, b))
+            .unwrap();
+        let mut added = false;
+        for (table, picked) in candidates.iter().zip(&mut picked) {
+            if !*picked
+                && overlaps_range(
+                    comparator,
+                    table,
+                    Bound::Included(first_key),
+                    Bound::Included(last_key),
+                )
+            {
+                *picked = true;
+                added = true;
+            }
+        }
+        if !added {
+            break;
+        }
+    }
+    candidates
+        .iter()
+        .zip(picked)
+        .filter(|(_, x)| *x)
+        .map(|(x, _)| Arc::clone(x))
+        .collect()
+}
+
+/// Find the SST, or the sorted run starting with the SST, that a compaction task picked.
+fn position_of<T>(items: &[T], table_of: impl Fn(&T) -> &Arc<SsTable>, table: &SsTable) -> usize {
+    items
+        .iter()
+        .position(|x| table_of(x).sst_id() == table.sst_id())
+        .expect("compacted SST disappeared")
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{CompactionStrategy, CompactionTask};
+use crate::lsm_storage::LsmStorageInner;
+use crate::ttl::Clock;
+
+#[derive(Clone, Debug)]
+pub struct FifoCompactionOptions {
+    /// Delete the oldest SSTs once all SSTs together are larger than this.
+    pub max_table_files_size: u64,
+    /// Delete SSTs whose newest data is older than this.
+    pub ttl: Option<Duration>,
+    /// Once L0 has at least this many SSTs, merge the newest ones that are smaller than
+    /// `intra_l0_max_file_size`. Zero disables intra-L0 merges.
+    pub intra_l0_file_num_trigger: usize,
+    pub intra_l0_max_file_size: u64,
+}
+
+impl Default for FifoCompactionOptions {
+    fn default() -> Self {
+        Self {
+            max_table_files_size: 1 << 30,
+            ttl: None,
+            intra_l0_file_num_trigger: 0,
+            intra_l0_max_file_size: 1 << 20,
+        }
+    }
+}
+
+/// FIFO compaction for data that is never updated. Deletes whole SSTs from the oldest end of L0
+/// once a size or time limit is exceeded.
+pub struct FifoCompactionStrategy {
+    options: FifoCompactionOptions,
+    clock: Arc<dyn Clock>,
+}
+
+impl FifoCompactionStrategy {
+    pub fn new(options: FifoCompactionOptions, clock: Arc<dyn Clock>) -> Self {
+        Self { options, clock }
+    }
+}
+
+impl CompactionStrategy for FifoCompactionStrategy {
+    fn flush_to_l0(&self) -> bool {
+        true
+    }
+
+    fn pick_compaction(&self, snapshot: &LsmStorageInner) -> Option<CompactionTask> {
+        let l0 = &snapshot.l0_sstables;
+
+        let mut num_expired = 0;
+        if let Some(ttl) = self.options.ttl {
+            let now = self.clock.now();
+            let ttl = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+            num_expired = l0
+                .iter()
+                // an expiry past the end of the clock never comes
+                .take_while(|x| {
+                    x.creation_time()
+                        .checked_add(ttl)
+                        .map_or(false, |expire_at| expire_at <= now)
+                })
+                .count();
+        }
+        let mut total_size: u64 = l0[num_expired..].iter().map(|x| x.table_size()).sum();
+        let mut num_deleted = num_expired;
+        while total_size > self.options.max_table_files_size {
+            total_size -= l0[num_deleted].table_size();
+            num_deleted += 1;
+        }
+        if num_deleted > 0 {
+            return Some(CompactionTask::Delete {
+                tables: l0[..num_deleted].to_vec(),
+            });
+        }
+
+        if self.options.intra_l0_file_num_trigger == 0
+            || l0.len() < self.options.intra_l0_file_num_trigger
+        {
+            return None;
+        }
+        let num_small = l0
+            .iter()
+            .rev()
+            .take_while(|x| x.table_size() < self.options.intra_l0_max_file_size)
+            .count();
+        if num_small < 2 {
+            return None;
+        }
+        Some(CompactionTask::IntraL0 {
+            tables: l0[l0.len() - num_small..].iter().rev().cloned().collect(),
+            bottommost: num_small == l0.len() && snapshot.levels.is_empty(),
+        })
+    }
+}
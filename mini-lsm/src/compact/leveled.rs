@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use super::{CompactionStrategy, CompactionTask};
+use crate::lsm_storage::LsmStorageInner;
+use crate::table::SsTable;
+
+#[derive(Clone, Debug)]
+pub struct LeveledCompactionOptions {
+    /// Compact all L0 SSTs into L1 once L0 has at least this many SSTs.
+    pub level0_file_num_compaction_trigger: usize,
+    /// The number of levels below L0.
+    pub max_levels: usize,
+    /// The target size of L1, in bytes.
+    pub base_level_size: u64,
+    /// Every level below L1 targets this many times the size of the level above it.
+    pub level_size_multiplier: u64,
+}
+
+impl Default for LeveledCompactionOptions {
+    fn default() -> Self {
+        Self {
+            level0_file_num_compaction_trigger: 4,
+            max_levels: 4,
+            base_level_size: 64 << 20,
+            level_size_multiplier: 10,
+        }
+    }
+}
+
+/// Leveled compaction. `levels` always holds `max_levels` sorted runs, some of which may be empty.
+/// L0 SSTs are merged into L1, and once a level grows past its target size, one of its SSTs is
+/// merged into the level below it.
+pub struct LeveledCompactionStrategy {
+    options: LeveledCompactionOptions,
+}
+
+impl LeveledCompactionStrategy {
+    pub fn new(options: LeveledCompactionOptions) -> Self {
+        Self { options }
+    }
+
+    /// The SSTs of `run` overlapping the key range of `tables`.
+    fn overlapping_tables(tables: &[Arc<SsTable>], run: &[Arc<SsTable>]) -> Vec<Arc<SsTable>> {
+        let Some(first) = tables.first() else {
+            return Vec::new();
+        };
+        let comparator = first.comparator();
+        let first_key = tables
+            .iter()
+            .map(|x| x.first_key())
+            .min_by(|a, b| comparator.compare(a, b))
+            .unwrap();
+        let last_key = tables
+            .iter()
+            .map(|x| x.last_key())
+            .max_by(|a, b| comparator.compare(a, b))
+            .unwrap();
+        run.iter()
+            .filter(|x| {
+                comparator.compare(x.first_key(), last_key).is_le()
+                    && comparator.compare(x.last_key(), first_key).is_ge()
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn merge_into(
+        snapshot: &LsmStorageInner,
+        upper_level: Option<usize>,
+        upper: Vec<Arc<SsTable>>,
+        lower_level: usize,
+    ) -> CompactionTask {
+        let lower = Self::overlapping_tables(&upper, &snapshot.levels[lower_level]);
+        CompactionTask::Leveled {
+            upper_level,
+            upper,
+            lower_level,
+            lower,
+            bottommost: snapshot.levels[lower_level + 1..]
+                .iter()
+                .all(|run| run.is_empty()),
+        }
+    }
+}
+
+impl CompactionStrategy for LeveledCompactionStrategy {
+    fn flush_to_l0(&self) -> bool {
+        true
+    }
+
+    fn num_fixed_levels(&self) -> Option<usize> {
+        Some(self.options.max_levels.max(1))
+    }
+
+    fn pick_compaction(&self, snapshot: &LsmStorageInner) -> Option<CompactionTask> {
+        let trigger = self.options.level0_file_num_compaction_trigger.max(1);
+        if snapshot.l0_sstables.len() >= trigger {
+            // L0 SSTs overlap each other, so all of them are merged at once.
+            let upper = snapshot.l0_sstables.iter().rev().cloned().collect();
+            return Some(Self::merge_into(snapshot, None, upper, 0));
+        }
+
+        // Merge the level furthest past its target size into the level below it. The last level
+        // has nowhere to go.
+        let levels = &snapshot.levels;
+        let mut target_size = self.options.base_level_size.max(1);
+        let mut picked = None;
+        let mut max_score = 1.0;
+        for (idx, run) in levels
+            .iter()
+            .enumerate()
+            .take(levels.len().saturating_sub(1))
+        {
+            let size: u64 = run.iter().map(|x| x.table_size()).sum();
+            let score = size as f64 / target_size as f64;
+            if score > max_score {
+                max_score = score;
+                picked = Some(idx);
+            }
+            target_size = target_size.saturating_mul(self.options.level_size_multiplier.max(1));
+        }
+        let level = picked?;
+        // The oldest SST has gone the longest without being compacted.
+        let table = levels[level].iter().min_by_key(|x| x.sst_id()).unwrap();
+        Some(Self::merge_into(
+            snapshot,
+            Some(level),
+            vec![table.clone()],
+            level + 1,
+        ))
+    }
+}
@@ -0,0 +1,111 @@
+use super::{CompactionStrategy, CompactionTask};
+use crate::lsm_storage::LsmStorageInner;
+
+#[derive(Clone, Debug)]
+pub struct TieredCompactionOptions {
+    /// Compaction starts once there are at least this many sorted runs.
+    pub num_sorted_runs_trigger: usize,
+    /// Merge all sorted runs once the size of all runs but the oldest exceeds this percentage of
+    /// the size of the oldest run.
+    pub max_size_amplification_percent: usize,
+    /// A run joins a size-ratio merge if it is at most this percent larger than the runs before
+    /// it combined.
+    pub size_ratio: usize,
+    /// The minimum number of runs merged by a size-ratio compaction.
+    pub min_merge_width: usize,
+    /// Merge a run with all older runs once at least this percentage of its entries are
+    /// tombstones, regardless of the number of runs. Zero disables this.
+    pub deletion_percent_trigger: u64,
+}
+
+impl Default for TieredCompactionOptions {
+    fn default() -> Self {
+        Self {
+            num_sorted_runs_trigger: 4,
+            max_size_amplification_percent: 200,
+            size_ratio: 1,
+            min_merge_width: 2,
+            deletion_percent_trigger: 50,
+        }
+    }
+}
+
+/// Tiered (universal) compaction. Every flush creates a new sorted run at the front of `levels`,
+/// and compaction merges adjacent runs, starting from the newest.
+pub struct TieredCompactionStrategy {
+    options: TieredCompactionOptions,
+}
+
+impl TieredCompactionStrategy {
+    pub fn new(options: TieredCompactionOptions) -> Self {
+        Self { options }
+    }
+
+    fn merge_newest(snapshot: &LsmStorageInner, num_runs: usize) -> CompactionTask {
+        CompactionTask::Tiered {
+            runs: snapshot.levels[..num_runs].to_vec(),
+            bottommost: num_runs == snapshot.levels.len(),
+        }
+    }
+
+    /// Find the newest run that is dense with tombstones.
+    fn find_deletion_dense_run(&self, snapshot: &LsmStorageInner) -> Option<usize> {
+        if self.options.deletion_percent_trigger == 0 {
+            return None;
+        }
+        snapshot.levels.iter().position(|run| {
+            let num_entries: u64 = run.iter().map(|x| x.properties().num_entries).sum();
+            let num_deletions: u64 = run.iter().map(|x| x.properties().num_deletions).sum();
+            num_deletions > 0
+                && num_deletions * 100 >= num_entries * self.options.deletion_percent_trigger
+        })
+    }
+}
+
+impl CompactionStrategy for TieredCompactionStrategy {
+    fn flush_to_l0(&self) -> bool {
+        false
+    }
+
+    fn pick_compaction(&self, snapshot: &LsmStorageInner) -> Option<CompactionTask> {
+        let runs = &snapshot.levels;
+        // Tombstones are only dropped by a bottommost compaction, so merge the dense run with
+        // everything older.
+        if let Some(idx) = self.find_deletion_dense_run(snapshot) {
+            return Some(CompactionTask::Tiered {
+                runs: runs[idx..].to_vec(),
+                bottommost: true,
+            });
+        }
+        let trigger = self.options.num_sorted_runs_trigger.max(2);
+        if runs.len() < trigger {
+            return None;
+        }
+        let run_size = |idx: usize| -> u64 { runs[idx].iter().map(|x| x.table_size()).sum() };
+
+        // Reduce space amplification by merging everything.
+        let last_size = run_size(runs.len() - 1);
+        let other_size: u64 = (0..runs.len() - 1).map(run_size).sum();
+        if other_size * 100 >= last_size * self.options.max_size_amplification_percent as u64 {
+            return Some(Self::merge_newest(snapshot, runs.len()));
+        }
+
+        // Merge the newest runs as long as the next run is not much larger than them combined.
+        let mut merged_size = run_size(0);
+        let mut width = 1;
+        while width < runs.len() {
+            let next_size = run_size(width);
+            if next_size * 100 > merged_size * (100 + self.options.size_ratio as u64) {
+                break;
+            }
+            merged_size += next_size;
+            width += 1;
+        }
+        if width >= self.options.min_merge_width.max(2) {
+            return Some(Self::merge_newest(snapshot, width));
+        }
+
+        // Otherwise, bring the number of runs back below the trigger.
+        Some(Self::merge_newest(snapshot, runs.len() - trigger + 2))
+    }
+}
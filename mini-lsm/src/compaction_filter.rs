@@ -0,0 +1,29 @@
+/// What a [`CompactionFilter`] does with a key-value pair.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CompactionDecision {
+    /// Keep the pair as it is.
+    Keep,
+    /// Remove the key. Outside a bottommost compaction, a tombstone is written in its place so that
+    /// older versions of the key stay hidden.
+    Remove,
+    /// Replace the value, keeping the expiry of the key if it has one. An empty value removes the
+    /// key as [`CompactionDecision::Remove`] does.
+    ChangeValue(Vec<u8>),
+}
+
+/// Application-specific garbage collection run by compaction, e.g. to drop the keys of deleted
+/// tenants or to rewrite values to a new schema.
+///
+/// The filter is called on the newest version of every key rewritten by compaction, except for
+/// tombstones and expired values. SSTs that compaction moves without rewriting them are not
+/// filtered, but bottommost compactions rewrite every SST they include. The storage has no snapshots, so every key a filter sees is only visible to reads
+/// through its newest version.
+pub trait CompactionFilter: Send + Sync {
+    /// The name of the filter.
+    fn name(&self) -> &str;
+
+    /// Decide what to do with a key-value pair. `level` is the sorted run the output is written to,
+    /// or zero for L0, and `bottommost` tells whether the compaction includes the oldest data of
+    /// the column family.
+    fn filter(&self, level: u32, key: &[u8], value: &[u8], bottommost: bool) -> CompactionDecision;
+}
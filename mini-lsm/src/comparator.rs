@@ -0,0 +1,74 @@
+use std::cmp::Ordering;
+
+/// Defines the order of keys in memtables, blocks, SST indexes and merge iterators.
+///
+/// Two keys must compare as equal if and only if they are the same bytes. The comparator is shared
+/// by the storage for its whole lifetime, and its name is persisted so that a storage is never
+/// reopened with a different order.
+pub trait Comparator: Send + Sync {
+    /// The name of the comparator, persisted in the `OPTIONS` file.
+    fn name(&self) -> &str;
+
+    /// Compare two keys.
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering;
+
+    /// Return a key that is greater than `start` and not greater than `limit`, preferably a short
+    /// one. SSTable indexes store it instead of the first key of a block. `start` is less than
+    /// `limit`.
+    ///
+    /// The default implementation returns `limit`.
+    fn find_shortest_separator(&self, start: &[u8], limit: &[u8]) -> Vec<u8> {
+        let _ = start;
+        limit.to_vec()
+    }
+}
+
+/// Orders keys bytewise. This is the default.
+pub struct BytewiseComparator;
+
+impl Comparator for BytewiseComparator {
+    fn name(&self) -> &str {
+        "mini-lsm.BytewiseComparator"
+    }
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        a.cmp(b)
+    }
+
+    fn find_shortest_separator(&self, start: &[u8], limit: &[u8]) -> Vec<u8> {
+        // `limit` is longer than the common prefix, since it is greater than `start`, and one more
+        // byte of it is enough to be greater than `start`
+        let common = start.iter().zip(limit).take_while(|(a, b)| a == b).count();
+        limit[..common + 1].to_vec()
+    }
+}
+
+/// Orders keys in reverse bytewise order.
+pub struct ReverseBytewiseComparator;
+
+impl Comparator for ReverseBytewiseComparator {
+    fn name(&self) -> &str {
+        "mini-lsm.ReverseBytewiseComparator"
+    }
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        b.cmp(a)
+    }
+}
+
+/// Orders 8-byte keys as big-endian `u64`s. Keys of other lengths are ordered bytewise, which
+/// agrees with the `u64` order on 8-byte keys, so all keys are in one total order.
+pub struct U64Comparator;
+
+impl Comparator for U64Comparator {
+    fn name(&self) -> &str {
+        "mini-lsm.U64Comparator"
+    }
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        match (<[u8; 8]>::try_from(a), <[u8; 8]>::try_from(b)) {
+            (Ok(a), Ok(b)) => u64::from_be_bytes(a).cmp(&u64::from_be_bytes(b)),
+            _ => a.cmp(b),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+mod fault;
+mod mem;
+mod posix;
+
+use std::path::Path;
+
+use anyhow::Result;
+use bytes::Bytes;
+pub use fault::FaultInjectionFileSystem;
+pub use mem::MemFileSystem;
+pub use posix::PosixFileSystem;
+
+/// How files are read. File systems that do not store files in the OS may ignore it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FileBackend {
+    /// Read with `pread` into newly allocated buffers.
+    #[default]
+    Pread,
+    /// Map the file into memory, and serve blocks from the mapping without copying.
+    Mmap,
+    /// Read with `O_DIRECT`, bypassing the OS page cache, which would otherwise hold the same
+    /// blocks as the block cache.
+    DirectIo,
+}
+
+/// The file operations of the storage. All files of a storage are created, read and deleted
+/// through its file system, which is set by [`LsmStorageOptions`].
+///
+/// [`LsmStorageOptions`]: crate::lsm_storage::LsmStorageOptions
+pub trait FileSystem: Send + Sync {
+    /// Create a directory and all its missing parents.
+    fn create_dir_all(&self, path: &Path) -> Result<()>;
+
+    /// Create a file to append to, truncating it if it exists.
+    fn create(&self, path: &Path) -> Result<Box<dyn WritableFile>>;
+
+    /// Open a file for reading.
+    fn open(&self, path: &Path, backend: FileBackend) -> Result<Box<dyn RandomAccessFile>>;
+
+    /// Rename a file, replacing the destination if it exists.
+    fn rename(&self, from: &Path, to: &Path) -> Result<()>;
+
+    /// Delete a file. Files that are open stay readable.
+    fn delete(&self, path: &Path) -> Result<()>;
+
+    /// List the names of the files in a directory.
+    fn list(&self, dir: &Path) -> Result<Vec<String>>;
+
+    /// Check whether a file or a directory exists.
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Lock a file, creating it if needed, so that no other lock of it can be taken until the
+    /// returned guard is dropped.
+    fn lock(&self, path: &Path) -> Result<Box<dyn FileLock>>;
+}
+
+/// A file being written.
+pub trait WritableFile: Send {
+    fn append(&mut self, data: &[u8]) -> Result<()>;
+
+    /// Make the appended data durable.
+    fn sync(&mut self) -> Result<()>;
+}
+
+/// A file being read.
+pub trait RandomAccessFile: Send + Sync {
+    /// Read `len` bytes at `offset`. Reading past the end of the file is an error.
+    fn read_at(&self, offset: u64, len: u64) -> Result<Bytes>;
+
+    fn size(&self) -> u64;
+}
+
+/// Holds a lock taken by [`FileSystem::lock`] until dropped.
+pub trait FileLock: Send + Sync {}
+
+/// Read a whole file.
+pub fn read_file(fs: &dyn FileSystem, path: &Path) -> Result<Bytes> {
+    let file = fs.open(path, FileBackend::Pread)?;
+    file.read_at(0, file.size())
+}
+
+/// Write a whole file and sync it.
+pub fn write_file(fs: &dyn FileSystem, path: &Path, data: &[u8]) -> Result<()> {
+    let mut file = fs.create(path)?;
+    file.append(data)?;
+    file.sync()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use parking_lot::Mutex;
+
+use super::{FileBackend, FileLock, FileSystem, RandomAccessFile, WritableFile};
+
+/// Wraps a file system to simulate crashes in tests. It can fail all writes, either at once or
+/// after a number of operations, and drop or tear the data that was written but not synced.
+///
+/// Only data appended to files is tracked. Renames and deletes are durable as soon as they return.
+pub struct FaultInjectionFileSystem {
+    base: Arc<dyn FileSystem>,
+    state: Arc<Mutex<FaultState>>,
+}
+
+struct FaultState {
+    active: bool,
+    /// The number of operations that succeed before the file system crashes, if a crash is set.
+    ops_until_crash: Option<usize>,
+    /// Files written through this file system.
+    files: HashMap<PathBuf, Arc<Mutex<FileState>>>,
+}
+
+#[derive(Default)]
+struct FileState {
+    len: u64,
+    synced_len: u64,
+}
+
+impl FaultState {
+    fn check_active(&mut self) -> Result<()> {
+        if !self.active {
+            bail!("injected fault: file system is inactive");
+        }
+        match &mut self.ops_until_crash {
+            Some(0) => {
+                self.active = false;
+                self.ops_until_crash = None;
+                bail!("injected fault: crash");
+            }
+            Some(n) => *n -= 1,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+impl FaultInjectionFileSystem {
+    pub fn new(base: Arc<dyn FileSystem>) -> Self {
+        Self {
+            base,
+            state: Arc::new(Mutex::new(FaultState {
+                active: true,
+                ops_until_crash: None,
+                files: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Make every later create, append, sync, rename and delete fail while `active` is false, as
+    /// if the process had crashed. Reads keep working.
+    pub fn set_active(&self, active: bool) {
+        self.state.lock().active = active;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.state.lock().active
+    }
+
+    /// Crash after `ops` more create, append, sync, rename and delete operations succeed: the next
+    /// one fails, and the file system becomes inactive as with `set_active(false)`. This fails
+    /// the storage at an arbitrary point, including in the middle of a sync or a rename.
+    pub fn crash_after(&self, ops: usize) {
+        self.state.lock().ops_until_crash = Some(ops);
+    }
+
+    /// Truncate every file written through this file system to the data that was last synced, as
+    /// if the machine had lost power.
+    pub fn drop_unsynced_data(&self) -> Result<()> {
+        self.truncate_unsynced_data(|synced_len, _| synced_len)
+    }
+
+    /// Keep a random prefix of the data written since the last sync of each file, as if only some
+    /// of its pages had reached the disk when the machine lost power. The same seed tears the same
+    /// files the same way.
+    pub fn tear_unsynced_data(&self, seed: u64) -> Result<()> {
+        let mut state = seed;
+        self.truncate_unsynced_data(|synced_len, len| {
+            // splitmix64
+            state = state.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^= z >> 31;
+            synced_len + z % (len - synced_len + 1)
+        })
+    }
+
+    /// Truncate every file with unsynced data to the length returned by `new_len` for its synced
+    /// length and its length.
+    fn truncate_unsynced_data(&self, mut new_len: impl FnMut(u64, u64) -> u64) -> Result<()> {
+        let state = self.state.lock();
+        // visit the files in a fixed order, so that `new_len` is called deterministically
+        let mut files: Vec<_> = state.files.iter().collect();
+        files.sort_by(|a, b| a.0.cmp(b.0));
+        for (path, file) in files {
+            let mut file = file.lock();
+            if file.len == file.synced_len {
+                continue;
+            }
+            let len = new_len(file.synced_len, file.len);
+            let kept = self.base.open(path, FileBackend::Pread)?.read_at(0, len)?;
+            let mut writer = self.base.create(path)?;
+            writer.append(&kept)?;
+            writer.sync()?;
+            file.len = len;
+            file.synced_len = len;
+        }
+        Ok(())
+    }
+}
+
+impl FileSystem for FaultInjectionFileSystem {
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        self.state.lock().check_active()?;
+        self.base.create_dir_all(path)
+    }
+
+    fn create(&self, path: &Path) -> Result<Box<dyn WritableFile>> {
+        let mut state = self.state.lock();
+        state.check_active()?;
+        let base = self.base.create(path)?;
+        let file = Arc::new(Mutex::new(FileState::default()));
+        state.files.insert(path.to_path_buf(), file.clone());
+        Ok(Box::new(FaultWritableFile {
+            base,
+            file,
+            state: self.state.clone(),
+        }))
+    }
+
+    fn open(&self, path: &Path, backend: FileBackend) -> Result<Box<dyn RandomAccessFile>> {
+        self.base.open(path, backend)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        let mut state = self.state.lock();
+        state.check_active()?;
+        self.base.rename(from, to)?;
+        match state.files.remove(from) {
+            Some(file) => state.files.insert(to.to_path_buf(), file),
+            None => state.files.remove(to),
+        };
+        Ok(())
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+        let mut state = self.state.lock();
+        state.check_active()?;
+        self.base.delete(path)?;
+        state.files.remove(path);
+        Ok(())
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<String>> {
+        self.base.list(dir)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.base.exists(path)
+    }
+
+    fn lock(&self, path: &Path) -> Result<Box<dyn FileLock>> {
+        self.base.lock(path)
+    }
+}
+
+struct FaultWritableFile {
+    base: Box<dyn WritableFile>,
+    file: Arc<Mutex<FileState>>,
+    state: Arc<Mutex<FaultState>>,
+}
+
+impl WritableFile for FaultWritableFile {
+    fn append(&mut self, data: &[u8]) -> Result<()> {
+        self.state.lock().check_active()?;
+        self.base.append(data)?;
+        self.file.lock().len += data.len() as u64;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.state.lock().check_active()?;
+        self.base.sync()?;
+        let mut file = self.file.lock();
+        file.synced_len = file.len;
+        Ok(())
+    }
+}
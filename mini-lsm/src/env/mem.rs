@@ -0,0 +1,169 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use parking_lot::{Mutex, RwLock};
+
+use super::{FileBackend, FileLock, FileSystem, RandomAccessFile, WritableFile};
+
+/// A file system that keeps all files in memory, for tests.
+#[derive(Default)]
+pub struct MemFileSystem {
+    inner: Arc<Mutex<MemFileSystemInner>>,
+}
+
+#[derive(Default)]
+struct MemFileSystemInner {
+    files: HashMap<PathBuf, Arc<RwLock<Vec<u8>>>>,
+    dirs: HashSet<PathBuf>,
+    locks: HashSet<PathBuf>,
+}
+
+impl MemFileSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MemFileSystemInner {
+    fn check_parent(&self, path: &Path) -> Result<()> {
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() && !self.dirs.contains(parent) => {
+                bail!("directory {} does not exist", parent.display())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FileSystem for MemFileSystem {
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        let mut inner = self.inner.lock();
+        for dir in path.ancestors() {
+            if inner.files.contains_key(dir) {
+                bail!("{} is a file", dir.display());
+            }
+            inner.dirs.insert(dir.to_path_buf());
+        }
+        Ok(())
+    }
+
+    fn create(&self, path: &Path) -> Result<Box<dyn WritableFile>> {
+        let mut inner = self.inner.lock();
+        inner.check_parent(path)?;
+        if inner.dirs.contains(path) {
+            bail!("{} is a directory", path.display());
+        }
+        let data = Arc::new(RwLock::new(Vec::new()));
+        inner.files.insert(path.to_path_buf(), data.clone());
+        Ok(Box::new(MemWritableFile(data)))
+    }
+
+    fn open(&self, path: &Path, _backend: FileBackend) -> Result<Box<dyn RandomAccessFile>> {
+        let inner = self.inner.lock();
+        let data = inner
+            .files
+            .get(path)
+            .ok_or_else(|| anyhow!("file {} does not exist", path.display()))?;
+        Ok(Box::new(MemRandomAccessFile(data.clone())))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        let mut inner = self.inner.lock();
+        inner.check_parent(to)?;
+        let data = inner
+            .files
+            .remove(from)
+            .ok_or_else(|| anyhow!("file {} does not exist", from.display()))?;
+        inner.files.insert(to.to_path_buf(), data);
+        Ok(())
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+        self.inner
+            .lock()
+            .files
+            .remove(path)
+            .ok_or_else(|| anyhow!("file {} does not exist", path.display()))?;
+        Ok(())
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<String>> {
+        let inner = self.inner.lock();
+        if !inner.dirs.contains(dir) {
+            bail!("directory {} does not exist", dir.display());
+        }
+        let files = inner.files.keys();
+        let dirs = inner.dirs.iter().filter(|x| x.as_path() != dir);
+        Ok(files
+            .chain(dirs)
+            .filter(|x| x.parent() == Some(dir))
+            .filter_map(|x| x.file_name())
+            .map(|x| x.to_string_lossy().into_owned())
+            .collect())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        let inner = self.inner.lock();
+        inner.files.contains_key(path) || inner.dirs.contains(path)
+    }
+
+    fn lock(&self, path: &Path) -> Result<Box<dyn FileLock>> {
+        let mut inner = self.inner.lock();
+        inner.check_parent(path)?;
+        if !inner.locks.insert(path.to_path_buf()) {
+            bail!("failed to lock {}: already locked", path.display());
+        }
+        inner.files.entry(path.to_path_buf()).or_default();
+        Ok(Box::new(MemFileLock {
+            fs: self.inner.clone(),
+            path: path.to_path_buf(),
+        }))
+    }
+}
+
+struct MemWritableFile(Arc<RwLock<Vec<u8>>>);
+
+impl WritableFile for MemWritableFile {
+    fn append(&mut self, data: &[u8]) -> Result<()> {
+        self.0.write().extend_from_slice(data);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct MemRandomAccessFile(Arc<RwLock<Vec<u8>>>);
+
+impl RandomAccessFile for MemRandomAccessFile {
+    fn read_at(&self, offset: u64, len: u64) -> Result<Bytes> {
+        let data = self.0.read();
+        if offset + len > data.len() as u64 {
+            bail!("read past the end of file");
+        }
+        Ok(Bytes::copy_from_slice(
+            &data[offset as usize..(offset + len) as usize],
+        ))
+    }
+
+    fn size(&self) -> u64 {
+        self.0.read().len() as u64
+    }
+}
+
+struct MemFileLock {
+    fs: Arc<Mutex<MemFileSystemInner>>,
+    path: PathBuf,
+}
+
+impl FileLock for MemFileLock {}
+
+impl Drop for MemFileLock {
+    fn drop(&mut self) {
+        self.fs.lock().locks.remove(&self.path);
+    }
+}
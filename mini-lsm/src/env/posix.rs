@@ -0,0 +1,161 @@
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use bytes::Bytes;
+
+use super::{FileBackend, FileLock, FileSystem, RandomAccessFile, WritableFile};
+
+/// The file system of the OS.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PosixFileSystem;
+
+/// The alignment of offsets, lengths and buffers of `O_DIRECT` reads.
+const DIRECT_IO_ALIGNMENT: usize = 4096;
+
+impl FileSystem for PosixFileSystem {
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        Ok(std::fs::create_dir_all(path)?)
+    }
+
+    fn create(&self, path: &Path) -> Result<Box<dyn WritableFile>> {
+        Ok(Box::new(PosixWritableFile(File::create(path)?)))
+    }
+
+    fn open(&self, path: &Path, backend: FileBackend) -> Result<Box<dyn RandomAccessFile>> {
+        let file = match backend {
+            FileBackend::Pread => PosixRandomAccessFile::Pread(File::open(path)?),
+            FileBackend::Mmap => {
+                let file = File::open(path)?;
+                // SAFETY: files are never modified once they are opened for reading
+                let mmap = unsafe { memmap2::Mmap::map(&file)? };
+                PosixRandomAccessFile::Mmap(Bytes::from_owner(mmap))
+            }
+            FileBackend::DirectIo => {
+                let file = File::options()
+                    .read(true)
+                    .custom_flags(libc::O_DIRECT)
+                    .open(path)?;
+                PosixRandomAccessFile::DirectIo(file)
+            }
+        };
+        let size = std::fs::metadata(path)?.len();
+        Ok(Box::new(SizedFile(file, size)))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        Ok(std::fs::rename(from, to)?)
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+        Ok(std::fs::remove_file(path)?)
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            names.push(entry?.file_name().to_string_lossy().into_owned());
+        }
+        Ok(names)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn lock(&self, path: &Path) -> Result<Box<dyn FileLock>> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        // SAFETY: the descriptor is valid while `file` is alive
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            bail!(
+                "failed to lock {}: {}",
+                path.display(),
+                std::io::Error::last_os_error()
+            );
+        }
+        // the lock is released when the file is closed
+        Ok(Box::new(PosixFileLock(file)))
+    }
+}
+
+struct PosixWritableFile(File);
+
+impl WritableFile for PosixWritableFile {
+    fn append(&mut self, data: &[u8]) -> Result<()> {
+        Ok(self.0.write_all(data)?)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(self.0.sync_all()?)
+    }
+}
+
+enum PosixRandomAccessFile {
+    Pread(File),
+    /// The whole file, mapped into memory.
+    Mmap(Bytes),
+    /// A file opened with `O_DIRECT`.
+    DirectIo(File),
+}
+
+struct SizedFile(PosixRandomAccessFile, u64);
+
+impl RandomAccessFile for SizedFile {
+    fn read_at(&self, offset: u64, len: u64) -> Result<Bytes> {
+        if offset + len > self.1 {
+            bail!("read past the end of file");
+        }
+        match &self.0 {
+            PosixRandomAccessFile::Pread(file) => {
+                let mut data = vec![0; len as usize];
+                file.read_exact_at(&mut data[..], offset)?;
+                Ok(data.into())
+            }
+            PosixRandomAccessFile::Mmap(data) => {
+                Ok(data.slice(offset as usize..(offset + len) as usize))
+            }
+            PosixRandomAccessFile::DirectIo(file) => read_direct(file, offset, len),
+        }
+    }
+
+    fn size(&self) -> u64 {
+        self.1
+    }
+}
+
+/// Read through an aligned buffer that covers the aligned range around the requested one.
+fn read_direct(file: &File, offset: u64, len: u64) -> Result<Bytes> {
+    let align = DIRECT_IO_ALIGNMENT as u64;
+    let start = offset / align * align;
+    let end = (offset + len + align - 1) / align * align;
+    let aligned_len = (end - start) as usize;
+    let mut buf = vec![0; aligned_len + DIRECT_IO_ALIGNMENT];
+    let padding = buf.as_ptr().align_offset(DIRECT_IO_ALIGNMENT);
+    let aligned = &mut buf[padding..padding + aligned_len];
+    let mut read = 0;
+    while read < aligned_len {
+        // reads are short only at the end of the file
+        let n = file.read_at(&mut aligned[read..], start + read as u64)?;
+        if n == 0 {
+            break;
+        }
+        read += n;
+    }
+    let range = (offset - start) as usize..(offset - start + len) as usize;
+    if read < range.end {
+        bail!("read past the end of file");
+    }
+    Ok(Bytes::copy_from_slice(&aligned[range]))
+}
+
+struct PosixFileLock(#[allow(dead_code)] File);
+
+impl FileLock for PosixFileLock {}
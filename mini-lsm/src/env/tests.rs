@@ -0,0 +1,126 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use super::*;
+
+fn check_file_system(fs: &dyn FileSystem, dir: &Path) {
+    let dir = dir.join("db");
+    fs.create_dir_all(&dir).unwrap();
+    assert!(fs.exists(&dir));
+    let path = dir.join("1.sst");
+    let mut file = fs.create(&path).unwrap();
+    file.append(b"hello, ").unwrap();
+    file.append(b"world").unwrap();
+    file.sync().unwrap();
+    drop(file);
+
+    let file = fs.open(&path, FileBackend::Pread).unwrap();
+    assert_eq!(file.size(), 12);
+    assert_eq!(&file.read_at(7, 5).unwrap()[..], b"world");
+    assert!(file.read_at(7, 6).is_err());
+    assert_eq!(&read_file(fs, &path).unwrap()[..], b"hello, world");
+
+    let new_path = dir.join("2.sst");
+    fs.rename(&path, &new_path).unwrap();
+    assert!(!fs.exists(&path));
+    assert_eq!(fs.list(&dir).unwrap(), vec!["2.sst".to_string()]);
+    fs.delete(&new_path).unwrap();
+    assert!(fs.list(&dir).unwrap().is_empty());
+    assert!(fs.delete(&new_path).is_err());
+    // an open file stays readable after it is deleted
+    assert_eq!(&file.read_at(0, 5).unwrap()[..], b"hello");
+
+    let lock = fs.lock(&dir.join("LOCK")).unwrap();
+    assert!(fs.lock(&dir.join("LOCK")).is_err());
+    drop(lock);
+    drop(fs.lock(&dir.join("LOCK")).unwrap());
+}
+
+#[test]
+fn test_posix_file_system() {
+    let dir = tempdir().unwrap();
+    check_file_system(&PosixFileSystem, dir.path());
+}
+
+#[test]
+fn test_mem_file_system() {
+    let fs = MemFileSystem::new();
+    check_file_system(&fs, Path::new("/"));
+    assert!(fs.create(Path::new("/missing/1.sst")).is_err());
+}
+
+#[test]
+fn test_fault_injection_file_system() {
+    let fs = FaultInjectionFileSystem::new(Arc::new(MemFileSystem::new()));
+    check_file_system(&fs, Path::new("/"));
+
+    let path = Path::new("/db/1.sst");
+    let mut file = fs.create(path).unwrap();
+    file.append(b"synced").unwrap();
+    file.sync().unwrap();
+    file.append(b" and lost").unwrap();
+    write_file(&fs, Path::new("/db/2.sst"), b"all synced").unwrap();
+    fs.drop_unsynced_data().unwrap();
+    assert_eq!(&read_file(&fs, path).unwrap()[..], b"synced");
+    assert_eq!(
+        &read_file(&fs, Path::new("/db/2.sst")).unwrap()[..],
+        b"all synced"
+    );
+
+    fs.set_active(false);
+    assert!(file.append(b"more").is_err());
+    assert!(file.sync().is_err());
+    assert!(fs.create(Path::new("/db/3.sst")).is_err());
+    assert!(fs.delete(path).is_err());
+    assert_eq!(&read_file(&fs, path).unwrap()[..], b"synced");
+    fs.set_active(true);
+    file.append(b" again").unwrap();
+    fs.drop_unsynced_data().unwrap();
+    assert_eq!(&read_file(&fs, path).unwrap()[..], b"synced");
+}
+
+#[test]
+fn test_fault_injection_crash_after() {
+    let fs = FaultInjectionFileSystem::new(Arc::new(MemFileSystem::new()));
+    fs.create_dir_all(Path::new("/db")).unwrap();
+    let path = Path::new("/db/1.sst");
+    let mut file = fs.create(path).unwrap();
+    fs.crash_after(2);
+    file.append(b"synced").unwrap();
+    file.sync().unwrap();
+    assert!(fs
+        .rename(path, Path::new("/db/2.sst"))
+        .unwrap_err()
+        .to_string()
+        .contains("crash"));
+    assert!(!fs.is_active());
+    assert!(file.append(b"more").is_err());
+    assert!(fs.exists(path));
+    fs.set_active(true);
+    fs.rename(path, Path::new("/db/2.sst")).unwrap();
+}
+
+#[test]
+fn test_fault_injection_tear_unsynced_data() {
+    let tear = |seed| {
+        let fs = FaultInjectionFileSystem::new(Arc::new(MemFileSystem::new()));
+        fs.create_dir_all(Path::new("/db")).unwrap();
+        let mut file = fs.create(Path::new("/db/1.sst")).unwrap();
+        file.append(b"synced").unwrap();
+        file.sync().unwrap();
+        file.append(b" and maybe lost").unwrap();
+        fs.tear_unsynced_data(seed).unwrap();
+        read_file(&fs, Path::new("/db/1.sst")).unwrap()
+    };
+    let mut lens = std::collections::HashSet::new();
+    for seed in 0..20 {
+        let data = tear(seed);
+        assert_eq!(data, tear(seed));
+        assert!(b"synced and maybe lost".starts_with(&data));
+        assert!(data.len() >= b"synced".len());
+        lens.insert(data.len());
+    }
+    assert!(lens.len() > 1);
+}
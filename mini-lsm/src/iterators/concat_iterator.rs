@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use super::StorageIterator;
+use crate::table::{SsTable, SsTableIterator};
+
+/// Concatenates the SSTs of a sorted run, whose key ranges do not overlap and which are sorted by
+/// key range. An SST is only opened once the iterator reaches it.
+pub struct SstConcatIterator {
+    current: Option<SsTableIterator>,
+    next_sst_idx: usize,
+    sstables: Vec<Arc<SsTable>>,
+}
+
+impl SstConcatIterator {
+    /// Create a new iterator and seek to the first key-value pair.
+    pub fn create_and_seek_to_first(sstables: Vec<Arc<SsTable>>) -> Result<Self> {
+        let mut iter = Self {
+            current: None,
+            next_sst_idx: 0,
+            sstables,
+        };
+        iter.move_until_valid()?;
+        Ok(iter)
+    }
+
+    /// Create a new iterator and seek to the first key-value pair which >= `key`.
+    pub fn create_and_seek_to_key(sstables: Vec<Arc<SsTable>>, key: &[u8]) -> Result<Self> {
+        // SSTs whose keys are all smaller than `key` are skipped without being read.
+        let idx = sstables.partition_point(|x| x.comparator().compare(x.last_key(), key).is_lt());
+        let mut iter = Self {
+            current: None,
+            next_sst_idx: idx + 1,
+            sstables,
+        };
+        if idx < iter.sstables.len() {
+            iter.current = Some(SsTableIterator::create_and_seek_to_key(
+                iter.sstables[idx].clone(),
+                key,
+            )?);
+        }
+        iter.move_until_valid()?;
+        Ok(iter)
+    }
+
+    /// Open the next SSTs until the current one has a key-value pair, or all are exhausted.
+    fn move_until_valid(&mut self) -> Result<()> {
+        while !self.current.as_ref().map_or(false, |x| x.is_valid()) {
+            let Some(table) = self.sstables.get(self.next_sst_idx) else {
+                self.current = None;
+                break;
+            };
+            self.current = Some(SsTableIterator::create_and_seek_to_first(table.clone())?);
+            self.next_sst_idx += 1;
+        }
+        Ok(())
+    }
+}
+
+impl StorageIterator for SstConcatIterator {
+    fn value(&self) -> &[u8] {
+        self.current.as_ref().unwrap().value()
+    }
+
+    fn key(&self) -> &[u8] {
+        self.current.as_ref().unwrap().key()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.current.as_ref().map_or(false, |x| x.is_valid())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.current.as_mut().unwrap().next()?;
+        self.move_until_valid()
+    }
+}
@@ -5,8 +5,9 @@ use std::collections::BinaryHeap;
 use anyhow::Result;
 
 use super::StorageIterator;
+use crate::comparator::{BytewiseComparator, Comparator};
 
-struct HeapWrapper<I: StorageIterator>(pub usize, pub Box<I>);
+struct HeapWrapper<I: StorageIterator>(pub usize, pub Box<I>, pub &'static dyn Comparator);
 
 impl<I: StorageIterator> PartialEq for HeapWrapper<I> {
     fn eq(&self, other: &Self) -> bool {
@@ -18,7 +19,7 @@ impl<I: StorageIterator> Eq for HeapWrapper<I> {}
 
 impl<I: StorageIterator> PartialOrd for HeapWrapper<I> {
     fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
-        match self.1.key().cmp(other.1.key()) {
+        match self.2.compare(self.1.key(), other.1.key()) {
             cmp::Ordering::Greater => Some(cmp::Ordering::Greater),
             cmp::Ordering::Less => Some(cmp::Ordering::Less),
             cmp::Ordering::Equal => self.0.partial_cmp(&other.0),
@@ -38,14 +39,21 @@ impl<I: StorageIterator> Ord for HeapWrapper<I> {
 pub struct MergeIterator<I: StorageIterator> {
     iters: BinaryHeap<HeapWrapper<I>>,
     current: Option<HeapWrapper<I>>,
+    comparator: &'static dyn Comparator,
 }
 
 impl<I: StorageIterator> MergeIterator<I> {
     pub fn create(iters: Vec<Box<I>>) -> Self {
+        Self::create_with_comparator(iters, &BytewiseComparator)
+    }
+
+    /// Merge iterators whose keys are ordered by `comparator`.
+    pub fn create_with_comparator(iters: Vec<Box<I>>, comparator: &'static dyn Comparator) -> Self {
         if iters.is_empty() {
             return Self {
                 iters: BinaryHeap::new(),
                 current: None,
+                comparator,
             };
         }
 
@@ -56,13 +64,14 @@ impl<I: StorageIterator> MergeIterator<I> {
             let mut iters = iters;
             return Self {
                 iters: heap,
-                current: Some(HeapWrapper(0, iters.pop().unwrap())),
+                current: Some(HeapWrapper(0, iters.pop().unwrap(), comparator)),
+                comparator,
             };
         }
 
         for (idx, iter) in iters.into_iter().enumerate() {
             if iter.is_valid() {
-                heap.push(HeapWrapper(idx, iter));
+                heap.push(HeapWrapper(idx, iter, comparator));
             }
         }
 
@@ -70,6 +79,7 @@ impl<I: StorageIterator> MergeIterator<I> {
         Self {
             iters: heap,
             current: Some(current),
+            comparator,
         }
     }
 }
@@ -97,7 +107,9 @@ impl<I: StorageIterator> StorageIterator for MergeIterator<I> {
         // Pop the item out of the heap if they have the same value.
         while let Some(mut inner_iter) = self.iters.peek_mut() {
             debug_assert!(
-                inner_iter.1.key() >= current.1.key(),
+                self.comparator
+                    .compare(inner_iter.1.key(), current.1.key())
+                    .is_ge(),
                 "heap invariant violated"
             );
             if inner_iter.1.key() == current.1.key() {
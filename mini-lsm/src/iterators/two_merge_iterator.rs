@@ -1,6 +1,7 @@
 use anyhow::Result;
 
 use super::StorageIterator;
+use crate::comparator::{BytewiseComparator, Comparator};
 
 /// Merges two iterators of different types into one. If the two iterators have the same key, only
 /// produce the key once and prefer the entry from A.
@@ -8,17 +9,18 @@ pub struct TwoMergeIterator<A: StorageIterator, B: StorageIterator> {
     a: A,
     b: B,
     choose_a: bool,
+    comparator: &'static dyn Comparator,
 }
 
 impl<A: StorageIterator, B: StorageIterator> TwoMergeIterator<A, B> {
-    fn choose_a(a: &A, b: &B) -> bool {
+    fn choose_a(a: &A, b: &B, comparator: &dyn Comparator) -> bool {
         if !a.is_valid() {
             return false;
         }
         if !b.is_valid() {
             return true;
         }
-        a.key() < b.key()
+        comparator.compare(a.key(), b.key()).is_lt()
     }
 
     fn skip_b(&mut self) -> Result<()> {
@@ -31,13 +33,19 @@ impl<A: StorageIterator, B: StorageIterator> TwoMergeIterator<A, B> {
     }
 
     pub fn create(a: A, b: B) -> Result<Self> {
+        Self::create_with_comparator(a, b, &BytewiseComparator)
+    }
+
+    /// Merge two iterators whose keys are ordered by `comparator`.
+    pub fn create_with_comparator(a: A, b: B, comparator: &'static dyn Comparator) -> Result<Self> {
         let mut iter = Self {
             choose_a: false,
             a,
             b,
+            comparator,
         };
         iter.skip_b()?;
-        iter.choose_a = Self::choose_a(&iter.a, &iter.b);
+        iter.choose_a = Self::choose_a(&iter.a, &iter.b, iter.comparator);
         Ok(iter)
     }
 }
@@ -74,7 +82,7 @@ impl<A: StorageIterator, B: StorageIterator> StorageIterator for TwoMergeIterato
             self.b.next()?;
         }
         self.skip_b()?;
-        self.choose_a = Self::choose_a(&self.a, &self.b);
+        self.choose_a = Self::choose_a(&self.a, &self.b, self.comparator);
         Ok(())
     }
 }
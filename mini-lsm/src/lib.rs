@@ -1,9 +1,20 @@
 pub mod block;
+pub mod compact;
+pub mod compaction_filter;
+pub mod comparator;
+pub mod env;
 pub mod iterators;
 pub mod lsm_iterator;
 pub mod lsm_storage;
+pub mod manifest;
 pub mod mem_table;
+pub mod prefix;
+pub mod rate_limiter;
 pub mod table;
+pub mod ttl;
+pub mod wal;
+pub mod write_batch;
+pub mod write_stall;
 
 #[cfg(test)]
 mod tests;
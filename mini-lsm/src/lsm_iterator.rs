@@ -3,52 +3,88 @@ use std::ops::Bound;
 use anyhow::Result;
 use bytes::Bytes;
 
+use crate::comparator::Comparator;
+use crate::iterators::concat_iterator::SstConcatIterator;
 use crate::iterators::merge_iterator::MergeIterator;
 use crate::iterators::two_merge_iterator::TwoMergeIterator;
 use crate::iterators::StorageIterator;
 use crate::mem_table::MemTableIterator;
 use crate::table::SsTableIterator;
+use crate::ttl::{decode_value, is_deleted_or_expired};
 
-type LsmIteratorInner =
-    TwoMergeIterator<MergeIterator<MemTableIterator>, MergeIterator<SsTableIterator>>;
+type LsmIteratorInner = TwoMergeIterator<
+    MergeIterator<MemTableIterator>,
+    TwoMergeIterator<MergeIterator<SsTableIterator>, MergeIterator<SstConcatIterator>>,
+>;
 
 pub struct LsmIterator {
     iter: LsmIteratorInner,
     end_bound: Bound<Bytes>,
     is_valid: bool,
+    /// The time at which the scan started. Values expired at this time are skipped.
+    now: u64,
+    comparator: &'static dyn Comparator,
+    num_skipped_tombstones: u64,
+    /// Where the user value starts in the stored value of the current entry.
+    value_offset: usize,
 }
 
 impl LsmIterator {
-    pub(crate) fn new(iter: LsmIteratorInner, end_bound: Bound<Bytes>) -> Result<Self> {
+    pub(crate) fn new(
+        iter: LsmIteratorInner,
+        end_bound: Bound<Bytes>,
+        now: u64,
+        comparator: &'static dyn Comparator,
+    ) -> Result<Self> {
         let mut iter = Self {
-            is_valid: iter.is_valid(),
+            is_valid: false,
             iter,
             end_bound,
+            now,
+            comparator,
+            num_skipped_tombstones: 0,
+            value_offset: 0,
         };
+        // the first key may already be past the end, e.g. if the bounds are empty
+        iter.update_is_valid();
         iter.move_to_non_delete()?;
         Ok(iter)
     }
 
+    /// Check whether the inner iterator is valid and has not passed the end bound.
+    fn update_is_valid(&mut self) {
+        self.is_valid = self.iter.is_valid()
+            && match self.end_bound.as_ref() {
+                Bound::Unbounded => true,
+                Bound::Included(key) => self.comparator.compare(self.iter.key(), key).is_le(),
+                Bound::Excluded(key) => self.comparator.compare(self.iter.key(), key).is_lt(),
+            };
+    }
+
     fn next_inner(&mut self) -> Result<()> {
         self.iter.next()?;
-        if !self.iter.is_valid() {
-            self.is_valid = false;
-            return Ok(());
-        }
-        match self.end_bound.as_ref() {
-            Bound::Unbounded => {}
-            Bound::Included(key) => self.is_valid = self.iter.key() <= key.as_ref(),
-            Bound::Excluded(key) => self.is_valid = self.iter.key() < key.as_ref(),
-        }
+        self.update_is_valid();
         Ok(())
     }
 
     fn move_to_non_delete(&mut self) -> Result<()> {
-        while self.is_valid() && self.iter.value().is_empty() {
+        while self.is_valid() && is_deleted_or_expired(self.iter.value(), self.now)? {
+            self.num_skipped_tombstones += 1;
             self.next_inner()?;
         }
+        // decode the value here, as `value` cannot return an error
+        if self.is_valid() {
+            let raw = self.iter.value();
+            self.value_offset = raw.len() - decode_value(raw)?.0.len();
+        }
         Ok(())
     }
+
+    /// Get the number of tombstones and expired values skipped so far. Older versions hidden by
+    /// a newer entry of the same key are not counted.
+    pub fn num_skipped_tombstones(&self) -> u64 {
+        self.num_skipped_tombstones
+    }
 }
 
 impl StorageIterator for LsmIterator {
@@ -61,7 +97,7 @@ impl StorageIterator for LsmIterator {
     }
 
     fn value(&self) -> &[u8] {
-        self.iter.value()
+        &self.iter.value()[self.value_offset..]
     }
 
     fn next(&mut self) -> Result<()> {
@@ -83,6 +119,13 @@ impl<I: StorageIterator> FusedIterator<I> {
     }
 }
 
+impl FusedIterator<LsmIterator> {
+    /// Get the number of tombstones and expired values the scan skipped so far.
+    pub fn num_skipped_tombstones(&self) -> u64 {
+        self.iter.num_skipped_tombstones()
+    }
+}
+
 impl<I: StorageIterator> StorageIterator for FusedIterator<I> {
     fn is_valid(&self) -> bool {
         self.iter.is_valid()
@@ -1,182 +1,1086 @@
+use std::collections::{HashMap, HashSet};
 use std::ops::Bound;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use bytes::Bytes;
-use parking_lot::{Mutex, RwLock};
+use parking_lot::{Condvar, Mutex, RwLock};
 
 use crate::block::Block;
+use crate::compact::{CompactionOptions, CompactionStrategy};
+use crate::compaction_filter::CompactionFilter;
+use crate::comparator::{BytewiseComparator, Comparator};
+use crate::env::{read_file, write_file, FileBackend, FileSystem, PosixFileSystem};
+use crate::iterators::concat_iterator::SstConcatIterator;
 use crate::iterators::merge_iterator::MergeIterator;
 use crate::iterators::two_merge_iterator::TwoMergeIterator;
 use crate::iterators::StorageIterator;
 use crate::lsm_iterator::{FusedIterator, LsmIterator};
+use crate::manifest::{Manifest, ManifestRecord};
 use crate::mem_table::{map_bound, MemTable};
-use crate::table::{SsTable, SsTableBuilder, SsTableIterator};
+use crate::prefix::PrefixExtractor;
+use crate::rate_limiter::{IoPriority, RateLimiter};
+use crate::table::{
+    FileObject, SsTable, SsTableBuilder, SsTableIterator, TablePropertiesCollectorFactory,
+};
+use crate::ttl::{decode_value, encode_value, is_deleted_or_expired, Clock, SystemClock};
+use crate::wal::Wal;
+use crate::write_batch::{WriteBatch, WriteBatchRecord};
+use crate::write_stall::{WriteStallCondition, WriteStallOptions};
 
 pub type BlockCache = moka::sync::Cache<(usize, usize), Arc<Block>>;
 
+/// The name of the column family used by `get`, `put`, `delete` and `scan`.
+pub const DEFAULT_CF: &str = "default";
+
 #[derive(Clone)]
 pub struct LsmStorageInner {
     /// The current memtable.
-    memtable: Arc<MemTable>,
+    pub(crate) memtable: Arc<MemTable>,
     /// Immutable memTables, from earliest to latest.
-    imm_memtables: Vec<Arc<MemTable>>,
+    pub(crate) imm_memtables: Vec<Arc<MemTable>>,
     /// L0 SsTables, from earliest to latest.
-    l0_sstables: Vec<Arc<SsTable>>,
-    /// L1 - L6 SsTables, sorted by key range.
-    #[allow(dead_code)]
-    levels: Vec<Vec<Arc<SsTable>>>,
-    /// The next SSTable ID.
-    next_sst_id: usize,
+    pub(crate) l0_sstables: Vec<Arc<SsTable>>,
+    /// Sorted runs below L0, from latest to earliest. Each run is sorted by key range. Leveled
+    /// compaction keeps a fixed number of runs, some of which may be empty.
+    pub(crate) levels: Vec<Vec<Arc<SsTable>>>,
+    /// Writes with this sequence number or an older one are all in SSTs.
+    pub(crate) flushed_seq: u64,
 }
 
 impl LsmStorageInner {
-    fn create() -> Self {
+    fn create(memtable: MemTable) -> Self {
         Self {
-            memtable: Arc::new(MemTable::create()),
+            memtable: Arc::new(memtable),
             imm_memtables: vec![],
             l0_sstables: vec![],
             levels: vec![],
-            next_sst_id: 1,
+            flushed_seq: 0,
+        }
+    }
+
+    /// L0 SsTables, from earliest to latest.
+    pub fn l0_sstables(&self) -> &[Arc<SsTable>] {
+        &self.l0_sstables
+    }
+
+    /// Sorted runs below L0, from latest to earliest.
+    pub fn levels(&self) -> &[Vec<Arc<SsTable>>] {
+        &self.levels
+    }
+}
+
+/// Options of a column family.
+#[derive(Clone)]
+pub struct ColumnFamilyOptions {
+    /// The target block size of the SSTs.
+    pub block_size: usize,
+    /// The target size of SSTs written by compaction.
+    pub target_sst_size: usize,
+    /// Split the index of each SST into partitions of about this size, which are read through the
+    /// block cache when needed. By default, the whole index is kept in memory.
+    pub index_partition_size: Option<usize>,
+    /// How SSTs are compacted in the background.
+    pub compaction: CompactionOptions,
+    /// The maximum number of threads a compaction job is split into. Each thread merges its own
+    /// key range.
+    pub max_subcompactions: usize,
+    /// When writes are delayed or blocked because compaction falls behind.
+    pub write_stall: WriteStallOptions,
+    /// Add user properties to every SST written by flush and compaction.
+    pub table_properties_collectors: Vec<Arc<dyn TablePropertiesCollectorFactory>>,
+    /// Bits per key of the bloom filter of each SST, or zero for no filter.
+    pub bloom_bits_per_key: usize,
+    /// Add whole keys to the bloom filters, which lets `get` skip SSTs.
+    pub whole_key_filtering: bool,
+    /// Add the prefixes of keys to the bloom filters, which lets `scan_prefix` skip SSTs and
+    /// memtables.
+    pub prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    /// The expected size of a memtable when it is flushed, which memtable bloom filters are sized
+    /// from.
+    pub write_buffer_size: usize,
+    /// The size of the bloom filter of each memtable as a fraction of `write_buffer_size`, or zero
+    /// for no filter. Prefixes are added if there is a prefix extractor.
+    pub memtable_prefix_bloom_size_ratio: f64,
+    /// Add whole keys to the memtable bloom filters, which lets `get` skip memtables.
+    pub memtable_whole_key_filtering: bool,
+    /// Called by compaction on every key-value pair it rewrites, to remove keys or change values.
+    pub compaction_filter: Option<Arc<dyn CompactionFilter>>,
+}
+
+impl Default for ColumnFamilyOptions {
+    fn default() -> Self {
+        Self {
+            block_size: 4096,
+            target_sst_size: 2 << 20,
+            index_partition_size: None,
+            compaction: CompactionOptions::NoCompaction,
+            max_subcompactions: 1,
+            write_stall: WriteStallOptions::default(),
+            table_properties_collectors: Vec::new(),
+            bloom_bits_per_key: 0,
+            whole_key_filtering: true,
+            prefix_extractor: None,
+            write_buffer_size: 64 << 20,
+            memtable_prefix_bloom_size_ratio: 0.0,
+            memtable_whole_key_filtering: false,
+            compaction_filter: None,
         }
     }
 }
 
+impl ColumnFamilyOptions {
+    /// Create an empty memtable with the bloom filter set by the options.
+    fn create_memtable(&self, comparator: &'static dyn Comparator) -> MemTable {
+        let bloom_size = (self.write_buffer_size as f64 * self.memtable_prefix_bloom_size_ratio)
+            .min(self.write_buffer_size as f64) as usize;
+        if bloom_size == 0 {
+            return MemTable::create_with_comparator(comparator);
+        }
+        MemTable::create_with_bloom_filter(
+            comparator,
+            bloom_size,
+            self.memtable_whole_key_filtering,
+            self.prefix_extractor.clone(),
+        )
+    }
+}
+
+/// A point-in-time view of a column family, returned by [`LsmStorage::stats_cf`].
+#[derive(Clone, Debug)]
+pub struct ColumnFamilyStats {
+    pub num_l0_sstables: usize,
+    /// The number of non-empty sorted runs below L0.
+    pub num_sorted_runs: usize,
+    pub num_imm_memtables: usize,
+    /// The estimated number of bytes the next compaction rewrites.
+    pub pending_compaction_bytes: u64,
+    pub write_stall: WriteStallCondition,
+    /// The number of writes delayed so far.
+    pub num_delayed_writes: u64,
+    /// The number of writes blocked so far.
+    pub num_stopped_writes: u64,
+    /// The total time writers spent delayed or blocked.
+    pub write_stall_time: Duration,
+    /// The total size of SSTs compaction moved to another sorted run without rewriting them.
+    pub compaction_bytes_moved: u64,
+    /// The total size of SSTs compaction read and rewrote.
+    pub compaction_bytes_rewritten: u64,
+    /// The last error of background compaction, if it ever failed.
+    pub background_error: Option<String>,
+}
+
+/// An independent keyspace with its own memtables, SSTs and options.
+pub(crate) struct ColumnFamily {
+    /// Identifies the column family in the WAL and the manifest. IDs are not reused.
+    id: u32,
+    name: String,
+    pub(crate) inner: Arc<RwLock<Arc<LsmStorageInner>>>,
+    /// Held while a change to the state is recorded in the manifest and installed, so that
+    /// changes are recorded in the order they are installed.
+    state_lock: Mutex<()>,
+    flush_lock: Mutex<()>,
+    /// Held by the compaction job running on this column family.
+    pub(crate) compaction_lock: Mutex<()>,
+    pub(crate) options: ColumnFamilyOptions,
+    pub(crate) strategy: Option<Box<dyn CompactionStrategy>>,
+    /// Set once the column family is dropped, so that an in-flight `sync` does not flush it.
+    dropped: AtomicBool,
+    pub(crate) comparator: &'static dyn Comparator,
+    /// The [`WriteStallCondition`] of the current state, updated under `stall_lock` whenever flush
+    /// or compaction changes the state.
+    write_stall: AtomicU8,
+    /// Blocked writers wait on this until flush or compaction changes the state.
+    stall_lock: Mutex<()>,
+    stall_cv: Condvar,
+    num_delayed_writes: AtomicU64,
+    num_stopped_writes: AtomicU64,
+    write_stall_micros: AtomicU64,
+    /// The total size of SSTs compaction moved without rewriting them.
+    pub(crate) compaction_bytes_moved: AtomicU64,
+    /// The total size of SSTs compaction rewrote.
+    pub(crate) compaction_bytes_rewritten: AtomicU64,
+    /// The last error of background compaction.
+    pub(crate) background_error: Mutex<Option<String>>,
+}
+
+impl ColumnFamily {
+    fn create(
+        id: u32,
+        name: &str,
+        options: ColumnFamilyOptions,
+        storage_options: &LsmStorageOptions,
+    ) -> Self {
+        let comparator = storage_options.comparator;
+        let strategy = options.compaction.strategy(&storage_options.clock);
+        let mut state = LsmStorageInner::create(options.create_memtable(comparator));
+        if let Some(num_levels) = strategy.as_ref().and_then(|x| x.num_fixed_levels()) {
+            state.levels = vec![Vec::new(); num_levels];
+        }
+        Self {
+            id,
+            name: name.to_string(),
+            inner: Arc::new(RwLock::new(Arc::new(state))),
+            state_lock: Mutex::new(()),
+            flush_lock: Mutex::new(()),
+            compaction_lock: Mutex::new(()),
+            strategy,
+            options,
+            dropped: AtomicBool::new(false),
+            comparator,
+            write_stall: AtomicU8::new(WriteStallCondition::Normal as u8),
+            stall_lock: Mutex::new(()),
+            stall_cv: Condvar::new(),
+            num_delayed_writes: AtomicU64::new(0),
+            num_stopped_writes: AtomicU64::new(0),
+            write_stall_micros: AtomicU64::new(0),
+            compaction_bytes_moved: AtomicU64::new(0),
+            compaction_bytes_rewritten: AtomicU64::new(0),
+            background_error: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> Arc<LsmStorageInner> {
+        let guard = self.inner.read();
+        Arc::clone(&guard)
+    }
+
+    /// Install the SSTs recovered from the manifest. The sorted runs are fit into the levels of
+    /// the compaction strategy, which may differ from the one that wrote them.
+    fn recover_state(
+        &self,
+        l0_sstables: Vec<Arc<SsTable>>,
+        mut levels: Vec<Vec<Arc<SsTable>>>,
+        flushed_seq: u64,
+    ) -> Result<()> {
+        match self.strategy.as_ref().and_then(|x| x.num_fixed_levels()) {
+            Some(num_levels) => {
+                if levels[num_levels.min(levels.len())..]
+                    .iter()
+                    .any(|run| !run.is_empty())
+                {
+                    bail!(
+                        "column family {} has more sorted runs than {} levels",
+                        self.name,
+                        num_levels
+                    );
+                }
+                levels.resize(num_levels, Vec::new());
+            }
+            None => levels.retain(|run| !run.is_empty()),
+        }
+        let mut guard = self.inner.write();
+        let mut snapshot = guard.as_ref().clone();
+        snapshot.l0_sstables = l0_sstables;
+        snapshot.levels = levels;
+        snapshot.flushed_seq = flushed_seq;
+        *guard = Arc::new(snapshot);
+        Ok(())
+    }
+
+    /// The manifest record of a state of the column family.
+    fn manifest_record(&self, snapshot: &LsmStorageInner) -> ManifestRecord {
+        let ids = |tables: &[Arc<SsTable>]| tables.iter().map(|x| x.sst_id()).collect();
+        ManifestRecord::ColumnFamily {
+            id: self.id,
+            name: self.name.clone(),
+            flushed_seq: snapshot.flushed_seq,
+            l0_sstables: ids(&snapshot.l0_sstables),
+            levels: snapshot.levels.iter().map(|run| ids(run)).collect(),
+        }
+    }
+
+    /// Whether some writes to the column family are only in memtables.
+    fn has_unflushed_data(&self) -> bool {
+        let snapshot = self.snapshot();
+        !snapshot.memtable.is_empty() || !snapshot.imm_memtables.is_empty()
+    }
+
+    /// Check a put, or a delete if `value` is `None`, before it is applied. Keys cannot be empty,
+    /// and empty values are reserved for tombstones.
+    fn check_write(&self, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        if key.is_empty() {
+            bail!("key cannot be empty");
+        }
+        if value.map_or(false, |x| x.is_empty()) {
+            bail!("value cannot be empty");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_dropped(&self) -> bool {
+        self.dropped.load(Ordering::SeqCst)
+    }
+
+    /// Recompute the write stall condition after a flush or compaction changed the state, and wake
+    /// up blocked writers.
+    pub(crate) fn notify_state_changed(&self) {
+        let _stall_lock = self.stall_lock.lock();
+        // Computed under the lock, so that the last update always sees the latest state.
+        let (write_stall, _) = self.compute_write_stall(&self.snapshot());
+        self.write_stall.store(write_stall as u8, Ordering::Release);
+        self.stall_cv.notify_all();
+    }
+
+    /// Get the write stall condition computed by the last state change.
+    fn write_stall(&self) -> WriteStallCondition {
+        WriteStallCondition::from_u8(self.write_stall.load(Ordering::Acquire))
+    }
+
+    /// Compute the write stall condition and the pending compaction bytes of a state. This picks a
+    /// compaction task, so writers do not call it.
+    fn compute_write_stall(&self, snapshot: &LsmStorageInner) -> (WriteStallCondition, u64) {
+        let task = self
+            .strategy
+            .as_ref()
+            .and_then(|x| x.pick_compaction(snapshot));
+        let pending_compaction_bytes = task.as_ref().map_or(0, |x| x.input_size());
+        // Waiting only helps if compaction has something to do.
+        let write_stall = if task.is_none() || self.is_dropped() {
+            WriteStallCondition::Normal
+        } else {
+            // Flushed sorted runs take the place of L0 SSTs with tiered compaction.
+            let num_l0 = if self.strategy.as_ref().map_or(true, |x| x.flush_to_l0()) {
+                snapshot.l0_sstables.len()
+            } else {
+                snapshot.levels.len()
+            };
+            self.options.write_stall.condition(
+                num_l0,
+                snapshot.imm_memtables.len(),
+                pending_compaction_bytes,
+            )
+        };
+        (write_stall, pending_compaction_bytes)
+    }
+
+    fn stats(&self) -> ColumnFamilyStats {
+        let snapshot = self.snapshot();
+        let (_, pending_compaction_bytes) = self.compute_write_stall(&snapshot);
+        ColumnFamilyStats {
+            num_l0_sstables: snapshot.l0_sstables.len(),
+            num_sorted_runs: snapshot.levels.iter().filter(|run| !run.is_empty()).count(),
+            num_imm_memtables: snapshot.imm_memtables.len(),
+            pending_compaction_bytes,
+            write_stall: self.write_stall(),
+            num_delayed_writes: self.num_delayed_writes.load(Ordering::Relaxed),
+            num_stopped_writes: self.num_stopped_writes.load(Ordering::Relaxed),
+            write_stall_time: Duration::from_micros(
+                self.write_stall_micros.load(Ordering::Relaxed),
+            ),
+            compaction_bytes_moved: self.compaction_bytes_moved.load(Ordering::Relaxed),
+            compaction_bytes_rewritten: self.compaction_bytes_rewritten.load(Ordering::Relaxed),
+            background_error: self.background_error.lock().clone(),
+        }
+    }
+}
+
+/// Options for opening an [`LsmStorage`].
+#[derive(Clone)]
+pub struct LsmStorageOptions {
+    /// The clock used to stamp and check the expiry of keys written with a TTL.
+    pub clock: Arc<dyn Clock>,
+    /// Options of the default column family.
+    pub default_cf: ColumnFamilyOptions,
+    /// Options of the other column families recovered when the storage is opened, by name.
+    /// Recovered column families not listed here get default options.
+    pub column_families: HashMap<String, ColumnFamilyOptions>,
+    /// The order of keys in all column families. Its name is persisted in the `OPTIONS` file, and
+    /// the storage refuses to open with a different comparator.
+    pub comparator: &'static dyn Comparator,
+    /// Limits the write rate of flush and compaction. Flush writes have high priority.
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// How SST files are read.
+    pub file_backend: FileBackend,
+    /// The file system all files of the storage are written to and read from.
+    pub file_system: Arc<dyn FileSystem>,
+}
+
+impl Default for LsmStorageOptions {
+    fn default() -> Self {
+        Self {
+            clock: Arc::new(SystemClock),
+            default_cf: ColumnFamilyOptions::default(),
+            column_families: HashMap::new(),
+            comparator: &BytewiseComparator,
+            rate_limiter: None,
+            file_backend: FileBackend::default(),
+            file_system: Arc::new(PosixFileSystem),
+        }
+    }
+}
+
+const OPTIONS_FILE: &str = "OPTIONS";
+const MANIFEST_FILE: &str = "MANIFEST";
+const MANIFEST_TMP_FILE: &str = "MANIFEST.tmp";
+const OPTIONS_COMPARATOR: &str = "comparator=";
+
+/// Persist the options that cannot change across restarts into the `OPTIONS` file, or check them
+/// against the file if the storage was opened before.
+fn check_or_write_options(path: &Path, options: &LsmStorageOptions) -> Result<()> {
+    let fs = options.file_system.as_ref();
+    let options_path = path.join(OPTIONS_FILE);
+    if !fs.exists(&options_path) {
+        fs.create_dir_all(path)?;
+        write_file(
+            fs,
+            &options_path,
+            format!("{}{}\n", OPTIONS_COMPARATOR, options.comparator.name()).as_bytes(),
+        )?;
+        return Ok(());
+    }
+    let content = read_file(fs, &options_path)?;
+    let content = std::str::from_utf8(&content)?;
+    for line in content.lines() {
+        if let Some(name) = line.strip_prefix(OPTIONS_COMPARATOR) {
+            if name != options.comparator.name() {
+                bail!(
+                    "storage was created with comparator {}, cannot open with {}",
+                    name,
+                    options.comparator.name()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
 /// The storage interface of the LSM tree.
 pub struct LsmStorage {
-    inner: Arc<RwLock<Arc<LsmStorageInner>>>,
-    flush_lock: Mutex<()>,
+    /// All column families, including the default one. SSTs of all column families share the
+    /// directory, the block cache and the SST ID space.
+    column_families: RwLock<HashMap<String, Arc<ColumnFamily>>>,
+    /// The next SSTable ID.
+    next_sst_id: AtomicUsize,
+    next_cf_id: AtomicU32,
+    /// The WAL new writes are appended to. It is locked while writes are applied to the
+    /// memtables, and while a flush swaps a memtable and starts a new WAL.
+    wal: Mutex<Wal>,
+    /// Earlier WAL files, with the sequence number of their last write. A file is deleted once
+    /// all its writes are flushed.
+    closed_wals: Mutex<Vec<(usize, u64)>>,
+    /// The sequence number of the last applied write, which reads see along with all older ones.
+    last_seq: AtomicU64,
+    manifest: Manifest,
     path: PathBuf,
-    block_cache: Arc<BlockCache>,
+    pub(crate) block_cache: Arc<BlockCache>,
+    pub(crate) options: LsmStorageOptions,
+    /// Dropped together with the storage to stop the compaction thread.
+    _compaction_stop_tx: Mutex<Sender<()>>,
+}
+
+/// Parse the ID of an SST or WAL file name with the given extension.
+fn parse_file_id(name: &str, extension: &str) -> Option<usize> {
+    name.strip_suffix(extension)?
+        .strip_suffix('.')?
+        .parse()
+        .ok()
 }
 
 impl LsmStorage {
-    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
-        Ok(Self {
-            inner: Arc::new(RwLock::new(Arc::new(LsmStorageInner::create()))),
-            flush_lock: Mutex::new(()),
-            path: path.as_ref().to_path_buf(),
-            block_cache: Arc::new(BlockCache::new(1 << 20)), // 4GB block cache
-        })
+    pub fn open(path: impl AsRef<Path>) -> Result<Arc<Self>> {
+        Self::open_with_options(path, LsmStorageOptions::default())
+    }
+
+    /// Open a storage, recovering the state recorded in its manifest and the writes in its WAL
+    /// files if it was opened before. Recovered writes are flushed to SSTs before this returns.
+    pub fn open_with_options(
+        path: impl AsRef<Path>,
+        options: LsmStorageOptions,
+    ) -> Result<Arc<Self>> {
+        let path = path.as_ref();
+        check_or_write_options(path, &options)?;
+        let fs = options.file_system.clone();
+        let block_cache = Arc::new(BlockCache::new(1 << 20)); // 4GB block cache
+
+        // The last recorded state of every column family that was not dropped.
+        let mut records = Vec::new();
+        let mut next_cf_id = 0;
+        let manifest_path = path.join(MANIFEST_FILE);
+        if fs.exists(&manifest_path) {
+            let mut dropped = HashSet::new();
+            for record in Manifest::replay(&read_file(fs.as_ref(), &manifest_path)?)? {
+                match record {
+                    ManifestRecord::ColumnFamily { id, .. } => {
+                        next_cf_id = next_cf_id.max(id + 1);
+                        if !dropped.contains(&id) {
+                            records.retain(
+                                |x| !matches!(x, ManifestRecord::ColumnFamily { id: y, .. } if *y == id),
+                            );
+                            records.push(record);
+                        }
+                    }
+                    ManifestRecord::DropColumnFamily { id } => {
+                        next_cf_id = next_cf_id.max(id + 1);
+                        dropped.insert(id);
+                        records.retain(
+                            |x| !matches!(x, ManifestRecord::ColumnFamily { id: y, .. } if *y == id),
+                        );
+                    }
+                }
+            }
+        }
+
+        let mut column_families = HashMap::new();
+        let mut live_ssts = HashSet::new();
+        let mut last_seq = 0;
+        for record in records {
+            let ManifestRecord::ColumnFamily {
+                id,
+                name,
+                flushed_seq,
+                l0_sstables,
+                levels,
+            } = record
+            else {
+                continue;
+            };
+            let cf_options = match name.as_str() {
+                DEFAULT_CF => options.default_cf.clone(),
+                _ => options
+                    .column_families
+                    .get(&name)
+                    .cloned()
+                    .unwrap_or_default(),
+            };
+            let cf = ColumnFamily::create(id, &name, cf_options, &options);
+            let mut open_sst = |id: usize| -> Result<Arc<SsTable>> {
+                live_ssts.insert(id);
+                let file =
+                    FileObject::open_in(fs.as_ref(), &sst_path(path, id), options.file_backend)?;
+                Ok(Arc::new(SsTable::open_with_comparator(
+                    id,
+                    Some(block_cache.clone()),
+                    file,
+                    options.comparator,
+                )?))
+            };
+            let l0_sstables = l0_sstables
+                .into_iter()
+                .map(&mut open_sst)
+                .collect::<Result<_>>()?;
+            let levels = levels
+                .into_iter()
+                .map(|run| run.into_iter().map(&mut open_sst).collect())
+                .collect::<Result<_>>()?;
+            cf.recover_state(l0_sstables, levels, flushed_seq)?;
+            last_seq = last_seq.max(flushed_seq);
+            column_families.insert(name, Arc::new(cf));
+        }
+        if !column_families.contains_key(DEFAULT_CF) {
+            let cf =
+                ColumnFamily::create(next_cf_id, DEFAULT_CF, options.default_cf.clone(), &options);
+            next_cf_id += 1;
+            column_families.insert(DEFAULT_CF.to_string(), Arc::new(cf));
+        }
+
+        // Replay the writes that were not flushed. Writes to dropped column families are skipped.
+        let files = fs.list(path)?;
+        let mut wal_ids: Vec<_> = files
+            .iter()
+            .filter_map(|x| parse_file_id(x, "wal"))
+            .collect();
+        wal_ids.sort_unstable();
+        let cf_by_id: HashMap<_, _> = column_families.values().map(|x| (x.id, x)).collect();
+        for &wal_id in &wal_ids {
+            let data = read_file(fs.as_ref(), &wal_path(path, wal_id))?;
+            for record in Wal::replay(&data)? {
+                last_seq = last_seq.max(record.seq);
+                for write in record.writes {
+                    let Some(cf) = cf_by_id.get(&write.cf_id) else {
+                        continue;
+                    };
+                    let snapshot = cf.snapshot();
+                    if record.seq > snapshot.flushed_seq {
+                        snapshot
+                            .memtable
+                            .put_with_seq(&write.key, &write.value, record.seq);
+                    }
+                }
+            }
+        }
+        let next_sst_id = files
+            .iter()
+            .filter_map(|x| parse_file_id(x, "sst"))
+            .chain(live_ssts.iter().copied())
+            .max()
+            .unwrap_or(0)
+            + 1;
+
+        // Start a new manifest from the recovered state. SSTs it does not refer to were left
+        // behind by a flush or compaction that did not finish.
+        let manifest_records: Vec<_> = column_families
+            .values()
+            .map(|cf| cf.manifest_record(&cf.snapshot()))
+            .collect();
+        let manifest = Manifest::create(
+            fs.as_ref(),
+            &manifest_path,
+            &path.join(MANIFEST_TMP_FILE),
+            &manifest_records,
+        )?;
+        for id in files.iter().filter_map(|x| parse_file_id(x, "sst")) {
+            if !live_ssts.contains(&id) {
+                fs.delete(&sst_path(path, id))?;
+            }
+        }
+
+        let wal_id = wal_ids.last().map_or(1, |id| id + 1);
+        let wal = Wal::create(fs.as_ref(), &wal_path(path, wal_id), wal_id)?;
+        let (compaction_stop_tx, compaction_stop_rx) = channel();
+        let storage = Arc::new(Self {
+            column_families: RwLock::new(column_families),
+            next_sst_id: AtomicUsize::new(next_sst_id),
+            next_cf_id: AtomicU32::new(next_cf_id),
+            wal: Mutex::new(wal),
+            closed_wals: Mutex::new(wal_ids.into_iter().map(|id| (id, last_seq)).collect()),
+            last_seq: AtomicU64::new(last_seq),
+            manifest,
+            path: path.to_path_buf(),
+            block_cache,
+            options,
+            _compaction_stop_tx: Mutex::new(compaction_stop_tx),
+        });
+        // Flushing the replayed writes lets the old WAL files go.
+        storage.sync()?;
+        Self::spawn_compaction_thread(Arc::downgrade(&storage), compaction_stop_rx);
+        Ok(storage)
+    }
+
+    pub(crate) fn next_sst_id(&self) -> usize {
+        self.next_sst_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    pub(crate) fn all_column_families(&self) -> Vec<Arc<ColumnFamily>> {
+        self.column_families.read().values().cloned().collect()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn snapshot_for_test(&self, cf: &str) -> Arc<LsmStorageInner> {
+        self.column_family(cf).unwrap().snapshot()
+    }
+
+    pub(crate) fn column_family(&self, name: &str) -> Result<Arc<ColumnFamily>> {
+        self.column_families
+            .read()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("column family {} does not exist", name))
+    }
+
+    /// Create a new, empty column family.
+    pub fn create_column_family(&self, name: &str, options: ColumnFamilyOptions) -> Result<()> {
+        let mut column_families = self.column_families.write();
+        if column_families.contains_key(name) {
+            bail!("column family {} already exists", name);
+        }
+        let id = self.next_cf_id.fetch_add(1, Ordering::SeqCst);
+        let cf = ColumnFamily::create(id, name, options, &self.options);
+        self.manifest
+            .add_record(&cf.manifest_record(&cf.snapshot()))?;
+        column_families.insert(name.to_string(), Arc::new(cf));
+        Ok(())
+    }
+
+    /// Drop a column family and delete its SSTs. Iterators already created on it stay usable.
+    pub fn drop_column_family(&self, name: &str) -> Result<()> {
+        if name == DEFAULT_CF {
+            bail!("cannot drop the default column family");
+        }
+        let cf = {
+            let mut column_families = self.column_families.write();
+            let cf = column_families
+                .get(name)
+                .ok_or_else(|| anyhow!("column family {} does not exist", name))?;
+            // Once the drop is recorded, the SSTs and the writes in the WAL of the column family
+            // are ignored when the storage is opened again.
+            self.manifest
+                .add_record(&ManifestRecord::DropColumnFamily { id: cf.id })?;
+            column_families.remove(name).unwrap()
+        };
+
+        // Wait for in-flight flush and compaction, and prevent later ones from writing new SSTs.
+        let _flush_lock = cf.flush_lock.lock();
+        let _compaction_lock = cf.compaction_lock.lock();
+        cf.dropped.store(true, Ordering::SeqCst);
+        // Writers blocked on the column family can go on.
+        cf.notify_state_changed();
+        let snapshot = cf.snapshot();
+        for table in snapshot
+            .l0_sstables
+            .iter()
+            .chain(snapshot.levels.iter().flatten())
+        {
+            self.options
+                .file_system
+                .delete(&self.path_of_sst(table.sst_id()))?;
+        }
+        Ok(())
+    }
+
+    /// List the names of all column families.
+    pub fn list_column_families(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.column_families.read().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Get the stats of the default column family.
+    pub fn stats(&self) -> Result<ColumnFamilyStats> {
+        self.stats_cf(DEFAULT_CF)
+    }
+
+    /// Get the stats of a column family.
+    pub fn stats_cf(&self, cf: &str) -> Result<ColumnFamilyStats> {
+        Ok(self.column_family(cf)?.stats())
+    }
+
+    /// Delay or block a write while compaction of the column family cannot keep up.
+    fn wait_for_write_stall(&self, cf: &ColumnFamily) {
+        let start = Instant::now();
+        match cf.write_stall() {
+            WriteStallCondition::Normal => return,
+            WriteStallCondition::Delayed => {
+                cf.num_delayed_writes.fetch_add(1, Ordering::Relaxed);
+                std::thread::sleep(cf.options.write_stall.slowdown_delay);
+            }
+            WriteStallCondition::Stopped => {
+                cf.num_stopped_writes.fetch_add(1, Ordering::Relaxed);
+                let mut guard = cf.stall_lock.lock();
+                while cf.write_stall() == WriteStallCondition::Stopped {
+                    cf.stall_cv.wait(&mut guard);
+                }
+            }
+        }
+        cf.write_stall_micros
+            .fetch_add(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Decode a stored value, treating tombstones and expired values as absent.
+    fn visible_value(value: &[u8], now: u64) -> Result<Option<Bytes>> {
+        if is_deleted_or_expired(value, now)? {
+            return Ok(None);
+        }
+        Ok(Some(Bytes::copy_from_slice(decode_value(value)?.0)))
     }
 
     /// Get a key from the storage. In day 7, this can be further optimized by using a bloom filter.
     pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
-        let snapshot = {
-            let guard = self.inner.read();
-            Arc::clone(&guard)
-        }; // drop global lock here
+        self.get_cf(DEFAULT_CF, key)
+    }
+
+    /// Get a key from a column family.
+    pub fn get_cf(&self, cf: &str, key: &[u8]) -> Result<Option<Bytes>> {
+        let cf = self.column_family(cf)?;
+        let read_seq = self.last_seq.load(Ordering::Acquire);
+        let snapshot = cf.snapshot(); // drop global lock here
+        let now = self.options.clock.now();
 
         // Search on the current memtable.
-        if let Some(value) = snapshot.memtable.get(key) {
-            if value.is_empty() {
-                // found tomestone, return key not exists
-                return Ok(None);
-            }
-            return Ok(Some(value));
+        if let Some(value) = snapshot.memtable.get_at_seq(key, read_seq) {
+            // a tombstone or an expired value hides older versions of the key
+            return Self::visible_value(&value, now);
         }
         // Search on immutable memtables.
         for memtable in snapshot.imm_memtables.iter().rev() {
-            if let Some(value) = memtable.get(key) {
-                if value.is_empty() {
-                    // found tomestone, return key not exists
-                    return Ok(None);
-                }
-                return Ok(Some(value));
+            if let Some(value) = memtable.get_at_seq(key, read_seq) {
+                return Self::visible_value(&value, now);
             }
         }
         let mut iters = Vec::new();
-        iters.reserve(snapshot.l0_sstables.len());
-        for table in snapshot.l0_sstables.iter().rev() {
+        iters.reserve(snapshot.l0_sstables.len() + snapshot.levels.len());
+        // Only one SST of a sorted run can hold the key.
+        let run_tables = snapshot.levels.iter().filter_map(|run| {
+            let idx = run.partition_point(|x| cf.comparator.compare(x.last_key(), key).is_lt());
+            run.get(idx)
+                .filter(|x| cf.comparator.compare(x.first_key(), key).is_le())
+        });
+        for table in snapshot.l0_sstables.iter().rev().chain(run_tables) {
+            if !table.may_contain_key(key) {
+                continue;
+            }
             iters.push(Box::new(SsTableIterator::create_and_seek_to_key(
                 table.clone(),
                 key,
             )?));
         }
-        let iter = MergeIterator::create(iters);
-        if iter.is_valid() {
-            return Ok(Some(Bytes::copy_from_slice(iter.value())));
+        let iter = MergeIterator::create_with_comparator(iters, cf.comparator);
+        if iter.is_valid() && iter.key() == key {
+            return Self::visible_value(iter.value(), now);
         }
         Ok(None)
     }
 
     /// Put a key-value pair into the storage by writing into the current memtable.
     pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
-        assert!(!value.is_empty(), "value cannot be empty");
-        assert!(!key.is_empty(), "key cannot be empty");
+        self.put_cf(DEFAULT_CF, key, value)
+    }
 
-        let guard = self.inner.read();
-        guard.memtable.put(key, value);
+    /// Put a key-value pair into a column family.
+    pub fn put_cf(&self, cf: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        let cf = self.column_family(cf)?;
+        cf.check_write(key, Some(value))?;
+        self.wait_for_write_stall(&cf);
+        self.apply_writes(&[(&cf, key, encode_value(value, None))])
+    }
+
+    /// Put a key-value pair that expires after `ttl`. Once expired, the key is treated as absent by
+    /// `get` and `scan`.
+    pub fn put_with_ttl(&self, key: &[u8], value: &[u8], ttl: Duration) -> Result<()> {
+        self.put_with_ttl_cf(DEFAULT_CF, key, value, ttl)
+    }
+
+    /// Put a key-value pair that expires after `ttl` into a column family.
+    pub fn put_with_ttl_cf(&self, cf: &str, key: &[u8], value: &[u8], ttl: Duration) -> Result<()> {
+        let cf = self.column_family(cf)?;
+        cf.check_write(key, Some(value))?;
+        self.wait_for_write_stall(&cf);
+        let value = encode_value(value, Some(self.expire_at(ttl)));
+        self.apply_writes(&[(&cf, key, value)])
+    }
 
+    /// The expiry of a key written now with `ttl`. A TTL too large to represent never expires.
+    fn expire_at(&self, ttl: Duration) -> u64 {
+        let ttl = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+        self.options.clock.now().saturating_add(ttl)
+    }
+
+    /// Write stored values, checked by [`ColumnFamily::check_write`] and encoded, into the WAL and
+    /// the memtables. All of them are stamped with one sequence number, so that reads see either
+    /// all of them or none, and so are replayed from the WAL.
+    fn apply_writes(&self, writes: &[(&ColumnFamily, &[u8], Vec<u8>)]) -> Result<()> {
+        let mut wal = self.wal.lock();
+        let seq = self.last_seq.load(Ordering::Relaxed) + 1;
+        let records: Vec<_> = writes
+            .iter()
+            .map(|(cf, key, value)| (cf.id, *key, &value[..]))
+            .collect();
+        wal.append(seq, &records)?;
+        for (cf, key, value) in writes {
+            let guard = cf.inner.read();
+            guard.memtable.put_with_seq(key, value, seq);
+        }
+        self.last_seq.store(seq, Ordering::Release);
         Ok(())
     }
 
     /// Remove a key from the storage by writing an empty value.
     pub fn delete(&self, key: &[u8]) -> Result<()> {
-        assert!(!key.is_empty(), "key cannot be empty");
+        self.delete_cf(DEFAULT_CF, key)
+    }
 
-        let guard = self.inner.read();
-        guard.memtable.put(key, b"");
+    /// Remove a key from a column family.
+    pub fn delete_cf(&self, cf: &str, key: &[u8]) -> Result<()> {
+        let cf = self.column_family(cf)?;
+        cf.check_write(key, None)?;
+        self.wait_for_write_stall(&cf);
+        self.apply_writes(&[(&cf, key, Vec::new())])
+    }
 
-        Ok(())
+    /// Apply a batch of writes, which may span several column families. If any column family in
+    /// the batch does not exist, or any record is invalid, nothing is written. Reads see either
+    /// all writes of the batch or none.
+    pub fn write(&self, batch: &WriteBatch) -> Result<()> {
+        let mut names: Vec<_> = batch.records().iter().map(|x| x.cf()).collect();
+        names.sort_unstable();
+        names.dedup();
+        for name in names {
+            // Missing column families are reported below.
+            if let Ok(cf) = self.column_family(name) {
+                self.wait_for_write_stall(&cf);
+            }
+        }
+
+        // Hold the lock until the whole batch is applied, so that no column family in the batch
+        // can be dropped halfway.
+        let column_families = self.column_families.read();
+        let writes = batch
+            .records()
+            .iter()
+            .map(|record| {
+                let cf = column_families
+                    .get(record.cf())
+                    .ok_or_else(|| anyhow!("column family {} does not exist", record.cf()))?;
+                match record {
+                    WriteBatchRecord::Put {
+                        key, value, ttl, ..
+                    } => {
+                        cf.check_write(key, Some(value))?;
+                        let expire_at = ttl.map(|ttl| self.expire_at(ttl));
+                        Ok((cf.as_ref(), &key[..], encode_value(value, expire_at)))
+                    }
+                    WriteBatchRecord::Delete { key, .. } => {
+                        cf.check_write(key, None)?;
+                        Ok((cf.as_ref(), &key[..], Vec::new()))
+                    }
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.apply_writes(&writes)
     }
 
-    fn path_of_sst(&self, id: usize) -> PathBuf {
-        self.path.join(format!("{:05}.sst", id))
+    pub(crate) fn path_of_sst(&self, id: usize) -> PathBuf {
+        sst_path(&self.path, id)
     }
 
     /// Persist data to disk.
     ///
-    /// In day 3: flush the current memtable to disk as L0 SST.
+    /// In day 3: flush the current memtable of every column family to disk as L0 SST.
     /// In day 6: call `fsync` on WAL.
     pub fn sync(&self) -> Result<()> {
-        let _flush_lock = self.flush_lock.lock();
+        for cf in self.all_column_families() {
+            self.flush_column_family(&cf)?;
+        }
+        self.wal.lock().sync()
+    }
+
+    /// Record a change to the state of a column family in the manifest, and install it.
+    pub(crate) fn update_state(
+        &self,
+        cf: &ColumnFamily,
+        f: impl FnOnce(&mut LsmStorageInner),
+    ) -> Result<()> {
+        let _state_lock = cf.state_lock.lock();
+        let mut snapshot = cf.snapshot().as_ref().clone();
+        f(&mut snapshot);
+        self.manifest.add_record(&cf.manifest_record(&snapshot))?;
+        *cf.inner.write() = Arc::new(snapshot);
+        cf.notify_state_changed();
+        Ok(())
+    }
+
+    /// Start a new WAL file for later writes. The old file is kept until all its writes are
+    /// flushed.
+    fn rotate_wal(&self, wal: &mut Wal) -> Result<()> {
+        wal.sync()?;
+        let id = wal.id() + 1;
+        let new_wal = Wal::create(
+            self.options.file_system.as_ref(),
+            &wal_path(&self.path, id),
+            id,
+        )?;
+        let old_wal = std::mem::replace(wal, new_wal);
+        let last_seq = self.last_seq.load(Ordering::Relaxed);
+        self.closed_wals.lock().push((old_wal.id(), last_seq));
+        Ok(())
+    }
 
-        let flush_memtable;
-        let sst_id;
+    /// Delete the WAL files whose writes are all flushed.
+    fn purge_wals(&self) -> Result<()> {
+        let column_families = self.all_column_families();
+        let mut closed_wals = self.closed_wals.lock();
+        let mut result = Ok(());
+        closed_wals.retain(|&(id, last_seq)| {
+            if result.is_err()
+                || column_families
+                    .iter()
+                    .any(|cf| cf.snapshot().flushed_seq < last_seq && cf.has_unflushed_data())
+            {
+                return true;
+            }
+            result = self.options.file_system.delete(&wal_path(&self.path, id));
+            result.is_err()
+        });
+        result
+    }
+
+    /// Create a builder for an SST of a column family written by flush or compaction.
+    pub(crate) fn new_sst_builder(
+        &self,
+        cf: &ColumnFamily,
+        creation_time: u64,
+        level: u32,
+        priority: IoPriority,
+    ) -> SsTableBuilder {
+        let mut builder = SsTableBuilder::new_with_comparator(cf.options.block_size, cf.comparator);
+        builder.set_creation_time(creation_time);
+        builder.set_level(level);
+        builder.set_file_system(self.options.file_system.clone());
+        builder.set_file_backend(self.options.file_backend);
+        if let Some(partition_size) = cf.options.index_partition_size {
+            builder.set_index_partition_size(partition_size);
+        }
+        if let Some(rate_limiter) = &self.options.rate_limiter {
+            builder.set_rate_limiter(rate_limiter.clone(), priority);
+        }
+        for factory in &cf.options.table_properties_collectors {
+            builder.add_properties_collector(factory.create());
+        }
+        if cf.options.bloom_bits_per_key > 0 {
+            builder.set_bloom_filter(
+                cf.options.bloom_bits_per_key,
+                cf.options.whole_key_filtering,
+            );
+            if let Some(prefix_extractor) = &cf.options.prefix_extractor {
+                builder.set_prefix_extractor(prefix_extractor.clone());
+            }
+        }
+        builder
+    }
+
+    pub(crate) fn flush_column_family(&self, cf: &ColumnFamily) -> Result<()> {
+        let _flush_lock = cf.flush_lock.lock();
+        if cf.is_dropped() {
+            return Ok(());
+        }
 
         // Move mutable memtable to immutable memtables.
-        {
-            let mut guard = self.inner.write();
+        if !cf.snapshot().memtable.is_empty() {
+            let _state_lock = cf.state_lock.lock();
+            // No write is halfway applied to the memtable once it is swapped, so the flushed SST
+            // only holds writes that reads already see. Later writes go to a new WAL file, which
+            // lets the old one be deleted once all column families flushed its writes.
+            let mut wal = self.wal.lock();
+            self.rotate_wal(&mut wal)?;
+            let mut guard = cf.inner.write();
             // Swap the current memtable with a new one.
             let mut snapshot = guard.as_ref().clone();
-            let memtable = std::mem::replace(&mut snapshot.memtable, Arc::new(MemTable::create()));
-            flush_memtable = memtable.clone();
-            sst_id = snapshot.next_sst_id;
+            let memtable = std::mem::replace(
+                &mut snapshot.memtable,
+                Arc::new(cf.options.create_memtable(cf.comparator)),
+            );
             // Add the memtable to the immutable memtables.
             snapshot.imm_memtables.push(memtable);
             // Update the snapshot.
             *guard = Arc::new(snapshot);
         }
+        // One more immutable memtable may delay writes.
+        cf.notify_state_changed();
 
         // At this point, the old memtable should be disabled for write, and all write threads
         // should be operating on the new memtable. We can safely flush the old memtable to
-        // disk.
-
-        let mut builder = SsTableBuilder::new(4096);
-        flush_memtable.flush(&mut builder)?;
-        let sst = Arc::new(builder.build(
-            sst_id,
-            Some(self.block_cache.clone()),
-            self.path_of_sst(sst_id),
-        )?);
+        // disk. Immutable memtables are flushed from the oldest, including any left by a flush
+        // that failed.
+        while let Some(flush_memtable) = cf.snapshot().imm_memtables.first().cloned() {
+            let sst_id = self.next_sst_id();
+            let mut builder =
+                self.new_sst_builder(cf, self.options.clock.now(), 0, IoPriority::High);
+            flush_memtable.flush(&mut builder)?;
+            let sst = Arc::new(builder.build(
+                sst_id,
+                Some(self.block_cache.clone()),
+                self.path_of_sst(sst_id),
+            )?);
 
-        // Add the flushed L0 table to the list.
-        {
-            let mut guard = self.inner.write();
-            let mut snapshot = guard.as_ref().clone();
-            // Remove the memtable from the immutable memtables.
-            snapshot.imm_memtables.pop();
-            // Add L0 table
-            snapshot.l0_sstables.push(sst);
-            // Update SST ID
-            snapshot.next_sst_id += 1;
-            // Update the snapshot.
-            *guard = Arc::new(snapshot);
+            // Add the flushed L0 table to the list.
+            self.update_state(cf, |snapshot| {
+                // Remove the memtable from the immutable memtables.
+                snapshot.imm_memtables.remove(0);
+                snapshot.flushed_seq = snapshot.flushed_seq.max(flush_memtable.max_seq());
+                // Add L0 table, or a new sorted run if the compaction strategy does not use L0.
+                if cf.strategy.as_ref().map_or(true, |x| x.flush_to_l0()) {
+                    snapshot.l0_sstables.push(sst);
+                } else {
+                    snapshot.levels.insert(0, vec![sst]);
+                }
+            })?;
         }
 
-        Ok(())
+        self.purge_wals()
     }
 
     /// Create an iterator over a range of keys.
@@ -185,45 +1089,150 @@ impl LsmStorage {
         lower: Bound<&[u8]>,
         upper: Bound<&[u8]>,
     ) -> Result<FusedIterator<LsmIterator>> {
-        let snapshot = {
-            let guard = self.inner.read();
-            Arc::clone(&guard)
-        }; // drop global lock here
+        self.scan_cf(DEFAULT_CF, lower, upper)
+    }
+
+    /// Create an iterator over a range of keys in a column family.
+    pub fn scan_cf(
+        &self,
+        cf: &str,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        let cf = self.column_family(cf)?;
+        self.scan_inner(&cf, lower, upper, None)
+    }
+
+    /// Create an iterator over all keys starting with `prefix`.
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Result<FusedIterator<LsmIterator>> {
+        self.scan_prefix_cf(DEFAULT_CF, prefix)
+    }
+
+    /// Create an iterator over all keys starting with `prefix` in a column family. If `prefix` is
+    /// a prefix of the prefix extractor of the column family, memtables and SSTs whose filter
+    /// rules it out are skipped.
+    pub fn scan_prefix_cf(&self, cf: &str, prefix: &[u8]) -> Result<FusedIterator<LsmIterator>> {
+        let cf = self.column_family(cf)?;
+        if cf.comparator.name() != BytewiseComparator.name() {
+            bail!("prefix scans require keys ordered bytewise");
+        }
+        let upper = prefix_successor(prefix);
+        let upper = match &upper {
+            Some(key) => Bound::Excluded(&key[..]),
+            None => Bound::Unbounded,
+        };
+        let extractor = cf
+            .options
+            .prefix_extractor
+            .as_deref()
+            .filter(|x| x.in_domain(prefix) && x.transform(prefix) == prefix);
+        let filter_prefix = extractor.map(|x| (prefix, x));
+        self.scan_inner(&cf, Bound::Included(prefix), upper, filter_prefix)
+    }
+
+    /// Create an iterator over a range of keys. If `filter_prefix` is set, memtables and SSTs
+    /// whose filter rules out the prefix are skipped.
+    fn scan_inner(
+        &self,
+        cf: &ColumnFamily,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        filter_prefix: Option<(&[u8], &dyn PrefixExtractor)>,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        let read_seq = self.last_seq.load(Ordering::Acquire);
+        let snapshot = cf.snapshot(); // drop global lock here
+        let now = self.options.clock.now();
 
         let mut memtable_iters = Vec::new();
         memtable_iters.reserve(snapshot.imm_memtables.len() + 1);
-        memtable_iters.push(Box::new(snapshot.memtable.scan(lower, upper)));
-        for memtable in snapshot.imm_memtables.iter().rev() {
-            memtable_iters.push(Box::new(memtable.scan(lower, upper)));
+        for memtable in
+            std::iter::once(&snapshot.memtable).chain(snapshot.imm_memtables.iter().rev())
+        {
+            if let Some((prefix, extractor)) = filter_prefix {
+                if !memtable.may_contain_prefix(prefix, extractor) {
+                    continue;
+                }
+            }
+            memtable_iters.push(Box::new(memtable.scan_at_seq(lower, upper, read_seq)));
         }
-        let memtable_iter = MergeIterator::create(memtable_iters);
+        let memtable_iter = MergeIterator::create_with_comparator(memtable_iters, cf.comparator);
 
-        let mut table_iters = Vec::new();
-        table_iters.reserve(snapshot.l0_sstables.len());
+        let may_contain_prefix = |table: &SsTable| {
+            filter_prefix.map_or(true, |(prefix, extractor)| {
+                table.may_contain_prefix(prefix, extractor)
+            })
+        };
+        let mut l0_iters = Vec::new();
+        l0_iters.reserve(snapshot.l0_sstables.len());
         for table in snapshot.l0_sstables.iter().rev() {
+            if !may_contain_prefix(table) {
+                continue;
+            }
             let iter = match lower {
-                Bound::Included(key) => {
+                Bound::Included(key) | Bound::Excluded(key) => {
                     SsTableIterator::create_and_seek_to_key(table.clone(), key)?
                 }
-                Bound::Excluded(key) => {
-                    let mut iter = SsTableIterator::create_and_seek_to_key(table.clone(), key)?;
-                    if iter.is_valid() && iter.key() == key {
-                        iter.next()?;
-                    }
-                    iter
-                }
                 Bound::Unbounded => SsTableIterator::create_and_seek_to_first(table.clone())?,
             };
+            l0_iters.push(Box::new(skip_excluded(iter, lower)?));
+        }
+        let l0_iter = MergeIterator::create_with_comparator(l0_iters, cf.comparator);
 
-            table_iters.push(Box::new(iter));
+        let mut run_iters = Vec::new();
+        run_iters.reserve(snapshot.levels.len());
+        for run in &snapshot.levels {
+            let tables: Vec<_> = run
+                .iter()
+                .filter(|x| may_contain_prefix(x))
+                .cloned()
+                .collect();
+            let iter = match lower {
+                Bound::Included(key) | Bound::Excluded(key) => {
+                    SstConcatIterator::create_and_seek_to_key(tables, key)?
+                }
+                Bound::Unbounded => SstConcatIterator::create_and_seek_to_first(tables)?,
+            };
+            run_iters.push(Box::new(skip_excluded(iter, lower)?));
         }
-        let table_iter = MergeIterator::create(table_iters);
+        let run_iter = MergeIterator::create_with_comparator(run_iters, cf.comparator);
+        // L0 SSTs are newer than all sorted runs.
+        let table_iter =
+            TwoMergeIterator::create_with_comparator(l0_iter, run_iter, cf.comparator)?;
 
-        let iter = TwoMergeIterator::create(memtable_iter, table_iter)?;
+        let iter =
+            TwoMergeIterator::create_with_comparator(memtable_iter, table_iter, cf.comparator)?;
 
         Ok(FusedIterator::new(LsmIterator::new(
             iter,
             map_bound(upper),
+            now,
+            cf.comparator,
         )?))
     }
 }
+
+fn sst_path(path: &Path, id: usize) -> PathBuf {
+    path.join(format!("{:05}.sst", id))
+}
+
+fn wal_path(path: &Path, id: usize) -> PathBuf {
+    path.join(format!("{:05}.wal", id))
+}
+
+/// Move an iterator seeked to the key of an excluded lower bound past that key.
+fn skip_excluded<I: StorageIterator>(mut iter: I, lower: Bound<&[u8]>) -> Result<I> {
+    if let Bound::Excluded(key) = lower {
+        if iter.is_valid() && iter.key() == key {
+            iter.next()?;
+        }
+    }
+    Ok(iter)
+}
+
+/// Get the smallest key greater than all keys starting with `prefix`, or `None` if there is none.
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let end = prefix.iter().rposition(|&x| x != 0xff)?;
+    let mut successor = prefix[..=end].to_vec();
+    successor[end] += 1;
+    Some(successor)
+}
@@ -0,0 +1,158 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use bytes::BufMut;
+use parking_lot::Mutex;
+
+use crate::env::{FileSystem, WritableFile};
+use crate::wal::{decode_records, encode_record, get_slice, get_uint};
+
+const COLUMN_FAMILY: u8 = 1;
+const DROP_COLUMN_FAMILY: u8 = 2;
+
+/// A change to the state of a storage recorded in the manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ManifestRecord {
+    /// The whole state of a column family, which replaces the state recorded before. Recording the
+    /// whole state keeps replay simple, at the cost of records growing with the number of SSTs.
+    ColumnFamily {
+        id: u32,
+        name: String,
+        /// Writes to the column family with this sequence number or an older one are in SSTs.
+        flushed_seq: u64,
+        /// The IDs of the L0 SSTs, from the oldest to the newest.
+        l0_sstables: Vec<usize>,
+        /// The IDs of the SSTs of each sorted run, from the newest run to the oldest one.
+        levels: Vec<Vec<usize>>,
+    },
+    /// A dropped column family. Later records of the column family are ignored.
+    DropColumnFamily { id: u32 },
+}
+
+impl ManifestRecord {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            ManifestRecord::ColumnFamily {
+                id,
+                name,
+                flushed_seq,
+                l0_sstables,
+                levels,
+            } => {
+                buf.put_u8(COLUMN_FAMILY);
+                buf.put_u32(*id);
+                buf.put_u16(name.len() as u16);
+                buf.put_slice(name.as_bytes());
+                buf.put_u64(*flushed_seq);
+                let put_ids = |buf: &mut Vec<u8>, ids: &[usize]| {
+                    buf.put_u32(ids.len() as u32);
+                    for &id in ids {
+                        buf.put_u64(id as u64);
+                    }
+                };
+                put_ids(buf, l0_sstables);
+                buf.put_u32(levels.len() as u32);
+                for run in levels {
+                    put_ids(buf, run);
+                }
+            }
+            ManifestRecord::DropColumnFamily { id } => {
+                buf.put_u8(DROP_COLUMN_FAMILY);
+                buf.put_u32(*id);
+            }
+        }
+    }
+
+    fn decode(mut buf: &[u8]) -> Result<Self> {
+        let record = match get_uint(&mut buf, 1)? as u8 {
+            COLUMN_FAMILY => {
+                let id = get_uint(&mut buf, 4)? as u32;
+                let name = String::from_utf8(get_slice(&mut buf, 2)?.to_vec())?;
+                let flushed_seq = get_uint(&mut buf, 8)?;
+                let get_ids = |buf: &mut &[u8]| -> Result<Vec<usize>> {
+                    let len = get_uint(buf, 4)?;
+                    (0..len).map(|_| Ok(get_uint(buf, 8)? as usize)).collect()
+                };
+                let l0_sstables = get_ids(&mut buf)?;
+                let num_levels = get_uint(&mut buf, 4)?;
+                let levels = (0..num_levels)
+                    .map(|_| get_ids(&mut buf))
+                    .collect::<Result<_>>()?;
+                ManifestRecord::ColumnFamily {
+                    id,
+                    name,
+                    flushed_seq,
+                    l0_sstables,
+                    levels,
+                }
+            }
+            DROP_COLUMN_FAMILY => ManifestRecord::DropColumnFamily {
+                id: get_uint(&mut buf, 4)? as u32,
+            },
+            tag => bail!("unknown manifest record {}", tag),
+        };
+        if !buf.is_empty() {
+            bail!("trailing bytes in manifest record");
+        }
+        Ok(record)
+    }
+}
+
+/// The log of changes to the SSTs and column families of a storage. Every change is synced
+/// before it takes effect, so that SSTs are only deleted once no recorded state refers to them.
+pub struct Manifest {
+    /// Taken once an append failed, which may have left part of a record in the file. Records
+    /// appended after it would be cut off by replay, so all later appends fail too.
+    file: Mutex<Option<Box<dyn WritableFile>>>,
+}
+
+impl Manifest {
+    /// Write a new manifest holding `records` to `tmp_path`, and move it to `path` once it is
+    /// synced, so that a crash leaves either the old manifest or the new one.
+    pub fn create(
+        fs: &dyn FileSystem,
+        path: &Path,
+        tmp_path: &Path,
+        records: &[ManifestRecord],
+    ) -> Result<Self> {
+        let mut file = fs.create(tmp_path)?;
+        let mut buf = Vec::new();
+        for record in records {
+            let mut payload = Vec::new();
+            record.encode(&mut payload);
+            encode_record(&payload, &mut buf);
+        }
+        file.append(&buf)?;
+        file.sync()?;
+        fs.rename(tmp_path, path)?;
+        Ok(Self {
+            file: Mutex::new(Some(file)),
+        })
+    }
+
+    /// Decode the records of a manifest. Replay stops at a record cut short by a crash, whose
+    /// change never took effect.
+    pub fn replay(data: &[u8]) -> Result<Vec<ManifestRecord>> {
+        decode_records(data)
+            .into_iter()
+            .map(ManifestRecord::decode)
+            .collect()
+    }
+
+    /// Append a record and sync it.
+    pub fn add_record(&self, record: &ManifestRecord) -> Result<()> {
+        let mut payload = Vec::new();
+        record.encode(&mut payload);
+        let mut buf = Vec::new();
+        encode_record(&payload, &mut buf);
+        let mut guard = self.file.lock();
+        let Some(file) = guard.as_mut() else {
+            bail!("an earlier write to the manifest failed");
+        };
+        let result = file.append(&buf).and_then(|()| file.sync());
+        if result.is_err() {
+            *guard = None;
+        }
+        result
+    }
+}
@@ -1,4 +1,8 @@
+mod bloom;
+
+use std::cmp::Ordering;
 use std::ops::Bound;
+use std::sync::atomic::{self, AtomicU64};
 use std::sync::Arc;
 
 use anyhow::Result;
@@ -7,12 +11,58 @@ use crossbeam_skiplist::map::Entry;
 use crossbeam_skiplist::SkipMap;
 use ouroboros::self_referencing;
 
+use self::bloom::MemTableBloom;
+use crate::comparator::{BytewiseComparator, Comparator};
 use crate::iterators::StorageIterator;
-use crate::table::SsTableBuilder;
+use crate::prefix::PrefixExtractor;
+use crate::table::{hash_for_filter, SsTableBuilder};
+
+/// A version of a key in the skiplist, ordered by the comparator of the mem-table, then from the
+/// newest to the oldest sequence number.
+#[derive(Clone)]
+struct MemTableKey {
+    key: Bytes,
+    seq: u64,
+    comparator: &'static dyn Comparator,
+}
+
+impl PartialEq for MemTableKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key && self.seq == other.seq
+    }
+}
+
+impl Eq for MemTableKey {}
+
+impl PartialOrd for MemTableKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MemTableKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.comparator
+            .compare(&self.key, &other.key)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
 
-/// A basic mem-table based on crossbeam-skiplist
+/// A basic mem-table based on crossbeam-skiplist.
+///
+/// Each write is stamped with a sequence number, and reads at a sequence number only see the
+/// writes stamped with it or an older one. The storage stamps all writes of a batch with the same
+/// number, so that readers see either all of them or none. Writes through [`MemTable::put`] are
+/// stamped with zero.
 pub struct MemTable {
-    map: Arc<SkipMap<Bytes, Bytes>>,
+    map: Arc<SkipMap<MemTableKey, Bytes>>,
+    comparator: &'static dyn Comparator,
+    /// The bloom filter over keys and prefixes, if the mem-table has one.
+    bloom: Option<MemTableBloom>,
+    whole_key_filtering: bool,
+    prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    /// The largest sequence number written.
+    max_seq: AtomicU64,
 }
 
 pub(crate) fn map_bound(bound: Bound<&[u8]>) -> Bound<Bytes> {
@@ -26,64 +76,212 @@ pub(crate) fn map_bound(bound: Bound<&[u8]>) -> Bound<Bytes> {
 impl MemTable {
     /// Create a new mem-table.
     pub fn create() -> Self {
+        Self::create_with_comparator(&BytewiseComparator)
+    }
+
+    /// Create a new mem-table whose keys are ordered by `comparator`.
+    pub fn create_with_comparator(comparator: &'static dyn Comparator) -> Self {
         Self {
             map: Arc::new(SkipMap::new()),
+            comparator,
+            bloom: None,
+            whole_key_filtering: false,
+            prefix_extractor: None,
+            max_seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Create a new mem-table with a bloom filter of about `bloom_size` bytes. Whole keys are
+    /// added to the filter if `whole_key_filtering` is set, and prefixes if a prefix extractor is
+    /// given.
+    pub fn create_with_bloom_filter(
+        comparator: &'static dyn Comparator,
+        bloom_size: usize,
+        whole_key_filtering: bool,
+        prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    ) -> Self {
+        Self {
+            bloom: Some(MemTableBloom::new(bloom_size)),
+            whole_key_filtering,
+            prefix_extractor,
+            ..Self::create_with_comparator(comparator)
+        }
+    }
+
+    fn make_key(&self, key: &[u8], seq: u64) -> MemTableKey {
+        MemTableKey {
+            key: Bytes::copy_from_slice(key),
+            seq,
+            comparator: self.comparator,
+        }
+    }
+
+    /// Map a bound of a range of keys to a bound of a range of versions, which includes or
+    /// excludes all versions of the key.
+    fn map_lower_bound(&self, bound: Bound<&[u8]>) -> Bound<MemTableKey> {
+        match bound {
+            Bound::Included(x) => Bound::Included(self.make_key(x, u64::MAX)),
+            Bound::Excluded(x) => Bound::Excluded(self.make_key(x, 0)),
+            Bound::Unbounded => Bound::Unbounded,
+        }
+    }
+
+    fn map_upper_bound(&self, bound: Bound<&[u8]>) -> Bound<MemTableKey> {
+        match bound {
+            Bound::Included(x) => Bound::Included(self.make_key(x, 0)),
+            Bound::Excluded(x) => Bound::Excluded(self.make_key(x, u64::MAX)),
+            Bound::Unbounded => Bound::Unbounded,
         }
     }
 
     /// Get a value by key.
     pub fn get(&self, key: &[u8]) -> Option<Bytes> {
-        self.map.get(key).map(|e| e.value().clone())
+        self.get_at_seq(key, u64::MAX)
+    }
+
+    /// Get the newest value of a key written at or before `read_seq`.
+    pub fn get_at_seq(&self, key: &[u8], read_seq: u64) -> Option<Bytes> {
+        if !self.may_contain_key(key) {
+            return None;
+        }
+        let lower = self.make_key(key, read_seq);
+        let upper = self.make_key(key, 0);
+        self.map
+            .range(lower..=upper)
+            .next()
+            .map(|e| e.value().clone())
     }
 
     /// Put a key-value pair into the mem-table.
     pub fn put(&self, key: &[u8], value: &[u8]) {
+        self.put_with_seq(key, value, 0);
+    }
+
+    /// Put a key-value pair into the mem-table, stamped with a sequence number. A write with the
+    /// same key and sequence number replaces it.
+    pub fn put_with_seq(&self, key: &[u8], value: &[u8], seq: u64) {
+        if let Some(bloom) = &self.bloom {
+            if self.whole_key_filtering {
+                bloom.add(hash_for_filter(key));
+            }
+            if let Some(extractor) = &self.prefix_extractor {
+                if extractor.in_domain(key) {
+                    bloom.add(hash_for_filter(extractor.transform(key)));
+                }
+            }
+        }
         self.map
-            .insert(Bytes::copy_from_slice(key), Bytes::copy_from_slice(value));
+            .insert(self.make_key(key, seq), Bytes::copy_from_slice(value));
+        self.max_seq.fetch_max(seq, atomic::Ordering::Relaxed);
+    }
+
+    /// The largest sequence number written to the mem-table.
+    pub fn max_seq(&self) -> u64 {
+        self.max_seq.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Check the bloom filter for a key. Returns `false` only if the key is not in the mem-table.
+    pub fn may_contain_key(&self, key: &[u8]) -> bool {
+        match &self.bloom {
+            Some(bloom) if self.whole_key_filtering => bloom.may_contain(hash_for_filter(key)),
+            _ => true,
+        }
+    }
+
+    /// Check the bloom filter for a prefix, which must be its own prefix under `extractor`.
+    /// Returns `false` only if no key in the mem-table has the prefix.
+    pub fn may_contain_prefix(&self, prefix: &[u8], extractor: &dyn PrefixExtractor) -> bool {
+        match (&self.bloom, &self.prefix_extractor) {
+            (Some(bloom), Some(own)) if own.name() == extractor.name() => {
+                bloom.may_contain(hash_for_filter(prefix))
+            }
+            _ => true,
+        }
+    }
+
+    /// Check if the mem-table contains no entry.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
     }
 
     /// Get an iterator over a range of keys.
     pub fn scan(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> MemTableIterator {
-        let (lower, upper) = (map_bound(lower), map_bound(upper));
+        self.scan_at_seq(lower, upper, u64::MAX)
+    }
+
+    /// Get an iterator over the newest values of a range of keys written at or before `read_seq`.
+    pub fn scan_at_seq(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        read_seq: u64,
+    ) -> MemTableIterator {
+        let (lower, upper) = (self.map_lower_bound(lower), self.map_upper_bound(upper));
         let mut iter = MemTableIteratorBuilder {
             map: self.map.clone(),
             iter_builder: |map| map.range((lower, upper)),
             item: (Bytes::from_static(&[]), Bytes::from_static(&[])),
+            read_seq,
         }
         .build();
-        let entry = iter.with_iter_mut(|iter| MemTableIterator::entry_to_item(iter.next()));
-        iter.with_mut(|x| *x.item = entry);
+        iter.next_visible();
         iter
     }
 
-    /// Flush the mem-table to SSTable.
+    /// Flush the newest value of every key in the mem-table to SSTable.
     pub fn flush(&self, builder: &mut SsTableBuilder) -> Result<()> {
+        let mut last_key = None;
         for entry in self.map.iter() {
-            builder.add(&entry.key()[..], &entry.value()[..]);
+            let key = &entry.key().key;
+            if last_key.as_ref() == Some(key) {
+                continue;
+            }
+            builder.add(&key[..], &entry.value()[..]);
+            last_key = Some(key.clone());
         }
         Ok(())
     }
 }
 
-type SkipMapRangeIter<'a> =
-    crossbeam_skiplist::map::Range<'a, Bytes, (Bound<Bytes>, Bound<Bytes>), Bytes, Bytes>;
+type SkipMapRangeIter<'a> = crossbeam_skiplist::map::Range<
+    'a,
+    MemTableKey,
+    (Bound<MemTableKey>, Bound<MemTableKey>),
+    MemTableKey,
+    Bytes,
+>;
 
 /// An iterator over a range of `SkipMap`.
 #[self_referencing]
 pub struct MemTableIterator {
-    map: Arc<SkipMap<Bytes, Bytes>>,
+    map: Arc<SkipMap<MemTableKey, Bytes>>,
     #[borrows(map)]
     #[not_covariant]
     iter: SkipMapRangeIter<'this>,
     item: (Bytes, Bytes),
+    read_seq: u64,
 }
 
 impl MemTableIterator {
-    fn entry_to_item(entry: Option<Entry<'_, Bytes, Bytes>>) -> (Bytes, Bytes) {
+    fn entry_to_item(entry: Option<Entry<'_, MemTableKey, Bytes>>) -> (Bytes, Bytes) {
         entry
-            .map(|x| (x.key().clone(), x.value().clone()))
+            .map(|x| (x.key().key.clone(), x.value().clone()))
             .unwrap_or_else(|| (Bytes::from_static(&[]), Bytes::from_static(&[])))
     }
+
+    /// Move to the newest version visible at `read_seq` of the next key, skipping newer versions
+    /// and the older versions of the current key.
+    fn next_visible(&mut self) {
+        let read_seq = *self.borrow_read_seq();
+        let entry = self.with_mut(|x| loop {
+            let entry = x.iter.next();
+            match &entry {
+                Some(e) if e.key().seq > read_seq || e.key().key == x.item.0 => continue,
+                _ => break MemTableIterator::entry_to_item(entry),
+            }
+        });
+        self.with_mut(|x| *x.item = entry);
+    }
 }
 
 impl StorageIterator for MemTableIterator {
@@ -100,8 +298,7 @@ impl StorageIterator for MemTableIterator {
     }
 
     fn next(&mut self) -> Result<()> {
-        let entry = self.with_iter_mut(|iter| MemTableIterator::entry_to_item(iter.next()));
-        self.with_mut(|x| *x.item = entry);
+        self.next_visible();
         Ok(())
     }
 }
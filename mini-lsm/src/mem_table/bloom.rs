@@ -0,0 +1,42 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The number of bits set for each hash.
+const NUM_PROBES: u32 = 6;
+
+/// A fixed-size bloom filter over 32-bit key hashes that can be updated concurrently.
+pub(crate) struct MemTableBloom {
+    words: Vec<AtomicU64>,
+}
+
+impl MemTableBloom {
+    /// Create a filter of about `size` bytes.
+    pub(crate) fn new(size: usize) -> Self {
+        let num_words = ((size + 7) / 8).max(1);
+        Self {
+            words: (0..num_words).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    fn probes(&self, h: u32) -> impl Iterator<Item = usize> {
+        let nbits = self.words.len() * 64;
+        let delta = h.rotate_left(15);
+        (0..NUM_PROBES).map(move |i| h.wrapping_add(delta.wrapping_mul(i)) as usize % nbits)
+    }
+
+    pub(crate) fn add(&self, h: u32) {
+        for bit in self.probes(h) {
+            // Relaxed is enough: a reader that must see the key has to be synchronized with the
+            // end of the put, e.g. by a lock or a channel of the caller, and then it sees these bits
+            // too. Only a put running at the same time as the read can be missed, and the reader
+            // may legitimately not see such a put.
+            self.words[bit / 64].fetch_or(1 << (bit % 64), Ordering::Relaxed);
+        }
+    }
+
+    /// Check whether a hash may have been added. False positives are possible, false negatives
+    /// are not.
+    pub(crate) fn may_contain(&self, h: u32) -> bool {
+        self.probes(h)
+            .all(|bit| self.words[bit / 64].load(Ordering::Relaxed) & (1 << (bit % 64)) != 0)
+    }
+}
@@ -0,0 +1,76 @@
+/// Extracts the prefix of a key, which SST filters are built over so that a scan of all keys under
+/// a prefix can skip SSTs without the prefix.
+///
+/// Keys sharing a prefix must be adjacent in the order of the comparator, and a prefix must be
+/// in the domain and be its own prefix.
+pub trait PrefixExtractor: Send + Sync {
+    /// The name of the extractor, persisted in the properties of SSTs. Filters built by an
+    /// extractor of another name are not used for prefix lookups.
+    fn name(&self) -> &str;
+
+    /// Whether the key has a prefix. Keys outside the domain are not added to prefix filters.
+    fn in_domain(&self, key: &[u8]) -> bool;
+
+    /// Get the prefix of a key in the domain.
+    fn transform<'a>(&self, key: &'a [u8]) -> &'a [u8];
+}
+
+/// Takes the first `len` bytes of keys. Shorter keys have no prefix.
+pub struct FixedPrefixExtractor {
+    len: usize,
+    name: String,
+}
+
+impl FixedPrefixExtractor {
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            name: format!("mini-lsm.FixedPrefix.{}", len),
+        }
+    }
+}
+
+impl PrefixExtractor for FixedPrefixExtractor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_domain(&self, key: &[u8]) -> bool {
+        key.len() >= self.len
+    }
+
+    fn transform<'a>(&self, key: &'a [u8]) -> &'a [u8] {
+        &key[..self.len]
+    }
+}
+
+/// Takes keys up to and including the first occurrence of a delimiter, e.g. `user:` of
+/// `user:42:name`. Keys without the delimiter have no prefix.
+pub struct DelimiterPrefixExtractor {
+    delimiter: u8,
+    name: String,
+}
+
+impl DelimiterPrefixExtractor {
+    pub fn new(delimiter: u8) -> Self {
+        Self {
+            delimiter,
+            name: format!("mini-lsm.DelimiterPrefix.{}", delimiter),
+        }
+    }
+}
+
+impl PrefixExtractor for DelimiterPrefixExtractor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_domain(&self, key: &[u8]) -> bool {
+        key.contains(&self.delimiter)
+    }
+
+    fn transform<'a>(&self, key: &'a [u8]) -> &'a [u8] {
+        let end = key.iter().position(|&x| x == self.delimiter).unwrap();
+        &key[..=end]
+    }
+}
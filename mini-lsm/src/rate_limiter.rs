@@ -0,0 +1,182 @@
+use std::time::{Duration, Instant};
+
+use parking_lot::{Condvar, Mutex};
+
+/// The priority of an I/O request. Low priority requests wait while a high priority request is
+/// waiting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoPriority {
+    /// Compaction writes.
+    Low,
+    /// Flush writes, which may hold back foreground writes.
+    High,
+}
+
+/// How often tokens are added to the bucket. The bucket holds at most one period worth of tokens.
+const REFILL_PERIOD: Duration = Duration::from_millis(100);
+/// How often an auto-tuned rate limiter adjusts its rate.
+const TUNE_PERIOD: Duration = Duration::from_secs(1);
+
+struct State {
+    bytes_per_second: u64,
+    /// The upper bound of `bytes_per_second` if the rate is auto-tuned.
+    auto_tune_max: Option<u64>,
+    available: u64,
+    last_refill: Instant,
+    num_waiting_high: usize,
+    total_bytes: [u64; 2],
+    /// Requests since the last tune, and how many of them had to wait.
+    num_requests: u64,
+    num_waited: u64,
+    last_tune: Instant,
+}
+
+impl State {
+    fn burst(&self) -> u64 {
+        // Saturates for rates so high that they are unlimited in practice.
+        (self
+            .bytes_per_second
+            .saturating_mul(REFILL_PERIOD.as_millis() as u64)
+            / 1000)
+            .max(1)
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let periods =
+            (now.duration_since(self.last_refill).as_nanos() / REFILL_PERIOD.as_nanos()) as u32;
+        if periods > 0 {
+            let burst = self.burst();
+            self.available = self
+                .available
+                .saturating_add(burst.saturating_mul(periods as u64))
+                .min(burst);
+            self.last_refill += REFILL_PERIOD * periods;
+        }
+    }
+
+    /// Raise the rate if most requests had to wait, and lower it if most did not.
+    fn tune(&mut self, now: Instant) {
+        let Some(max) = self.auto_tune_max else {
+            return;
+        };
+        if now.duration_since(self.last_tune) < TUNE_PERIOD {
+            return;
+        }
+        if let Some(percent_waited) = (self.num_waited * 100).checked_div(self.num_requests) {
+            if percent_waited >= 90 {
+                let rate = self.bytes_per_second;
+                self.bytes_per_second = rate.saturating_add((rate / 20).max(1)).min(max);
+            } else if percent_waited <= 50 {
+                let rate = self.bytes_per_second;
+                self.bytes_per_second = (rate - rate / 20).max(max / 20).max(1);
+            }
+        }
+        self.num_requests = 0;
+        self.num_waited = 0;
+        self.last_tune = now;
+    }
+}
+
+/// A token bucket that limits how many bytes per second flush and compaction write.
+///
+/// Share one rate limiter between storages through [`LsmStorageOptions`] to limit their total
+/// I/O. The rate can be changed at any time.
+///
+/// [`LsmStorageOptions`]: crate::lsm_storage::LsmStorageOptions
+pub struct RateLimiter {
+    state: Mutex<State>,
+    cv: Condvar,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter with a fixed rate.
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self::create(bytes_per_second, None)
+    }
+
+    /// Create a rate limiter whose rate follows the demand, between 5% and 100% of
+    /// `max_bytes_per_second`.
+    pub fn new_auto_tuned(max_bytes_per_second: u64) -> Self {
+        Self::create(max_bytes_per_second, Some(max_bytes_per_second))
+    }
+
+    fn create(bytes_per_second: u64, auto_tune_max: Option<u64>) -> Self {
+        assert!(bytes_per_second > 0, "rate must be positive");
+        let now = Instant::now();
+        let mut state = State {
+            bytes_per_second,
+            auto_tune_max,
+            available: 0,
+            last_refill: now,
+            num_waiting_high: 0,
+            total_bytes: [0; 2],
+            num_requests: 0,
+            num_waited: 0,
+            last_tune: now,
+        };
+        state.available = state.burst();
+        Self {
+            state: Mutex::new(state),
+            cv: Condvar::new(),
+        }
+    }
+
+    /// Get the current rate in bytes per second.
+    pub fn bytes_per_second(&self) -> u64 {
+        self.state.lock().bytes_per_second
+    }
+
+    /// Change the rate. If the rate is auto-tuned, this changes its upper bound.
+    pub fn set_bytes_per_second(&self, bytes_per_second: u64) {
+        assert!(bytes_per_second > 0, "rate must be positive");
+        let mut state = self.state.lock();
+        match &mut state.auto_tune_max {
+            Some(max) => {
+                *max = bytes_per_second;
+                state.bytes_per_second = state.bytes_per_second.min(bytes_per_second);
+            }
+            None => state.bytes_per_second = bytes_per_second,
+        }
+        state.available = state.available.min(state.burst());
+    }
+
+    /// Get the number of bytes requested so far with a priority.
+    pub fn total_bytes_through(&self, priority: IoPriority) -> u64 {
+        self.state.lock().total_bytes[priority as usize]
+    }
+
+    /// Block until `bytes` may be written.
+    pub fn request(&self, bytes: usize, priority: IoPriority) {
+        let mut state = self.state.lock();
+        let mut remaining = bytes as u64;
+        let mut waited = false;
+        if priority == IoPriority::High {
+            state.num_waiting_high += 1;
+        }
+        while remaining > 0 {
+            let now = Instant::now();
+            state.refill(now);
+            // Large requests are split, since the bucket never holds more than a burst.
+            let chunk = remaining.min(state.burst());
+            let blocked_by_high = priority == IoPriority::Low && state.num_waiting_high > 0;
+            if !blocked_by_high && state.available >= chunk {
+                state.available -= chunk;
+                remaining -= chunk;
+                continue;
+            }
+            waited = true;
+            let next_refill = state.last_refill + REFILL_PERIOD;
+            self.cv.wait_until(&mut state, next_refill);
+        }
+        if priority == IoPriority::High {
+            state.num_waiting_high -= 1;
+            self.cv.notify_all();
+        }
+        state.total_bytes[priority as usize] += bytes as u64;
+        state.num_requests += 1;
+        if waited {
+            state.num_waited += 1;
+        }
+        state.tune(Instant::now());
+    }
+}
@@ -1,23 +1,35 @@
+mod bloom;
 mod builder;
+mod index;
 mod iterator;
+mod properties;
 
-use std::fs::File;
 use std::path::Path;
 use std::sync::Arc;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
+pub(crate) use bloom::hash as hash_for_filter;
+use bloom::Bloom;
 pub use builder::SsTableBuilder;
 use bytes::{Buf, BufMut, Bytes};
+use index::IndexPartitionMeta;
 pub use iterator::SsTableIterator;
+pub use properties::{TableProperties, TablePropertiesCollector, TablePropertiesCollectorFactory};
 
-use crate::block::Block;
+use crate::block::{Block, SIZEOF_U16};
+use crate::comparator::{BytewiseComparator, Comparator};
+use crate::env::{FileBackend, FileSystem, PosixFileSystem, RandomAccessFile};
 use crate::lsm_storage::BlockCache;
+use crate::prefix::PrefixExtractor;
+use crate::rate_limiter::{IoPriority, RateLimiter};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BlockMeta {
     /// Offset of this data block.
     pub offset: usize,
-    /// The first key of the data block.
+    /// The first key of the first data block, and for the other blocks a key between the last key
+    /// of the previous block and the first key of this block, usually shorter than both. A block
+    /// holds the keys from its own `first_key` up to the `first_key` of the next block.
     pub first_key: Bytes,
 }
 
@@ -41,18 +53,29 @@ impl BlockMeta {
     }
 
     /// Decode block meta from a buffer.
-    pub fn decode_block_meta(mut buf: impl Buf) -> Vec<BlockMeta> {
+    pub fn decode_block_meta(mut buf: impl Buf) -> Result<Vec<BlockMeta>> {
         let mut block_meta = Vec::new();
         while buf.has_remaining() {
+            if buf.remaining() < SIZEOF_U32 + SIZEOF_U16 {
+                bail!("truncated block meta");
+            }
             let offset = buf.get_u32() as usize;
             let first_key_len = buf.get_u16() as usize;
+            if buf.remaining() < first_key_len {
+                bail!("truncated block meta");
+            }
             let first_key = buf.copy_to_bytes(first_key_len);
             block_meta.push(BlockMeta { offset, first_key });
         }
-        block_meta
+        Ok(block_meta)
     }
 }
 
+const SIZEOF_U32: usize = std::mem::size_of::<u32>();
+
+/// The footer holds the offsets of the index, the filter and the properties.
+const FOOTER_SIZE: u64 = 3 * SIZEOF_U32 as u64;
+
 /// A file object.
 ///
 /// Before day 4, it should look like:
@@ -77,40 +100,82 @@ impl BlockMeta {
 ///     }
 /// }
 /// ```
-pub struct FileObject(File, u64);
+pub struct FileObject(Box<dyn RandomAccessFile>);
+
+/// The size of each write when writing a file through a rate limiter.
+const RATE_LIMITED_WRITE_SIZE: usize = 64 << 10;
 
 impl FileObject {
     pub fn read(&self, offset: u64, len: u64) -> Result<Vec<u8>> {
-        use std::os::unix::fs::FileExt;
-        let mut data = vec![0; len as usize];
-        self.0.read_exact_at(&mut data[..], offset)?;
-        Ok(data)
+        Ok(self.read_bytes(offset, len)?.to_vec())
+    }
+
+    /// Read a range of the file. A memory-mapped file returns a slice of its mapping.
+    pub fn read_bytes(&self, offset: u64, len: u64) -> Result<Bytes> {
+        self.0.read_at(offset, len)
     }
 
     pub fn size(&self) -> u64 {
-        self.1
+        self.0.size()
     }
 
     /// Create a new file object (day 2) and write the file to the disk (day 4).
     pub fn create(path: &Path, data: Vec<u8>) -> Result<Self> {
-        std::fs::write(path, &data)?;
-        Ok(FileObject(
-            File::options().read(true).write(false).open(path)?,
-            data.len() as u64,
-        ))
+        Self::create_in(&PosixFileSystem, path, data, FileBackend::Pread, None)
     }
 
-    pub fn open(_path: &Path) -> Result<Self> {
-        unimplemented!()
+    /// Write and sync a file in a file system, optionally through a rate limiter, and open it
+    /// with a backend.
+    pub fn create_in(
+        fs: &dyn FileSystem,
+        path: &Path,
+        data: Vec<u8>,
+        backend: FileBackend,
+        rate_limiter: Option<(&RateLimiter, IoPriority)>,
+    ) -> Result<Self> {
+        let mut file = fs.create(path)?;
+        match rate_limiter {
+            Some((rate_limiter, priority)) => {
+                for chunk in data.chunks(RATE_LIMITED_WRITE_SIZE) {
+                    rate_limiter.request(chunk.len(), priority);
+                    file.append(chunk)?;
+                }
+            }
+            None => file.append(&data)?,
+        }
+        file.sync()?;
+        Self::open_in(fs, path, backend)
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::open_with_backend(path, FileBackend::Pread)
+    }
+
+    /// Open a file with a backend.
+    pub fn open_with_backend(path: &Path, backend: FileBackend) -> Result<Self> {
+        Self::open_in(&PosixFileSystem, path, backend)
+    }
+
+    /// Open a file in a file system with a backend.
+    pub fn open_in(fs: &dyn FileSystem, path: &Path, backend: FileBackend) -> Result<Self> {
+        Ok(FileObject(fs.open(path, backend)?))
     }
 }
 
 pub struct SsTable {
     file: FileObject,
+    /// The index of all data blocks, if the index is not partitioned.
     block_metas: Vec<BlockMeta>,
+    /// The top-level index, if the index is partitioned. Index partitions are read on demand.
+    index_partitions: Vec<IndexPartitionMeta>,
+    /// Where the data blocks end, if the index is not partitioned.
     block_meta_offset: usize,
     id: usize,
     block_cache: Option<Arc<BlockCache>>,
+    /// The bloom filter over keys and prefixes, if the SSTable has one.
+    bloom: Option<Bloom>,
+    comparator: &'static dyn Comparator,
+    properties: TableProperties,
 }
 
 impl SsTable {
@@ -119,32 +184,137 @@ impl SsTable {
         Self::open(0, None, file)
     }
 
+    #[cfg(test)]
+    pub(crate) fn block_metas_for_test(&self) -> &[BlockMeta] {
+        &self.block_metas
+    }
+
+    #[cfg(test)]
+    pub(crate) fn num_index_partitions_for_test(&self) -> usize {
+        self.index_partitions.len()
+    }
+
     /// Open SSTable from a file.
     pub fn open(id: usize, block_cache: Option<Arc<BlockCache>>, file: FileObject) -> Result<Self> {
+        Self::open_with_comparator(id, block_cache, file, &BytewiseComparator)
+    }
+
+    /// Open SSTable whose keys are ordered by `comparator` from a file.
+    pub fn open_with_comparator(
+        id: usize,
+        block_cache: Option<Arc<BlockCache>>,
+        file: FileObject,
+        comparator: &'static dyn Comparator,
+    ) -> Result<Self> {
         let len = file.size();
-        let raw_meta_offset = file.read(len - 4, 4)?;
-        let block_meta_offset = (&raw_meta_offset[..]).get_u32() as u64;
-        let raw_meta = file.read(block_meta_offset, len - 4 - block_meta_offset)?;
+        if len < FOOTER_SIZE {
+            bail!("SST file too short");
+        }
+        let raw_footer = file.read(len - FOOTER_SIZE, FOOTER_SIZE)?;
+        let mut footer = &raw_footer[..];
+        let block_meta_offset = footer.get_u32() as u64;
+        let filter_offset = footer.get_u32() as u64;
+        let properties_offset = footer.get_u32() as u64;
+        if block_meta_offset > filter_offset
+            || filter_offset > properties_offset
+            || properties_offset > len - FOOTER_SIZE
+        {
+            bail!("invalid SST footer");
+        }
+        let raw_meta = file.read(block_meta_offset, filter_offset - block_meta_offset)?;
+        let raw_properties = file.read(properties_offset, len - FOOTER_SIZE - properties_offset)?;
+        let properties = TableProperties::decode(&raw_properties)?;
+        let bloom = if properties.filter_policy.is_empty() {
+            None
+        } else {
+            let raw_filter = file.read(filter_offset, properties_offset - filter_offset)?;
+            Some(Bloom::decode(&raw_filter)?)
+        };
+        let (block_metas, index_partitions) = if properties.index_partitions > 0 {
+            let partitions = IndexPartitionMeta::decode_top_level(&raw_meta[..])?;
+            // the first partition starts at the first data block, and every partition holds at
+            // least one data block
+            let valid = partitions.first().map_or(true, |x| x.first_block_idx == 0)
+                && partitions
+                    .windows(2)
+                    .all(|x| x[0].first_block_idx < x[1].first_block_idx)
+                && partitions.last().map_or(true, |x| {
+                    (x.first_block_idx as u64) < properties.num_data_blocks
+                });
+            if !valid {
+                bail!("invalid top-level index");
+            }
+            (Vec::new(), partitions)
+        } else {
+            let block_metas = BlockMeta::decode_block_meta(&raw_meta[..])?;
+            let mut prev_offset = 0;
+            for meta in &block_metas {
+                if meta.offset < prev_offset || meta.offset as u64 > block_meta_offset {
+                    bail!("invalid block meta");
+                }
+                prev_offset = meta.offset;
+            }
+            (block_metas, Vec::new())
+        };
         Ok(Self {
             file,
-            block_metas: BlockMeta::decode_block_meta(&raw_meta[..]),
+            block_metas,
+            index_partitions,
             block_meta_offset: block_meta_offset as usize,
             id,
             block_cache,
+            bloom,
+            comparator,
+            properties,
         })
     }
 
     /// Read a block from the disk.
     pub fn read_block(&self, block_idx: usize) -> Result<Arc<Block>> {
-        let offset = self.block_metas[block_idx].offset;
-        let offset_end = self
-            .block_metas
-            .get(block_idx + 1)
-            .map_or(self.block_meta_offset, |x| x.offset);
-        let block_data = self
-            .file
-            .read(offset as u64, (offset_end - offset) as u64)?;
-        Ok(Arc::new(Block::decode(&block_data[..])))
+        let (offset, len) = if self.index_partitions.is_empty() {
+            let Some(meta) = self.block_metas.get(block_idx) else {
+                bail!("block {} does not exist", block_idx);
+            };
+            let offset = meta.offset;
+            let offset_end = self
+                .block_metas
+                .get(block_idx + 1)
+                .map_or(self.block_meta_offset, |x| x.offset);
+            (offset, offset_end - offset)
+        } else {
+            let partition_idx = self
+                .index_partitions
+                .partition_point(|x| x.first_block_idx <= block_idx)
+                - 1;
+            let partition = self.read_index_partition(partition_idx)?;
+            let entry_idx = block_idx - self.index_partitions[partition_idx].first_block_idx;
+            if entry_idx >= partition.num_entries() {
+                bail!("block {} is missing from the index", block_idx);
+            }
+            let mut handle = partition.entry(entry_idx).1;
+            if handle.len() != 2 * SIZEOF_U32 {
+                bail!("invalid block handle");
+            }
+            (handle.get_u32() as usize, handle.get_u32() as usize)
+        };
+        let block_data = self.file.read_bytes(offset as u64, len as u64)?;
+        Ok(Arc::new(Block::decode_bytes(block_data)?))
+    }
+
+    /// Read a partition of a two-level index, with block cache. Partitions are cached after the
+    /// data blocks of the SSTable.
+    fn read_index_partition(&self, partition_idx: usize) -> Result<Arc<Block>> {
+        let read = || -> Result<Arc<Block>> {
+            let meta = &self.index_partitions[partition_idx];
+            let data = self.file.read_bytes(meta.offset as u64, meta.len as u64)?;
+            Ok(Arc::new(Block::decode_bytes(data)?))
+        };
+        match self.block_cache {
+            Some(ref block_cache) => block_cache
+                .try_get_with((self.id, self.num_of_blocks() + partition_idx), read)
+                .map_err(|e| anyhow!("{}", e)),
+            None => read(),
+        }
     }
 
     /// Read a block from disk, with block cache.
@@ -159,16 +329,97 @@ impl SsTable {
         }
     }
 
-    /// Find the block that may contain `key`.
-    pub fn find_block_idx(&self, key: &[u8]) -> usize {
-        self.block_metas
-            .partition_point(|meta| meta.first_key <= key)
-            .saturating_sub(1)
+    /// Find the block that may contain `key`, which is the last block whose index key is not greater
+    /// than `key`. If `key` falls between two blocks, all keys of the returned block may be less
+    /// than `key`, and a seek continues with the next block.
+    pub fn find_block_idx(&self, key: &[u8]) -> Result<usize> {
+        if self.index_partitions.is_empty() {
+            return Ok(self
+                .block_metas
+                .partition_point(|meta| self.comparator.compare(&meta.first_key, key).is_le())
+                .saturating_sub(1));
+        }
+        let partition_idx = self
+            .index_partitions
+            .partition_point(|meta| self.comparator.compare(&meta.first_key, key).is_le())
+            .saturating_sub(1);
+        let partition = self.read_index_partition(partition_idx)?;
+        // binary search for the last data block whose index key is at or before the key
+        let (mut low, mut high) = (0, partition.num_entries());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.comparator.compare(partition.entry(mid).0, key).is_le() {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(self.index_partitions[partition_idx].first_block_idx + low.saturating_sub(1))
+    }
+
+    /// Check the bloom filter for a key. Returns `false` only if the key is not in the SSTable.
+    pub fn may_contain_key(&self, key: &[u8]) -> bool {
+        match &self.bloom {
+            Some(filter) if self.properties.whole_key_filtering => {
+                filter.may_contain(bloom::hash(key))
+            }
+            _ => true,
+        }
+    }
+
+    /// Check the bloom filter for a prefix, which must be its own prefix under `extractor`.
+    /// Returns `false` only if no key in the SSTable has the prefix.
+    pub fn may_contain_prefix(&self, prefix: &[u8], extractor: &dyn PrefixExtractor) -> bool {
+        match &self.bloom {
+            Some(filter) if self.properties.prefix_extractor == extractor.name() => {
+                filter.may_contain(bloom::hash(prefix))
+            }
+            _ => true,
+        }
     }
 
     /// Get number of data blocks.
     pub fn num_of_blocks(&self) -> usize {
-        self.block_metas.len()
+        if self.index_partitions.is_empty() {
+            self.block_metas.len()
+        } else {
+            self.properties.num_data_blocks as usize
+        }
+    }
+
+    /// Get the comparator that orders the keys of this SSTable.
+    pub fn comparator(&self) -> &'static dyn Comparator {
+        self.comparator
+    }
+
+    /// Get the smallest key in this SSTable.
+    pub fn first_key(&self) -> &[u8] {
+        &self.properties.smallest_key
+    }
+
+    /// Get the largest key in this SSTable.
+    pub fn last_key(&self) -> &[u8] {
+        &self.properties.largest_key
+    }
+
+    /// Get the statistics stored with this SSTable.
+    pub fn properties(&self) -> &TableProperties {
+        &self.properties
+    }
+
+    /// Get when the newest data in this SSTable was written, in milliseconds. Zero if unknown.
+    pub fn creation_time(&self) -> u64 {
+        self.properties.creation_time
+    }
+
+    /// Get the size of the SSTable file in bytes.
+    pub fn table_size(&self) -> u64 {
+        self.file.size()
+    }
+
+    /// Get the ID of this SSTable.
+    pub fn sst_id(&self) -> usize {
+        self.id
     }
 }
 
@@ -0,0 +1,98 @@
+use anyhow::{bail, Result};
+use bytes::{BufMut, Bytes};
+
+/// The filter policy of SSTables with a bloom filter, stored in their properties.
+pub(crate) const FILTER_POLICY: &str = "mini-lsm.BloomFilter";
+
+/// A bloom filter over 32-bit key hashes, probed by double hashing.
+pub(crate) struct Bloom {
+    filter: Bytes,
+    k: u8,
+}
+
+/// Hash a key or a prefix for a bloom filter.
+pub(crate) fn hash(key: &[u8]) -> u32 {
+    const SEED: u32 = 0xbc9f1d34;
+    const M: u32 = 0xc6a4a793;
+    let mut h = SEED ^ (key.len() as u32).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(4);
+    for chunk in &mut chunks {
+        h = h.wrapping_add(u32::from_le_bytes(chunk.try_into().unwrap()));
+        h = h.wrapping_mul(M);
+        h ^= h >> 16;
+    }
+    let rest = chunks.remainder();
+    if rest.len() == 3 {
+        h = h.wrapping_add((rest[2] as u32) << 16);
+    }
+    if rest.len() >= 2 {
+        h = h.wrapping_add((rest[1] as u32) << 8);
+    }
+    if !rest.is_empty() {
+        h = h.wrapping_add(rest[0] as u32);
+        h = h.wrapping_mul(M);
+        h ^= h >> 24;
+    }
+    h
+}
+
+impl Bloom {
+    /// Build a filter with about `bits_per_key` bits for each hash.
+    pub(crate) fn build_from_key_hashes(hashes: &[u32], bits_per_key: usize) -> Self {
+        // k = ln(2) * bits_per_key minimizes the false positive rate
+        let k = ((bits_per_key as f64 * 0.69) as u8).clamp(1, 30);
+        let nbits = (hashes.len() * bits_per_key).max(64);
+        let nbytes = (nbits + 7) / 8;
+        let nbits = nbytes * 8;
+        let mut filter = vec![0u8; nbytes];
+        for &h in hashes {
+            let mut h = h;
+            let delta = h.rotate_left(15);
+            for _ in 0..k {
+                let bit = h as usize % nbits;
+                filter[bit / 8] |= 1 << (bit % 8);
+                h = h.wrapping_add(delta);
+            }
+        }
+        Self {
+            filter: filter.into(),
+            k,
+        }
+    }
+
+    /// Check whether a hash may have been added. False positives are possible, false negatives
+    /// are not.
+    pub(crate) fn may_contain(&self, h: u32) -> bool {
+        let nbits = self.filter.len() * 8;
+        let mut h = h;
+        let delta = h.rotate_left(15);
+        for _ in 0..self.k {
+            let bit = h as usize % nbits;
+            if self.filter[bit / 8] & (1 << (bit % 8)) == 0 {
+                return false;
+            }
+            h = h.wrapping_add(delta);
+        }
+        true
+    }
+
+    /// Encode the filter as its bits followed by the number of probes.
+    pub(crate) fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.filter);
+        buf.put_u8(self.k);
+    }
+
+    /// Decode a filter written by [`Bloom::encode`].
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self> {
+        let Some((&k, filter)) = buf.split_last() else {
+            bail!("empty bloom filter");
+        };
+        if filter.is_empty() || k == 0 || k > 30 {
+            bail!("invalid bloom filter");
+        }
+        Ok(Self {
+            filter: Bytes::copy_from_slice(filter),
+            k,
+        })
+    }
+}
@@ -4,46 +4,163 @@ use std::sync::Arc;
 use anyhow::Result;
 use bytes::BufMut;
 
-use super::{BlockMeta, FileObject, SsTable};
+use super::bloom::{self, Bloom};
+use super::{
+    BlockMeta, FileObject, IndexPartitionMeta, SsTable, TableProperties, TablePropertiesCollector,
+};
 use crate::block::BlockBuilder;
+use crate::comparator::{BytewiseComparator, Comparator};
+use crate::env::{FileBackend, FileSystem, PosixFileSystem};
 use crate::lsm_storage::BlockCache;
+use crate::prefix::PrefixExtractor;
+use crate::rate_limiter::{IoPriority, RateLimiter};
 
 /// Builds an SSTable from key-value pairs.
 pub struct SsTableBuilder {
     builder: BlockBuilder,
+    /// The index key of the current block.
     first_key: Vec<u8>,
+    /// The last key added.
+    last_key: Vec<u8>,
     data: Vec<u8>,
     pub(super) meta: Vec<BlockMeta>,
     block_size: usize,
+    comparator: &'static dyn Comparator,
+    properties: TableProperties,
+    collectors: Vec<Box<dyn TablePropertiesCollector>>,
+    index_partition_size: Option<usize>,
+    rate_limiter: Option<(Arc<RateLimiter>, IoPriority)>,
+    /// Bits per key of the bloom filter, or zero if the SSTable has no filter.
+    filter_bits_per_key: usize,
+    prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    /// Hashes of the keys and the prefixes added to the filter.
+    key_hashes: Vec<u32>,
+    last_prefix_hash: Option<u32>,
+    file_system: Arc<dyn FileSystem>,
+    file_backend: FileBackend,
 }
 
 impl SsTableBuilder {
     /// Create a builder based on target block size.
     pub fn new(block_size: usize) -> Self {
+        Self::new_with_comparator(block_size, &BytewiseComparator)
+    }
+
+    /// Create a builder for keys ordered by `comparator`. Keys must be added in that order.
+    pub fn new_with_comparator(block_size: usize, comparator: &'static dyn Comparator) -> Self {
         Self {
             data: Vec::new(),
             meta: Vec::new(),
             first_key: Vec::new(),
+            last_key: Vec::new(),
             block_size,
             builder: BlockBuilder::new(block_size),
+            comparator,
+            properties: TableProperties::default(),
+            collectors: Vec::new(),
+            index_partition_size: None,
+            rate_limiter: None,
+            filter_bits_per_key: 0,
+            prefix_extractor: None,
+            key_hashes: Vec::new(),
+            last_prefix_hash: None,
+            file_system: Arc::new(PosixFileSystem),
+            file_backend: FileBackend::default(),
         }
     }
 
+    /// Set when the newest data in the SSTable was written, in milliseconds.
+    pub fn set_creation_time(&mut self, creation_time: u64) {
+        self.properties.creation_time = creation_time;
+    }
+
+    /// Set the level the SSTable is written for.
+    pub fn set_level(&mut self, level: u32) {
+        self.properties.level = level;
+    }
+
+    /// Build a two-level index, whose partitions are about `partition_size` bytes and are only
+    /// read when needed.
+    pub fn set_index_partition_size(&mut self, partition_size: usize) {
+        self.index_partition_size = Some(partition_size);
+    }
+
+    /// Add a collector that sees every key-value pair and adds user properties to the SSTable.
+    pub fn add_properties_collector(&mut self, collector: Box<dyn TablePropertiesCollector>) {
+        self.collectors.push(collector);
+    }
+
+    /// Write the SSTable file through a rate limiter.
+    pub fn set_rate_limiter(&mut self, rate_limiter: Arc<RateLimiter>, priority: IoPriority) {
+        self.rate_limiter = Some((rate_limiter, priority));
+    }
+
+    /// Write the SSTable to a file system instead of the one of the OS.
+    pub fn set_file_system(&mut self, file_system: Arc<dyn FileSystem>) {
+        self.file_system = file_system;
+    }
+
+    /// Read the SSTable through a file backend once it is built.
+    pub fn set_file_backend(&mut self, file_backend: FileBackend) {
+        self.file_backend = file_backend;
+    }
+
+    /// Build a bloom filter with about `bits_per_key` bits for each key. Whole keys are added if
+    /// `whole_key_filtering` is set, and prefixes if a prefix extractor is set.
+    pub fn set_bloom_filter(&mut self, bits_per_key: usize, whole_key_filtering: bool) {
+        assert!(
+            bits_per_key > 0,
+            "a bloom filter needs at least one bit per key"
+        );
+        self.filter_bits_per_key = bits_per_key;
+        self.properties.whole_key_filtering = whole_key_filtering;
+    }
+
+    /// Add the prefixes of keys to the bloom filter.
+    pub fn set_prefix_extractor(&mut self, prefix_extractor: Arc<dyn PrefixExtractor>) {
+        self.properties.prefix_extractor = prefix_extractor.name().to_string();
+        self.prefix_extractor = Some(prefix_extractor);
+    }
+
     /// Adds a key-value pair to SSTable
     pub fn add(&mut self, key: &[u8], value: &[u8]) {
+        self.properties.add(key, value);
+        for collector in &mut self.collectors {
+            collector.add(key, value);
+        }
+        if self.filter_bits_per_key > 0 {
+            self.add_to_filter(key);
+        }
         if self.first_key.is_empty() {
             self.first_key = key.to_vec();
         }
 
-        if self.builder.add(key, value) {
-            return;
+        if !self.builder.add(key, value) {
+            // create a new block builder and append block data
+            self.finish_block();
+
+            // add the key-value pair to the next block
+            assert!(self.builder.add(key, value));
+            self.first_key = self.comparator.find_shortest_separator(&self.last_key, key);
         }
-        // create a new block builder and append block data
-        self.finish_block();
+        self.last_key.clear();
+        self.last_key.extend_from_slice(key);
+    }
 
-        // add the key-value pair to the next block
-        assert!(self.builder.add(key, value));
-        self.first_key = key.to_vec();
+    fn add_to_filter(&mut self, key: &[u8]) {
+        if self.properties.whole_key_filtering {
+            self.key_hashes.push(bloom::hash(key));
+        }
+        if let Some(extractor) = &self.prefix_extractor {
+            if extractor.in_domain(key) {
+                // keys with the same prefix are adjacent
+                let h = bloom::hash(extractor.transform(key));
+                if self.last_prefix_hash != Some(h) {
+                    self.key_hashes.push(h);
+                    self.last_prefix_hash = Some(h);
+                }
+            }
+        }
     }
 
     /// Get the estimated size of the SSTable.
@@ -71,16 +188,61 @@ impl SsTableBuilder {
     ) -> Result<SsTable> {
         self.finish_block();
         let mut buf = self.data;
+        let data_end = buf.len();
+        self.properties.num_data_blocks = self.meta.len() as u64;
+        let mut index_partitions = Vec::new();
+        if let Some(partition_size) = self.index_partition_size {
+            index_partitions = IndexPartitionMeta::build_partitions(
+                &self.meta,
+                data_end,
+                partition_size,
+                &mut buf,
+            );
+            self.properties.index_partitions = index_partitions.len() as u64;
+            // only the top-level index is kept in memory
+            self.meta.clear();
+        }
         let meta_offset = buf.len();
-        BlockMeta::encode_block_meta(&self.meta, &mut buf);
+        if index_partitions.is_empty() {
+            BlockMeta::encode_block_meta(&self.meta, &mut buf);
+        } else {
+            IndexPartitionMeta::encode_top_level(&index_partitions, &mut buf);
+        }
+        let filter_offset = buf.len();
+        let mut bloom = None;
+        if self.filter_bits_per_key > 0 {
+            let filter = Bloom::build_from_key_hashes(&self.key_hashes, self.filter_bits_per_key);
+            filter.encode(&mut buf);
+            self.properties.filter_policy = bloom::FILTER_POLICY.to_string();
+            bloom = Some(filter);
+        }
+        let properties_offset = buf.len();
+        for collector in &mut self.collectors {
+            self.properties.add_user_properties(collector.finish())?;
+        }
+        self.properties.encode(&mut buf);
         buf.put_u32(meta_offset as u32);
-        let file = FileObject::create(path.as_ref(), buf)?;
+        buf.put_u32(filter_offset as u32);
+        buf.put_u32(properties_offset as u32);
+        let file = FileObject::create_in(
+            self.file_system.as_ref(),
+            path.as_ref(),
+            buf,
+            self.file_backend,
+            self.rate_limiter
+                .as_ref()
+                .map(|(x, priority)| (x.as_ref(), *priority)),
+        )?;
         Ok(SsTable {
             id,
             file,
             block_metas: self.meta,
-            block_meta_offset: meta_offset,
+            index_partitions,
+            block_meta_offset: data_end,
             block_cache,
+            bloom,
+            comparator: self.comparator,
+            properties: self.properties,
         })
     }
 
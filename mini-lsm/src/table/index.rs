@@ -0,0 +1,113 @@
+use anyhow::{bail, Result};
+use bytes::{Buf, BufMut, Bytes};
+
+use super::{BlockMeta, SIZEOF_U32};
+use crate::block::{BlockBuilder, SIZEOF_U16};
+
+/// Where an index partition of a two-level index is stored. Each partition is a block that maps
+/// the index key of a data block (see [`BlockMeta::first_key`]) to the offset and the length of
+/// the data block, and the metas of all partitions form the top-level index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct IndexPartitionMeta {
+    pub(crate) offset: usize,
+    pub(crate) len: usize,
+    /// The index of the first data block in the partition.
+    pub(crate) first_block_idx: usize,
+    /// The index key of the first data block in the partition.
+    pub(crate) first_key: Bytes,
+}
+
+impl IndexPartitionMeta {
+    /// Write index partitions of about `partition_size` bytes for `block_metas` to `buf`, and return
+    /// the top-level index. `data_end` is where the last data block ends.
+    pub(crate) fn build_partitions(
+        block_metas: &[BlockMeta],
+        data_end: usize,
+        partition_size: usize,
+        buf: &mut Vec<u8>,
+    ) -> Vec<IndexPartitionMeta> {
+        let mut partitions = Vec::new();
+        let mut builder = BlockBuilder::new(partition_size);
+        let mut first_block_idx = 0;
+        for (idx, meta) in block_metas.iter().enumerate() {
+            let end = block_metas.get(idx + 1).map_or(data_end, |x| x.offset);
+            let mut handle = Vec::with_capacity(8);
+            handle.put_u32(meta.offset as u32);
+            handle.put_u32((end - meta.offset) as u32);
+            if builder.add(&meta.first_key, &handle) {
+                continue;
+            }
+            let full = std::mem::replace(&mut builder, BlockBuilder::new(partition_size));
+            partitions.push(Self::write_partition(
+                full,
+                &block_metas[first_block_idx],
+                first_block_idx,
+                buf,
+            ));
+            first_block_idx = idx;
+            assert!(builder.add(&meta.first_key, &handle));
+        }
+        if !builder.is_empty() {
+            partitions.push(Self::write_partition(
+                builder,
+                &block_metas[first_block_idx],
+                first_block_idx,
+                buf,
+            ));
+        }
+        partitions
+    }
+
+    fn write_partition(
+        builder: BlockBuilder,
+        first_block: &BlockMeta,
+        first_block_idx: usize,
+        buf: &mut Vec<u8>,
+    ) -> Self {
+        let encoded = builder.build().encode();
+        let meta = Self {
+            offset: buf.len(),
+            len: encoded.len(),
+            first_block_idx,
+            first_key: first_block.first_key.clone(),
+        };
+        buf.extend(encoded);
+        meta
+    }
+
+    /// Encode the top-level index to a buffer.
+    pub(crate) fn encode_top_level(partitions: &[IndexPartitionMeta], buf: &mut Vec<u8>) {
+        for meta in partitions {
+            buf.put_u32(meta.offset as u32);
+            buf.put_u32(meta.len as u32);
+            buf.put_u32(meta.first_block_idx as u32);
+            buf.put_u16(meta.first_key.len() as u16);
+            buf.put_slice(&meta.first_key);
+        }
+    }
+
+    /// Decode the top-level index from a buffer.
+    pub(crate) fn decode_top_level(mut buf: impl Buf) -> Result<Vec<IndexPartitionMeta>> {
+        let mut partitions = Vec::new();
+        while buf.has_remaining() {
+            if buf.remaining() < 3 * SIZEOF_U32 + SIZEOF_U16 {
+                bail!("truncated top-level index");
+            }
+            let offset = buf.get_u32() as usize;
+            let len = buf.get_u32() as usize;
+            let first_block_idx = buf.get_u32() as usize;
+            let first_key_len = buf.get_u16() as usize;
+            if buf.remaining() < first_key_len {
+                bail!("truncated top-level index");
+            }
+            let first_key = buf.copy_to_bytes(first_key_len);
+            partitions.push(Self {
+                offset,
+                len,
+                first_block_idx,
+                first_key,
+            });
+        }
+        Ok(partitions)
+    }
+}
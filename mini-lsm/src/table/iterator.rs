@@ -17,7 +17,10 @@ impl SsTableIterator {
     fn seek_to_first_inner(table: &Arc<SsTable>) -> Result<(usize, BlockIterator)> {
         Ok((
             0,
-            BlockIterator::create_and_seek_to_first(table.read_block_cached(0)?),
+            BlockIterator::create_and_seek_to_first_with_comparator(
+                table.read_block_cached(0)?,
+                table.comparator(),
+            ),
         ))
     }
 
@@ -41,14 +44,19 @@ impl SsTableIterator {
     }
 
     fn seek_to_key_inner(table: &Arc<SsTable>, key: &[u8]) -> Result<(usize, BlockIterator)> {
-        let mut blk_idx = table.find_block_idx(key);
-        let mut blk_iter =
-            BlockIterator::create_and_seek_to_key(table.read_block_cached(blk_idx)?, key);
+        let mut blk_idx = table.find_block_idx(key)?;
+        let mut blk_iter = BlockIterator::create_and_seek_to_key_with_comparator(
+            table.read_block_cached(blk_idx)?,
+            key,
+            table.comparator(),
+        );
         if !blk_iter.is_valid() {
             blk_idx += 1;
             if blk_idx < table.num_of_blocks() {
-                blk_iter =
-                    BlockIterator::create_and_seek_to_first(table.read_block_cached(blk_idx)?);
+                blk_iter = BlockIterator::create_and_seek_to_first_with_comparator(
+                    table.read_block_cached(blk_idx)?,
+                    table.comparator(),
+                );
             }
         }
         Ok((blk_idx, blk_iter))
@@ -92,8 +100,9 @@ impl StorageIterator for SsTableIterator {
         if !self.blk_iter.is_valid() {
             self.blk_idx += 1;
             if self.blk_idx < self.table.num_of_blocks() {
-                self.blk_iter = BlockIterator::create_and_seek_to_first(
+                self.blk_iter = BlockIterator::create_and_seek_to_first_with_comparator(
                     self.table.read_block_cached(self.blk_idx)?,
+                    self.table.comparator(),
                 );
             }
         }
@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::ttl::decode_value;
+
+/// Names of built-in properties start with this prefix.
+const BUILTIN_PREFIX: &str = "mini-lsm.";
+const NUM_ENTRIES: &str = "mini-lsm.num.entries";
+const NUM_DELETIONS: &str = "mini-lsm.num.deletions";
+const EARLIEST_EXPIRE_AT: &str = "mini-lsm.earliest.expire.at";
+const RAW_KEY_SIZE: &str = "mini-lsm.raw.key.size";
+const RAW_VALUE_SIZE: &str = "mini-lsm.raw.value.size";
+const SMALLEST_KEY: &str = "mini-lsm.smallest.key";
+const LARGEST_KEY: &str = "mini-lsm.largest.key";
+const CREATION_TIME: &str = "mini-lsm.creation.time";
+const LEVEL: &str = "mini-lsm.level";
+const COMPRESSION: &str = "mini-lsm.compression";
+const FILTER_POLICY: &str = "mini-lsm.filter.policy";
+const NUM_DATA_BLOCKS: &str = "mini-lsm.num.data.blocks";
+const INDEX_PARTITIONS: &str = "mini-lsm.index.partitions";
+const WHOLE_KEY_FILTERING: &str = "mini-lsm.whole.key.filtering";
+const PREFIX_EXTRACTOR: &str = "mini-lsm.prefix.extractor";
+
+/// Statistics of an SSTable, stored in the properties section of its file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableProperties {
+    /// The number of entries, including tombstones.
+    pub num_entries: u64,
+    /// The number of tombstones, which are entries with an empty value.
+    pub num_deletions: u64,
+    /// The earliest expiry of the values with a TTL, in milliseconds, or `u64::MAX` if no value
+    /// has one.
+    pub earliest_expire_at: u64,
+    /// The total size of all keys before encoding.
+    pub raw_key_size: u64,
+    /// The total size of all values before encoding.
+    pub raw_value_size: u64,
+    pub smallest_key: Bytes,
+    pub largest_key: Bytes,
+    /// When the newest data in the SSTable was written, in milliseconds. Zero if unknown.
+    pub creation_time: u64,
+    /// The level the SSTable was written for: 0 for flushed SSTs and L0, otherwise the position
+    /// of its sorted run below L0, counting from 1, when it was written.
+    pub level: u32,
+    /// The compression of the data blocks.
+    pub compression: String,
+    /// The name of the filter policy, or an empty string if the SSTable has no filter.
+    pub filter_policy: String,
+    /// Whether whole keys were added to the filter.
+    pub whole_key_filtering: bool,
+    /// The name of the prefix extractor whose prefixes were added to the filter, or an empty
+    /// string if there is none.
+    pub prefix_extractor: String,
+    pub num_data_blocks: u64,
+    /// The number of partitions of a two-level index, or zero if the index is not partitioned.
+    pub index_partitions: u64,
+    /// Properties added by [`TablePropertiesCollector`]s.
+    pub user_properties: BTreeMap<String, Bytes>,
+}
+
+impl Default for TableProperties {
+    fn default() -> Self {
+        Self {
+            num_entries: 0,
+            num_deletions: 0,
+            earliest_expire_at: u64::MAX,
+            raw_key_size: 0,
+            raw_value_size: 0,
+            smallest_key: Bytes::new(),
+            largest_key: Bytes::new(),
+            creation_time: 0,
+            level: 0,
+            compression: "none".to_string(),
+            filter_policy: String::new(),
+            whole_key_filtering: false,
+            prefix_extractor: String::new(),
+            num_data_blocks: 0,
+            index_partitions: 0,
+            user_properties: BTreeMap::new(),
+        }
+    }
+}
+
+/// Collects user-defined properties of an SSTable while it is built.
+pub trait TablePropertiesCollector: Send {
+    /// Called for every key-value pair added to the SSTable, in order. Values are passed as
+    /// stored, which for the storage engine includes a one-byte TTL header. Tombstones are empty.
+    fn add(&mut self, key: &[u8], value: &[u8]);
+
+    /// Called once the SSTable is complete. Returns the properties to store. Names must not start
+    /// with `mini-lsm.`, or building the SSTable fails.
+    fn finish(&mut self) -> Vec<(String, Bytes)>;
+}
+
+/// Creates a [`TablePropertiesCollector`] for every SSTable written by flush and compaction.
+pub trait TablePropertiesCollectorFactory: Send + Sync {
+    fn create(&self) -> Box<dyn TablePropertiesCollector>;
+}
+
+impl TableProperties {
+    pub(super) fn add(&mut self, key: &[u8], value: &[u8]) {
+        if self.num_entries == 0 {
+            self.smallest_key = Bytes::copy_from_slice(key);
+        }
+        self.largest_key = Bytes::copy_from_slice(key);
+        self.num_entries += 1;
+        if value.is_empty() {
+            self.num_deletions += 1;
+        } else if let Ok((_, Some(expire_at))) = decode_value(value) {
+            self.earliest_expire_at = self.earliest_expire_at.min(expire_at);
+        }
+        self.raw_key_size += key.len() as u64;
+        self.raw_value_size += value.len() as u64;
+    }
+
+    /// Add the properties returned by a collector. Names of built-in properties are rejected, as
+    /// they would replace the built-in values when the SSTable is opened.
+    pub(super) fn add_user_properties(&mut self, properties: Vec<(String, Bytes)>) -> Result<()> {
+        for (name, value) in properties {
+            if name.starts_with(BUILTIN_PREFIX) {
+                bail!("user table property {} uses the reserved prefix", name);
+            }
+            self.user_properties.insert(name, value);
+        }
+        Ok(())
+    }
+
+    /// The percentage of entries that are tombstones.
+    pub fn deletion_percent(&self) -> u64 {
+        (self.num_deletions * 100)
+            .checked_div(self.num_entries)
+            .unwrap_or(0)
+    }
+
+    /// Encode the properties as a list of named values.
+    pub(super) fn encode(&self, buf: &mut Vec<u8>) {
+        put_property(buf, NUM_ENTRIES, &self.num_entries.to_be_bytes());
+        put_property(buf, NUM_DELETIONS, &self.num_deletions.to_be_bytes());
+        put_property(
+            buf,
+            EARLIEST_EXPIRE_AT,
+            &self.earliest_expire_at.to_be_bytes(),
+        );
+        put_property(buf, RAW_KEY_SIZE, &self.raw_key_size.to_be_bytes());
+        put_property(buf, RAW_VALUE_SIZE, &self.raw_value_size.to_be_bytes());
+        put_property(buf, SMALLEST_KEY, &self.smallest_key);
+        put_property(buf, LARGEST_KEY, &self.largest_key);
+        put_property(buf, CREATION_TIME, &self.creation_time.to_be_bytes());
+        put_property(buf, LEVEL, &self.level.to_be_bytes());
+        put_property(buf, COMPRESSION, self.compression.as_bytes());
+        put_property(buf, FILTER_POLICY, self.filter_policy.as_bytes());
+        put_property(
+            buf,
+            WHOLE_KEY_FILTERING,
+            &(self.whole_key_filtering as u64).to_be_bytes(),
+        );
+        put_property(buf, PREFIX_EXTRACTOR, self.prefix_extractor.as_bytes());
+        put_property(buf, NUM_DATA_BLOCKS, &self.num_data_blocks.to_be_bytes());
+        put_property(buf, INDEX_PARTITIONS, &self.index_partitions.to_be_bytes());
+        for (name, value) in &self.user_properties {
+            put_property(buf, name, value);
+        }
+    }
+
+    /// Decode the properties. Unknown built-in names are skipped.
+    pub(super) fn decode(mut buf: &[u8]) -> Result<Self> {
+        let mut properties = Self::default();
+        while buf.has_remaining() {
+            let name = std::str::from_utf8(get_slice(&mut buf, 2)?)?;
+            let value = get_slice(&mut buf, 4)?;
+            match name {
+                NUM_ENTRIES => properties.num_entries = get_u64(value)?,
+                NUM_DELETIONS => properties.num_deletions = get_u64(value)?,
+                EARLIEST_EXPIRE_AT => properties.earliest_expire_at = get_u64(value)?,
+                RAW_KEY_SIZE => properties.raw_key_size = get_u64(value)?,
+                RAW_VALUE_SIZE => properties.raw_value_size = get_u64(value)?,
+                SMALLEST_KEY => properties.smallest_key = Bytes::copy_from_slice(value),
+                LARGEST_KEY => properties.largest_key = Bytes::copy_from_slice(value),
+                CREATION_TIME => properties.creation_time = get_u64(value)?,
+                LEVEL => properties.level = get_u64(value)? as u32,
+                COMPRESSION => properties.compression = String::from_utf8(value.to_vec())?,
+                FILTER_POLICY => properties.filter_policy = String::from_utf8(value.to_vec())?,
+                WHOLE_KEY_FILTERING => properties.whole_key_filtering = get_u64(value)? != 0,
+                PREFIX_EXTRACTOR => {
+                    properties.prefix_extractor = String::from_utf8(value.to_vec())?
+                }
+                NUM_DATA_BLOCKS => properties.num_data_blocks = get_u64(value)?,
+                INDEX_PARTITIONS => properties.index_partitions = get_u64(value)?,
+                x if x.starts_with(BUILTIN_PREFIX) => {}
+                _ => {
+                    properties
+                        .user_properties
+                        .insert(name.to_string(), Bytes::copy_from_slice(value));
+                }
+            }
+        }
+        Ok(properties)
+    }
+}
+
+fn put_property(buf: &mut Vec<u8>, name: &str, value: &[u8]) {
+    buf.put_u16(name.len() as u16);
+    buf.put_slice(name.as_bytes());
+    buf.put_u32(value.len() as u32);
+    buf.put_slice(value);
+}
+
+/// Read a slice prefixed by its length, which takes `len_size` bytes.
+fn get_slice<'a>(buf: &mut &'a [u8], len_size: usize) -> Result<&'a [u8]> {
+    if buf.remaining() < len_size {
+        bail!("truncated table properties");
+    }
+    let len = buf.get_uint(len_size) as usize;
+    if buf.remaining() < len {
+        bail!("truncated table properties");
+    }
+    let (slice, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(slice)
+}
+
+/// Decode a big-endian integer of up to 8 bytes.
+fn get_u64(value: &[u8]) -> Result<u64> {
+    if value.is_empty() || value.len() > 8 {
+        bail!("invalid table property");
+    }
+    Ok((&value[..]).get_uint(value.len()))
+}
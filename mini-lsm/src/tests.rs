@@ -1 +1,27 @@
+pub mod block_iterator_tests;
+pub mod column_family_tests;
+pub mod compact_range_tests;
+pub mod compaction_filter_tests;
+pub mod comparator_tests;
+pub mod concat_iterator_tests;
+pub mod corruption_tests;
 pub mod day4_tests;
+pub mod fifo_compaction_tests;
+pub mod file_backend_tests;
+pub mod harness;
+pub mod leveled_compaction_tests;
+pub mod memtable_bloom_tests;
+pub mod memtable_seq_tests;
+pub mod merge_iterator_model_tests;
+pub mod model_tests;
+pub mod prefix_tests;
+pub mod rate_limiter_tests;
+pub mod recovery_tests;
+pub mod sst_durability_tests;
+pub mod sst_index_tests;
+pub mod subcompaction_tests;
+pub mod table_properties_tests;
+pub mod tiered_compaction_tests;
+pub mod tombstone_compaction_tests;
+pub mod ttl_tests;
+pub mod write_stall_tests;
@@ -0,0 +1,19 @@
+use std::sync::Arc;
+
+use super::harness::{generate_block, key_of, num_of_keys};
+use crate::block::BlockIterator;
+
+#[test]
+fn test_block_iterator_borrows_block() {
+    let block = Arc::new(generate_block());
+    let mut iter = BlockIterator::create_and_seek_to_key(block.clone(), &key_of(3));
+    // the key and the value point into the block data
+    let (key, value) = block.entry(3);
+    assert_eq!(iter.key().as_ptr(), key.as_ptr());
+    assert_eq!(iter.value().as_ptr(), value.as_ptr());
+    iter.seek_to_key(b"key_999");
+    assert!(!iter.is_valid());
+    let mut iter = BlockIterator::create_and_seek_to_key(block, &key_of(num_of_keys() - 1));
+    iter.next();
+    assert!(!iter.is_valid());
+}
@@ -0,0 +1,196 @@
+use std::ops::Bound;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use super::harness::{check_iter_result, open_in_memory, open_in_memory_with_options};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{ColumnFamilyOptions, LsmStorageOptions, DEFAULT_CF};
+use crate::ttl::MockClock;
+use crate::write_batch::WriteBatch;
+
+#[test]
+fn test_column_family_isolation() {
+    let storage = open_in_memory();
+    storage
+        .create_column_family(
+            "index",
+            ColumnFamilyOptions {
+                block_size: 128,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(storage.list_column_families(), vec![DEFAULT_CF, "index"]);
+    storage.put(b"1", b"233").unwrap();
+    storage.put_cf("index", b"1", b"2333").unwrap();
+    storage.put_cf("index", b"2", b"23333").unwrap();
+    storage.sync().unwrap();
+    storage.delete_cf("index", b"2").unwrap();
+    assert_eq!(&storage.get(b"1").unwrap().unwrap()[..], b"233");
+    assert!(storage.get(b"2").unwrap().is_none());
+    assert_eq!(
+        &storage.get_cf("index", b"1").unwrap().unwrap()[..],
+        b"2333"
+    );
+    assert!(storage.get_cf("index", b"2").unwrap().is_none());
+    check_iter_result(
+        storage
+            .scan_cf("index", Bound::Unbounded, Bound::Unbounded)
+            .unwrap(),
+        vec![(Bytes::from("1"), Bytes::from("2333"))],
+    );
+    check_iter_result(
+        storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        vec![(Bytes::from("1"), Bytes::from("233"))],
+    );
+}
+
+#[test]
+fn test_column_family_create_drop() {
+    let storage = open_in_memory();
+    assert!(storage.drop_column_family(DEFAULT_CF).is_err());
+    assert!(storage.drop_column_family("meta").is_err());
+    assert!(storage.put_cf("meta", b"1", b"233").is_err());
+    storage
+        .create_column_family("meta", ColumnFamilyOptions::default())
+        .unwrap();
+    assert!(storage
+        .create_column_family("meta", ColumnFamilyOptions::default())
+        .is_err());
+    storage.put_cf("meta", b"1", b"233").unwrap();
+    storage.sync().unwrap();
+    let iter = storage
+        .scan_cf("meta", Bound::Unbounded, Bound::Unbounded)
+        .unwrap();
+    storage.drop_column_family("meta").unwrap();
+    assert!(storage.get_cf("meta", b"1").is_err());
+    // iterators created before the drop still work
+    check_iter_result(iter, vec![(Bytes::from("1"), Bytes::from("233"))]);
+    // a re-created column family starts empty
+    storage
+        .create_column_family("meta", ColumnFamilyOptions::default())
+        .unwrap();
+    assert!(storage.get_cf("meta", b"1").unwrap().is_none());
+}
+
+#[test]
+fn test_write_batch_across_column_families() {
+    let storage = open_in_memory();
+    storage
+        .create_column_family("index", ColumnFamilyOptions::default())
+        .unwrap();
+    storage.put(b"2", b"2333").unwrap();
+
+    let mut batch = WriteBatch::new();
+    batch.put(b"1", b"233");
+    batch.delete(b"2");
+    batch.put_cf("index", b"233", b"1");
+    storage.write(&batch).unwrap();
+    assert_eq!(&storage.get(b"1").unwrap().unwrap()[..], b"233");
+    assert!(storage.get(b"2").unwrap().is_none());
+    assert_eq!(&storage.get_cf("index", b"233").unwrap().unwrap()[..], b"1");
+
+    // a batch naming a missing column family writes nothing
+    let mut batch = WriteBatch::new();
+    batch.put(b"3", b"23333");
+    batch.put_cf("missing", b"3", b"23333");
+    assert!(storage.write(&batch).is_err());
+    assert!(storage.get(b"3").unwrap().is_none());
+}
+
+#[test]
+fn test_write_batch_invalid_record() {
+    let storage = open_in_memory();
+    let mut batch = WriteBatch::new();
+    batch.put(b"1", b"233");
+    batch.put(b"2", b"2333");
+    batch.put(b"3", b"");
+    assert!(storage.write(&batch).is_err());
+    assert!(storage.get(b"1").unwrap().is_none());
+    assert!(storage.get(b"2").unwrap().is_none());
+
+    let mut batch = WriteBatch::new();
+    batch.put(b"1", b"233");
+    batch.delete(b"");
+    assert!(storage.write(&batch).is_err());
+    assert!(storage.get(b"1").unwrap().is_none());
+    assert!(storage.put(b"", b"233").is_err());
+    assert!(storage.put(b"1", b"").is_err());
+    assert!(storage.delete(b"").is_err());
+}
+
+#[test]
+fn test_write_batch_atomic_to_readers() {
+    let storage = open_in_memory();
+    storage
+        .create_column_family("index", ColumnFamilyOptions::default())
+        .unwrap();
+    let writer = {
+        let storage = storage.clone();
+        std::thread::spawn(move || {
+            for i in 0..1000u32 {
+                let value = i.to_be_bytes();
+                let mut batch = WriteBatch::new();
+                batch.put(b"a", &value);
+                batch.put(b"b", &value);
+                batch.put_cf("index", b"a", &value);
+                storage.write(&batch).unwrap();
+                if i % 100 == 0 {
+                    storage.sync().unwrap();
+                }
+            }
+        })
+    };
+    while !writer.is_finished() {
+        // a reader never sees half of a batch
+        let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+        if iter.is_valid() {
+            let a = Bytes::copy_from_slice(iter.value());
+            iter.next().unwrap();
+            assert_eq!(iter.key(), b"b");
+            assert_eq!(iter.value(), &a[..]);
+        }
+        let b = storage.get(b"b").unwrap();
+        let index_a = storage.get_cf("index", b"a").unwrap();
+        if let (Some(b), Some(index_a)) = (b, index_a) {
+            // the column family is read later, so it may only be newer
+            assert!(index_a >= b);
+        }
+    }
+    writer.join().unwrap();
+}
+
+#[test]
+fn test_put_with_ttl_cf() {
+    let clock = Arc::new(MockClock::new(1000));
+    let storage = open_in_memory_with_options(LsmStorageOptions {
+        clock: clock.clone(),
+        ..Default::default()
+    });
+    storage
+        .create_column_family("index", ColumnFamilyOptions::default())
+        .unwrap();
+    storage
+        .put_with_ttl_cf("index", b"1", b"233", Duration::from_secs(10))
+        .unwrap();
+    let mut batch = WriteBatch::new();
+    batch.put_with_ttl(b"1", b"2333", Duration::from_secs(10));
+    batch.put_with_ttl_cf("index", b"2", b"23333", Duration::from_secs(20));
+    storage.write(&batch).unwrap();
+    assert!(storage
+        .put_with_ttl_cf("missing", b"1", b"233", Duration::from_secs(10))
+        .is_err());
+    assert_eq!(&storage.get(b"1").unwrap().unwrap()[..], b"2333");
+    assert_eq!(&storage.get_cf("index", b"1").unwrap().unwrap()[..], b"233");
+    clock.advance(Duration::from_secs(10));
+    assert!(storage.get(b"1").unwrap().is_none());
+    assert!(storage.get_cf("index", b"1").unwrap().is_none());
+    check_iter_result(
+        storage
+            .scan_cf("index", Bound::Unbounded, Bound::Unbounded)
+            .unwrap(),
+        vec![(Bytes::from("2"), Bytes::from("23333"))],
+    );
+}
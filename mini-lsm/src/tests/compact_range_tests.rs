@@ -0,0 +1,161 @@
+use std::ops::Bound;
+
+use super::harness::{
+    l0_sst_ids, list_files, open_in_memory, open_in_memory_with_options, tiered_options,
+};
+use crate::compact::TieredCompactionOptions;
+use crate::lsm_storage::{ColumnFamilyOptions, LsmStorageOptions, DEFAULT_CF};
+
+#[test]
+fn test_compact_range_drops_deleted_keys() {
+    let storage = open_in_memory();
+    for i in 0..100 {
+        let key = format!("key_{:03}", i);
+        storage.put(key.as_bytes(), b"value").unwrap();
+    }
+    storage.sync().unwrap();
+    for i in 0..100 {
+        let key = format!("key_{:03}", i);
+        storage.delete(key.as_bytes()).unwrap();
+    }
+    // the deletes are still in the memtable, and are flushed by `compact_range`
+    storage
+        .compact_range(Bound::Included(b"key_000"), Bound::Included(b"key_099"))
+        .unwrap();
+    assert!(l0_sst_ids(&storage).is_empty());
+    // the WAL files holding the flushed writes are deleted too
+    assert_eq!(list_files(&storage), ["00003.wal", "MANIFEST", "OPTIONS"]);
+    assert!(storage.get(b"key_000").unwrap().is_none());
+}
+
+#[test]
+fn test_compact_range_keeps_newer_tables() {
+    let storage = open_in_memory();
+    for prefix in ["a", "b", "c"] {
+        for i in 0..10 {
+            let key = format!("{}_{:03}", prefix, i);
+            storage.put(key.as_bytes(), prefix.as_bytes()).unwrap();
+        }
+        storage.sync().unwrap();
+    }
+    storage.delete(b"a_000").unwrap();
+    storage.sync().unwrap();
+    let ids = l0_sst_ids(&storage);
+
+    // nothing to compact
+    storage
+        .compact_range(Bound::Included(b"d"), Bound::Unbounded)
+        .unwrap();
+    assert_eq!(l0_sst_ids(&storage), ids);
+
+    // only the SST of "b" overlaps the range, and the output takes the oldest place in L0
+    storage
+        .compact_range(Bound::Included(b"b"), Bound::Excluded(b"c"))
+        .unwrap();
+    let new_ids = l0_sst_ids(&storage);
+    assert_eq!(new_ids.len(), 4);
+    assert!(!ids.contains(&new_ids[0]));
+    assert_eq!(new_ids[1..], [ids[0], ids[2], ids[3]]);
+    assert_eq!(&storage.get(b"a_001").unwrap().unwrap()[..], b"a");
+    assert_eq!(&storage.get(b"b_001").unwrap().unwrap()[..], b"b");
+    assert!(storage.get(b"a_000").unwrap().is_none());
+
+    // the delete is compacted with the SST of "a", and the others are left alone
+    storage
+        .compact_range(Bound::Unbounded, Bound::Included(b"a_000"))
+        .unwrap();
+    let last_ids = l0_sst_ids(&storage);
+    assert_eq!(last_ids.len(), 3);
+    assert_eq!(last_ids[1..], [new_ids[0], ids[2]]);
+    assert!(storage.get(b"a_000").unwrap().is_none());
+    assert_eq!(&storage.get(b"a_001").unwrap().unwrap()[..], b"a");
+    assert_eq!(&storage.get(b"c_009").unwrap().unwrap()[..], b"c");
+}
+
+#[test]
+fn test_compact_range_sorted_runs() {
+    let options = LsmStorageOptions {
+        default_cf: tiered_options(TieredCompactionOptions {
+            num_sorted_runs_trigger: 100,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let storage = open_in_memory_with_options(options);
+    for round in 0..3 {
+        for i in 0..10 {
+            let key = format!("key_{:03}", i);
+            storage
+                .put(key.as_bytes(), format!("value_{}", round).as_bytes())
+                .unwrap();
+        }
+        storage.sync().unwrap();
+    }
+    assert_eq!(storage.snapshot_for_test(DEFAULT_CF).levels().len(), 3);
+    storage
+        .compact_range(Bound::Included(b"key_005"), Bound::Unbounded)
+        .unwrap();
+    assert_eq!(storage.snapshot_for_test(DEFAULT_CF).levels().len(), 1);
+    assert_eq!(&storage.get(b"key_000").unwrap().unwrap()[..], b"value_2");
+}
+
+#[test]
+fn test_compact_range_keeps_tables_outside_range() {
+    let options = LsmStorageOptions {
+        default_cf: ColumnFamilyOptions {
+            block_size: 64,
+            target_sst_size: 256,
+            ..tiered_options(TieredCompactionOptions {
+                num_sorted_runs_trigger: 100,
+                ..Default::default()
+            })
+        },
+        ..Default::default()
+    };
+    let storage = open_in_memory_with_options(options);
+    for round in 0..2 {
+        for i in 0..100 {
+            let key = format!("key_{:03}", i);
+            storage
+                .put(key.as_bytes(), format!("value_{}", round).as_bytes())
+                .unwrap();
+        }
+        storage.sync().unwrap();
+    }
+    // a flush writes a single SST, so split the older run by compacting all of it first
+    storage
+        .compact_range(Bound::Unbounded, Bound::Unbounded)
+        .unwrap();
+    for i in 40..50 {
+        let key = format!("key_{:03}", i);
+        storage.put(key.as_bytes(), b"value_2").unwrap();
+    }
+    storage.sync().unwrap();
+    let snapshot = storage.snapshot_for_test(DEFAULT_CF);
+    assert_eq!(snapshot.levels().len(), 2);
+    let bottommost = snapshot.levels()[1].clone();
+    assert!(bottommost.len() > 4);
+
+    storage
+        .compact_range(Bound::Included(b"key_040"), Bound::Included(b"key_049"))
+        .unwrap();
+    let snapshot = storage.snapshot_for_test(DEFAULT_CF);
+    assert_eq!(snapshot.levels().len(), 1);
+    let ids: Vec<_> = snapshot.levels()[0].iter().map(|x| x.sst_id()).collect();
+    for table in &bottommost {
+        let outside = table.last_key() < &b"key_040"[..] || table.first_key() > &b"key_049"[..];
+        assert_eq!(ids.contains(&table.sst_id()), outside);
+    }
+    for i in 0..100 {
+        let key = format!("key_{:03}", i);
+        let expected = if (40..50).contains(&i) {
+            "value_2"
+        } else {
+            "value_1"
+        };
+        assert_eq!(
+            &storage.get(key.as_bytes()).unwrap().unwrap()[..],
+            expected.as_bytes()
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use std::ops::Bound;
+use std::sync::Arc;
+
+use super::harness::open_in_memory_with_options;
+use crate::compact::{CompactionOptions, TieredCompactionOptions};
+use crate::compaction_filter::{CompactionDecision, CompactionFilter};
+use crate::lsm_storage::{ColumnFamilyOptions, LsmStorage, LsmStorageOptions};
+
+/// Removes the keys of tenant `b`, upper-cases the values of tenant `c`, and empties the values of
+/// tenant `d`, which removes them too.
+struct TenantFilter;
+
+impl CompactionFilter for TenantFilter {
+    fn name(&self) -> &str {
+        "TenantFilter"
+    }
+
+    fn filter(
+        &self,
+        _level: u32,
+        key: &[u8],
+        value: &[u8],
+        _bottommost: bool,
+    ) -> CompactionDecision {
+        match key[0] {
+            b'b' => CompactionDecision::Remove,
+            b'c' => CompactionDecision::ChangeValue(value.to_ascii_uppercase()),
+            b'd' => CompactionDecision::ChangeValue(Vec::new()),
+            _ => CompactionDecision::Keep,
+        }
+    }
+}
+
+fn open_with_filter(compaction: CompactionOptions) -> Arc<LsmStorage> {
+    open_in_memory_with_options(LsmStorageOptions {
+        default_cf: ColumnFamilyOptions {
+            block_size: 128,
+            target_sst_size: 1024,
+            compaction,
+            compaction_filter: Some(Arc::new(TenantFilter)),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+fn put_tenants(storage: &LsmStorage) {
+    for tenant in ["a", "b", "c", "d"] {
+        for i in 0..20 {
+            let key = format!("{}_{:03}", tenant, i);
+            storage.put(key.as_bytes(), b"value").unwrap();
+        }
+    }
+    storage.sync().unwrap();
+}
+
+fn check_filtered(storage: &LsmStorage) {
+    for i in 0..20 {
+        let key = |tenant: &str| format!("{}_{:03}", tenant, i).into_bytes();
+        assert_eq!(&storage.get(&key("a")).unwrap().unwrap()[..], b"value");
+        assert!(storage.get(&key("b")).unwrap().is_none());
+        assert_eq!(&storage.get(&key("c")).unwrap().unwrap()[..], b"VALUE");
+        assert!(storage.get(&key("d")).unwrap().is_none());
+    }
+}
+
+#[test]
+fn test_compaction_filter_tiered() {
+    let storage = open_with_filter(CompactionOptions::Tiered(TieredCompactionOptions {
+        num_sorted_runs_trigger: 2,
+        ..Default::default()
+    }));
+    put_tenants(&storage);
+    // the filter only runs in compaction
+    assert_eq!(&storage.get(b"b_000").unwrap().unwrap()[..], b"value");
+    put_tenants(&storage);
+    storage.trigger_compaction().unwrap();
+    check_filtered(&storage);
+}
+
+#[test]
+fn test_compaction_filter_non_overlapping() {
+    let storage = open_with_filter(CompactionOptions::Tiered(TieredCompactionOptions {
+        num_sorted_runs_trigger: 2,
+        ..Default::default()
+    }));
+    // one SST per tenant, so that no SST overlaps another and all could be moved
+    for tenant in ["a", "b", "c", "d"] {
+        for i in 0..20 {
+            let key = format!("{}_{:03}", tenant, i);
+            storage.put(key.as_bytes(), b"value").unwrap();
+        }
+        storage.sync().unwrap();
+    }
+    storage.trigger_compaction().unwrap();
+    check_filtered(&storage);
+}
+
+#[test]
+fn test_compaction_filter_compact_range() {
+    let storage = open_with_filter(CompactionOptions::NoCompaction);
+    put_tenants(&storage);
+    storage
+        .compact_range(Bound::Unbounded, Bound::Unbounded)
+        .unwrap();
+    check_filtered(&storage);
+}
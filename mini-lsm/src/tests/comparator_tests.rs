@@ -0,0 +1,95 @@
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use super::harness::{check_iter_result, open_in_memory_with_options, DB_PATH};
+use crate::comparator::{ReverseBytewiseComparator, U64Comparator};
+use crate::env::MemFileSystem;
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+
+#[test]
+fn test_storage_reverse_comparator() {
+    let storage = open_in_memory_with_options(LsmStorageOptions {
+        comparator: &ReverseBytewiseComparator,
+        ..Default::default()
+    });
+    storage.put(b"1", b"233").unwrap();
+    storage.put(b"3", b"23333").unwrap();
+    storage.sync().unwrap();
+    storage.put(b"2", b"2333").unwrap();
+    storage.put(b"4", b"233333").unwrap();
+    storage.delete(b"3").unwrap();
+    assert_eq!(&storage.get(b"1").unwrap().unwrap()[..], b"233");
+    assert!(storage.get(b"3").unwrap().is_none());
+    check_iter_result(
+        storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        vec![
+            (Bytes::from("4"), Bytes::from("233333")),
+            (Bytes::from("2"), Bytes::from("2333")),
+            (Bytes::from("1"), Bytes::from("233")),
+        ],
+    );
+    check_iter_result(
+        storage
+            .scan(Bound::Included(b"3"), Bound::Excluded(b"1"))
+            .unwrap(),
+        vec![(Bytes::from("2"), Bytes::from("2333"))],
+    );
+}
+
+#[test]
+fn test_storage_u64_comparator() {
+    let storage = open_in_memory_with_options(LsmStorageOptions {
+        comparator: &U64Comparator,
+        ..Default::default()
+    });
+    for i in (0..100u64).rev() {
+        storage.put(&(i * 1000).to_be_bytes(), b"233").unwrap();
+        if i % 10 == 0 {
+            storage.sync().unwrap();
+        }
+    }
+    let mut iter = storage
+        .scan(
+            Bound::Excluded(&10000u64.to_be_bytes()),
+            Bound::Included(&20000u64.to_be_bytes()),
+        )
+        .unwrap();
+    let mut keys = Vec::new();
+    while iter.is_valid() {
+        keys.push(u64::from_be_bytes(iter.key().try_into().unwrap()));
+        iter.next().unwrap();
+    }
+    assert_eq!(keys, (11..=20).map(|i| i * 1000).collect::<Vec<_>>());
+    assert!(storage.get(&1000u64.to_be_bytes()).unwrap().is_some());
+    assert!(storage.get(&1001u64.to_be_bytes()).unwrap().is_none());
+
+    // keys that are not 8 bytes long are ordered bytewise instead of panicking
+    storage.put(b"1", b"2333").unwrap();
+    storage.sync().unwrap();
+    assert_eq!(&storage.get(b"1").unwrap().unwrap()[..], b"2333");
+    assert!(storage.get(b"12").unwrap().is_none());
+    let iter = storage
+        .scan(Bound::Included(b"1"), Bound::Excluded(b"2"))
+        .unwrap();
+    check_iter_result(iter, vec![(Bytes::from("1"), Bytes::from("2333"))]);
+}
+
+#[test]
+fn test_storage_reopen_with_other_comparator() {
+    let fs = Arc::new(MemFileSystem::new());
+    let options = LsmStorageOptions {
+        comparator: &ReverseBytewiseComparator,
+        file_system: fs.clone(),
+        ..Default::default()
+    };
+    drop(LsmStorage::open_with_options(DB_PATH, options.clone()).unwrap());
+    let default_options = LsmStorageOptions {
+        file_system: fs,
+        ..Default::default()
+    };
+    assert!(LsmStorage::open_with_options(DB_PATH, default_options).is_err());
+    drop(LsmStorage::open_with_options(DB_PATH, options).unwrap());
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use super::harness::{build_in_memory, check_iter_result, key_of, num_of_keys, value_of};
+use crate::iterators::concat_iterator::SstConcatIterator;
+use crate::iterators::StorageIterator;
+use crate::table::{SsTable, SsTableBuilder};
+
+/// Split the harness keys into a sorted run of three SSTs.
+fn generate_run() -> Vec<Arc<SsTable>> {
+    let num_per_table = num_of_keys() / 3 + 1;
+    (0..num_of_keys())
+        .collect::<Vec<_>>()
+        .chunks(num_per_table)
+        .map(|chunk| {
+            let mut builder = SsTableBuilder::new(128);
+            for &idx in chunk {
+                builder.add(&key_of(idx), &value_of(idx));
+            }
+            Arc::new(SsTable::open_for_test(build_in_memory(builder)).unwrap())
+        })
+        .collect()
+}
+
+fn expected_from(idx: usize) -> Vec<(Bytes, Bytes)> {
+    (idx..num_of_keys())
+        .map(|i| (Bytes::from(key_of(i)), Bytes::from(value_of(i))))
+        .collect()
+}
+
+#[test]
+fn test_concat_iterator() {
+    let run = generate_run();
+    assert_eq!(run.len(), 3);
+    check_iter_result(
+        SstConcatIterator::create_and_seek_to_first(run.clone()).unwrap(),
+        expected_from(0),
+    );
+    for idx in [0, 33, 34, 35, 66, 67, 99] {
+        check_iter_result(
+            SstConcatIterator::create_and_seek_to_key(run.clone(), &key_of(idx)).unwrap(),
+            expected_from(idx),
+        );
+    }
+    // a key between two SSTs, and keys before and after the run
+    let key = format!("{}a", String::from_utf8(key_of(33)).unwrap());
+    check_iter_result(
+        SstConcatIterator::create_and_seek_to_key(run.clone(), key.as_bytes()).unwrap(),
+        expected_from(34),
+    );
+    check_iter_result(
+        SstConcatIterator::create_and_seek_to_key(run.clone(), b"a").unwrap(),
+        expected_from(0),
+    );
+    let iter = SstConcatIterator::create_and_seek_to_key(run, b"z").unwrap();
+    assert!(!iter.is_valid());
+    let iter = SstConcatIterator::create_and_seek_to_first(Vec::new()).unwrap();
+    assert!(!iter.is_valid());
+}
@@ -0,0 +1,164 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use super::harness::{generate_block, key_of, num_of_keys, value_of};
+use crate::block::{Block, BlockIterator};
+use crate::env::{read_file, write_file, FileBackend, FileSystem, MemFileSystem};
+use crate::iterators::StorageIterator;
+use crate::manifest::{Manifest, ManifestRecord};
+use crate::table::{FileObject, SsTable, SsTableBuilder, SsTableIterator};
+use crate::wal::{Wal, WalRecord};
+
+/// Iterate over a block decoded from untrusted data, which must not panic.
+fn iterate_all(block: Block) {
+    let mut iter = BlockIterator::create_and_seek_to_first(Arc::new(block));
+    while iter.is_valid() {
+        iter.key();
+        iter.value();
+        iter.next();
+    }
+}
+
+#[test]
+fn test_block_decode_corrupted() {
+    let encoded = generate_block().encode();
+    assert!(Block::try_decode(&[]).is_err());
+    assert!(Block::try_decode(&[0]).is_err());
+    // claims 256 entries
+    assert!(Block::try_decode(&[1, 0]).is_err());
+    for len in 0..encoded.len() {
+        if let Ok(block) = Block::try_decode(&encoded[..len]) {
+            iterate_all(block);
+        }
+    }
+    for pos in 0..encoded.len() {
+        for flip in [0x01, 0x80, 0xff] {
+            let mut corrupted = encoded.to_vec();
+            corrupted[pos] ^= flip;
+            if let Ok(block) = Block::try_decode(&corrupted) {
+                iterate_all(block);
+            }
+        }
+    }
+}
+
+/// Open an SST from `data`, and read all of it if it opens. Corrupted data must fail with an error
+/// instead of a panic.
+fn open_and_read(data: &[u8]) {
+    let fs = MemFileSystem::new();
+    let path = Path::new("1.sst");
+    write_file(&fs, path, data).unwrap();
+    let file = FileObject::open_in(&fs, path, FileBackend::Pread).unwrap();
+    let Ok(sst) = SsTable::open_for_test(file) else {
+        return;
+    };
+    let sst = Arc::new(sst);
+    if let Ok(mut iter) = SsTableIterator::create_and_seek_to_first(sst.clone()) {
+        while iter.is_valid() {
+            iter.key();
+            iter.value();
+            if iter.next().is_err() {
+                break;
+            }
+        }
+    }
+    for idx in [0, num_of_keys() / 2, num_of_keys()] {
+        sst.may_contain_key(&key_of(idx));
+        let _ = SsTableIterator::create_and_seek_to_key(sst.clone(), &key_of(idx));
+    }
+}
+
+#[test]
+fn test_sst_open_corrupted() {
+    for partitioned in [false, true] {
+        let fs = Arc::new(MemFileSystem::new());
+        let mut builder = SsTableBuilder::new(128);
+        builder.set_file_system(fs.clone());
+        builder.set_bloom_filter(10, true);
+        if partitioned {
+            builder.set_index_partition_size(64);
+        }
+        for idx in 0..num_of_keys() {
+            builder.add(&key_of(idx), &value_of(idx));
+        }
+        builder.build_for_test("1.sst").unwrap();
+        let data = read_file(fs.as_ref(), Path::new("1.sst")).unwrap();
+
+        for len in 0..data.len() {
+            open_and_read(&data[..len]);
+        }
+        for pos in 0..data.len() {
+            let mut corrupted = data.to_vec();
+            corrupted[pos] ^= 0xff;
+            open_and_read(&corrupted);
+        }
+    }
+}
+
+/// Check that a log replays to some prefix of `records` whatever is done to the end or the middle
+/// of it, since a crash may leave the last record torn.
+fn check_log_corrupted<T: PartialEq + std::fmt::Debug>(
+    encoded: &[u8],
+    records: &[T],
+    replay: impl Fn(&[u8]) -> anyhow::Result<Vec<T>>,
+) {
+    assert_eq!(replay(encoded).unwrap(), records);
+    let is_prefix = |replayed: &[T]| replayed == &records[..replayed.len()];
+    for len in 0..encoded.len() {
+        let replayed = replay(&encoded[..len]).unwrap();
+        assert!(replayed.len() < records.len() && is_prefix(&replayed));
+    }
+    for pos in 0..encoded.len() {
+        for flip in [0x01, 0x80, 0xff] {
+            let mut corrupted = encoded.to_vec();
+            corrupted[pos] ^= flip;
+            let replayed = replay(&corrupted).unwrap();
+            assert!(replayed.len() < records.len() && is_prefix(&replayed));
+        }
+    }
+}
+
+#[test]
+fn test_wal_replay_corrupted() {
+    let fs = MemFileSystem::new();
+    let path = Path::new("1.wal");
+    let mut wal = Wal::create(&fs, path, 1).unwrap();
+    wal.append(1, &[(0, b"key_1", b"value_1")]).unwrap();
+    wal.append(2, &[(0, b"key_2", b""), (1, b"key_3", b"value_3")])
+        .unwrap();
+    wal.append(3, &[(2, b"key_4", b"value_4")]).unwrap();
+    wal.sync().unwrap();
+    let records = Wal::replay(&read_file(&fs, path).unwrap()).unwrap();
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[1].seq, 2);
+    assert_eq!(records[1].writes[1].cf_id, 1);
+    assert_eq!(&records[1].writes[1].key[..], b"key_3");
+    assert!(records[1].writes[0].value.is_empty());
+    check_log_corrupted::<WalRecord>(&read_file(&fs, path).unwrap(), &records, Wal::replay);
+}
+
+#[test]
+fn test_manifest_replay_corrupted() {
+    let fs = MemFileSystem::new();
+    let records = vec![
+        ManifestRecord::ColumnFamily {
+            id: 0,
+            name: "default".to_string(),
+            flushed_seq: 233,
+            l0_sstables: vec![3, 4],
+            levels: vec![vec![1, 2], vec![]],
+        },
+        ManifestRecord::DropColumnFamily { id: 1 },
+    ];
+    let manifest = Manifest::create(
+        &fs,
+        Path::new("MANIFEST"),
+        Path::new("MANIFEST.tmp"),
+        &records[..1],
+    )
+    .unwrap();
+    manifest.add_record(&records[1]).unwrap();
+    assert!(!fs.exists(Path::new("MANIFEST.tmp")));
+    let encoded = read_file(&fs, Path::new("MANIFEST")).unwrap();
+    check_log_corrupted(&encoded, &records, Manifest::replay);
+}
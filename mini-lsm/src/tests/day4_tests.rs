@@ -9,7 +9,7 @@ fn as_bytes(x: &[u8]) -> Bytes {
     Bytes::copy_from_slice(x)
 }
 
-fn check_iter_result(iter: impl StorageIterator, expected: Vec<(Bytes, Bytes)>) {
+pub(crate) fn check_iter_result(iter: impl StorageIterator, expected: Vec<(Bytes, Bytes)>) {
     let mut iter = iter;
     for (k, v) in expected {
         assert!(iter.is_valid());
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::harness::{close, l0_sst_ids, open_in_memory_with_options, reopen, sst_exists, DB_PATH};
+use crate::compact::{CompactionOptions, FifoCompactionOptions};
+use crate::env::{FaultInjectionFileSystem, MemFileSystem};
+use crate::lsm_storage::{ColumnFamilyOptions, LsmStorage, LsmStorageOptions, DEFAULT_CF};
+use crate::ttl::MockClock;
+
+fn fifo_options(clock: Arc<MockClock>, options: FifoCompactionOptions) -> LsmStorageOptions {
+    LsmStorageOptions {
+        clock,
+        default_cf: ColumnFamilyOptions {
+            compaction: CompactionOptions::Fifo(options),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Write one SST of 100 keys for each batch, one second apart.
+fn write_batches(storage: &LsmStorage, clock: &MockClock, num_batches: usize) {
+    for batch in 0..num_batches {
+        for i in 0..100 {
+            let key = format!("key_{:03}_{:03}", batch, i);
+            storage.put(key.as_bytes(), b"value").unwrap();
+        }
+        storage.sync().unwrap();
+        clock.advance(Duration::from_secs(1));
+    }
+}
+
+#[test]
+fn test_fifo_compaction_size_limit() {
+    let clock = Arc::new(MockClock::new(0));
+    let storage = open_in_memory_with_options(fifo_options(
+        clock.clone(),
+        FifoCompactionOptions::default(),
+    ));
+    write_batches(&storage, &clock, 5);
+    let ids = l0_sst_ids(&storage);
+    let table_size = storage.snapshot_for_test(DEFAULT_CF).l0_sstables()[0].table_size();
+    drop(storage);
+
+    let storage = open_in_memory_with_options(fifo_options(
+        clock.clone(),
+        FifoCompactionOptions {
+            max_table_files_size: table_size * 3,
+            ..Default::default()
+        },
+    ));
+    // SST IDs are assigned in the same way as in the first storage
+    write_batches(&storage, &clock, 5);
+    storage.trigger_compaction().unwrap();
+    assert_eq!(l0_sst_ids(&storage), ids[2..]);
+    assert!(!sst_exists(&storage, ids[0]));
+    assert!(storage.get(b"key_001_000").unwrap().is_none());
+    assert!(storage.get(b"key_002_000").unwrap().is_some());
+}
+
+#[test]
+fn test_fifo_compaction_ttl() {
+    let clock = Arc::new(MockClock::new(0));
+    let storage = open_in_memory_with_options(fifo_options(
+        clock.clone(),
+        FifoCompactionOptions {
+            ttl: Some(Duration::from_secs(3)),
+            ..Default::default()
+        },
+    ));
+    write_batches(&storage, &clock, 5);
+    // the SSTs were written at 0s, 1s, ..., 4s and it is now 5s
+    storage.trigger_compaction().unwrap();
+    assert_eq!(l0_sst_ids(&storage), vec![4, 5]);
+    let storage = reopen(storage);
+    assert_eq!(l0_sst_ids(&storage), vec![4, 5]);
+    assert!(storage.get(b"key_002_000").unwrap().is_none());
+}
+
+#[test]
+fn test_fifo_compaction_crash_while_deleting() {
+    let clock = Arc::new(MockClock::new(0));
+    let fs = Arc::new(FaultInjectionFileSystem::new(
+        Arc::new(MemFileSystem::new()),
+    ));
+    let options = LsmStorageOptions {
+        file_system: fs.clone(),
+        ..fifo_options(
+            clock.clone(),
+            FifoCompactionOptions {
+                ttl: Some(Duration::from_secs(3)),
+                ..Default::default()
+            },
+        )
+    };
+    let storage = LsmStorage::open_with_options(DB_PATH, options.clone()).unwrap();
+    write_batches(&storage, &clock, 5);
+    // The deletion of 3 SSTs is recorded in the manifest with an append and a sync, and the
+    // crash comes after the first file is deleted. The background worker may have deleted some of
+    // them already, as the clock moved forward.
+    fs.crash_after(3);
+    let _ = storage.trigger_compaction();
+    close(storage);
+    fs.drop_unsynced_data().unwrap();
+    fs.set_active(true);
+
+    let storage = LsmStorage::open_with_options(DB_PATH, options).unwrap();
+    assert_eq!(l0_sst_ids(&storage), vec![4, 5]);
+    // The files the crash left behind are deleted on open.
+    for id in 1..=3 {
+        assert!(!sst_exists(&storage, id));
+    }
+    assert!(storage.get(b"key_002_000").unwrap().is_none());
+    assert!(storage.get(b"key_003_000").unwrap().is_some());
+}
+
+#[test]
+fn test_fifo_compaction_ttl_never_expires() {
+    let clock = Arc::new(MockClock::new(0));
+    let storage = open_in_memory_with_options(fifo_options(
+        clock.clone(),
+        FifoCompactionOptions {
+            ttl: Some(Duration::MAX),
+            ..Default::default()
+        },
+    ));
+    write_batches(&storage, &clock, 2);
+    clock.set(u64::MAX - 1);
+    storage.trigger_compaction().unwrap();
+    assert_eq!(l0_sst_ids(&storage), vec![1, 2]);
+}
+
+#[test]
+fn test_fifo_compaction_intra_l0() {
+    let clock = Arc::new(MockClock::new(0));
+    let storage = open_in_memory_with_options(fifo_options(
+        clock.clone(),
+        FifoCompactionOptions {
+            intra_l0_file_num_trigger: 4,
+            ..Default::default()
+        },
+    ));
+    write_batches(&storage, &clock, 3);
+    storage.trigger_compaction().unwrap();
+    assert_eq!(storage.snapshot_for_test(DEFAULT_CF).l0_sstables().len(), 3);
+    write_batches(&storage, &clock, 1);
+    storage.trigger_compaction().unwrap();
+    assert_eq!(storage.snapshot_for_test(DEFAULT_CF).l0_sstables().len(), 1);
+    for batch in 0..3 {
+        for i in 0..100 {
+            let key = format!("key_{:03}_{:03}", batch, i);
+            assert!(storage.get(key.as_bytes()).unwrap().is_some());
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use super::harness::{key_of, num_of_keys, value_of};
+use crate::env::FileBackend;
+use crate::iterators::StorageIterator;
+use crate::table::{FileObject, SsTable, SsTableBuilder, SsTableIterator};
+
+#[test]
+fn test_sst_file_backends() {
+    let mut builder = SsTableBuilder::new(128);
+    for idx in 0..num_of_keys() {
+        builder.add(&key_of(idx), &value_of(idx));
+    }
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    drop(builder.build_for_test(&path).unwrap());
+    let expected = std::fs::read(&path).unwrap();
+    for backend in [FileBackend::Pread, FileBackend::Mmap, FileBackend::DirectIo] {
+        let file = FileObject::open_with_backend(&path, backend).unwrap();
+        assert_eq!(file.size(), expected.len() as u64);
+        // unaligned ranges, including the last byte of the file
+        let mid = expected.len() / 2;
+        for (offset, len) in [(0, 1), (17, 300), (mid, mid - 1), (expected.len() - 1, 1)] {
+            assert_eq!(
+                file.read(offset as u64, len as u64).unwrap(),
+                &expected[offset..offset + len],
+                "{:?}",
+                backend
+            );
+        }
+        assert!(file.read(expected.len() as u64 - 1, 2).is_err());
+
+        let sst = Arc::new(SsTable::open_for_test(file).unwrap());
+        let mut iter = SsTableIterator::create_and_seek_to_first(sst.clone()).unwrap();
+        for i in 0..num_of_keys() {
+            assert_eq!(iter.key(), key_of(i));
+            assert_eq!(iter.value(), value_of(i));
+            iter.next().unwrap();
+        }
+        assert!(!iter.is_valid());
+        for i in 0..num_of_keys() {
+            let iter = SsTableIterator::create_and_seek_to_key(sst.clone(), &key_of(i)).unwrap();
+            assert_eq!(iter.value(), value_of(i));
+        }
+    }
+}
+
+#[test]
+fn test_sst_build_with_file_backend() {
+    let mut builder = SsTableBuilder::new(128);
+    builder.set_file_backend(FileBackend::Mmap);
+    for idx in 0..num_of_keys() {
+        builder.add(&key_of(idx), &value_of(idx));
+    }
+    let dir = tempdir().unwrap();
+    let sst = Arc::new(builder.build_for_test(dir.path().join("1.sst")).unwrap());
+    let iter = SsTableIterator::create_and_seek_to_key(sst, &key_of(42)).unwrap();
+    assert_eq!(iter.value(), value_of(42));
+}
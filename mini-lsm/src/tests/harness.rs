@@ -0,0 +1,137 @@
+use std::path::Path;
+use std::sync::Arc;
+
+pub(crate) use super::day4_tests::check_iter_result;
+use crate::block::{Block, BlockBuilder};
+use crate::compact::{CompactionOptions, LeveledCompactionOptions, TieredCompactionOptions};
+use crate::env::{FileBackend, MemFileSystem};
+use crate::lsm_storage::{ColumnFamilyOptions, LsmStorage, LsmStorageOptions, DEFAULT_CF};
+use crate::table::{FileObject, SsTableBuilder};
+
+/// Where storages opened by the harness keep their files, in their own in-memory file system.
+pub(crate) const DB_PATH: &str = "/db";
+
+pub(crate) fn open_in_memory() -> Arc<LsmStorage> {
+    open_in_memory_with_options(LsmStorageOptions::default())
+}
+
+/// Open a storage on a new in-memory file system, which replaces the one in `options`.
+pub(crate) fn open_in_memory_with_options(options: LsmStorageOptions) -> Arc<LsmStorage> {
+    let options = LsmStorageOptions {
+        file_system: Arc::new(MemFileSystem::new()),
+        ..options
+    };
+    LsmStorage::open_with_options(DB_PATH, options).unwrap()
+}
+
+/// Close a storage opened by the harness, and open it again from its files.
+pub(crate) fn reopen(storage: Arc<LsmStorage>) -> Arc<LsmStorage> {
+    let options = storage.options.clone();
+    close(storage);
+    LsmStorage::open_with_options(DB_PATH, options).unwrap()
+}
+
+/// Drop a storage once the compaction thread no longer holds it, so that the storage does not
+/// touch its files anymore.
+pub(crate) fn close(mut storage: Arc<LsmStorage>) {
+    loop {
+        match Arc::try_unwrap(storage) {
+            Ok(_) => return,
+            Err(x) => storage = x,
+        }
+        std::thread::yield_now();
+    }
+}
+
+/// Options of a column family with tiered compaction.
+pub(crate) fn tiered_options(options: TieredCompactionOptions) -> ColumnFamilyOptions {
+    ColumnFamilyOptions {
+        compaction: CompactionOptions::Tiered(options),
+        ..Default::default()
+    }
+}
+
+/// Options of a column family with leveled compaction.
+pub(crate) fn leveled_options(options: LeveledCompactionOptions) -> ColumnFamilyOptions {
+    ColumnFamilyOptions {
+        compaction: CompactionOptions::Leveled(options),
+        ..Default::default()
+    }
+}
+
+/// Run compaction tasks on the default column family until none is needed.
+pub(crate) fn compact_until_done(storage: &LsmStorage) {
+    let cf = storage.column_family(DEFAULT_CF).unwrap();
+    while storage.compact_column_family(&cf).unwrap() {}
+}
+
+/// The IDs of the L0 SSTs of the default column family, from the oldest to the newest.
+pub(crate) fn l0_sst_ids(storage: &LsmStorage) -> Vec<usize> {
+    storage
+        .snapshot_for_test(DEFAULT_CF)
+        .l0_sstables()
+        .iter()
+        .map(|x| x.sst_id())
+        .collect()
+}
+
+/// The number of entries, including tombstones, stored in the sorted runs of the default column
+/// family.
+pub(crate) fn num_stored_entries(storage: &LsmStorage) -> u64 {
+    let snapshot = storage.snapshot_for_test(DEFAULT_CF);
+    snapshot
+        .levels()
+        .iter()
+        .flatten()
+        .map(|x| x.properties().num_entries)
+        .sum()
+}
+
+/// Whether the file of an SST exists.
+pub(crate) fn sst_exists(storage: &LsmStorage, sst_id: usize) -> bool {
+    storage
+        .options
+        .file_system
+        .exists(&storage.path_of_sst(sst_id))
+}
+
+/// The sorted names of all files of a storage opened by the harness.
+pub(crate) fn list_files(storage: &LsmStorage) -> Vec<String> {
+    let mut files = storage
+        .options
+        .file_system
+        .list(Path::new(DB_PATH))
+        .unwrap();
+    files.sort();
+    files
+}
+
+/// The key of the `idx`-th entry of the blocks and SSTs built by the harness.
+pub(crate) fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:03}", idx * 5).into_bytes()
+}
+
+pub(crate) fn value_of(idx: usize) -> Vec<u8> {
+    format!("value_{:010}", idx).into_bytes()
+}
+
+pub(crate) fn num_of_keys() -> usize {
+    100
+}
+
+/// Build a block of `num_of_keys()` entries.
+pub(crate) fn generate_block() -> Block {
+    let mut builder = BlockBuilder::new(10000);
+    for idx in 0..num_of_keys() {
+        assert!(builder.add(&key_of(idx), &value_of(idx)));
+    }
+    builder.build()
+}
+
+/// Build an SST in a new in-memory file system, and open its file again.
+pub(crate) fn build_in_memory(mut builder: SsTableBuilder) -> FileObject {
+    let fs = Arc::new(MemFileSystem::new());
+    builder.set_file_system(fs.clone());
+    builder.build_for_test("1.sst").unwrap();
+    FileObject::open_in(fs.as_ref(), Path::new("1.sst"), FileBackend::Pread).unwrap()
+}
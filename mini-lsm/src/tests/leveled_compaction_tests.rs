@@ -0,0 +1,154 @@
+use std::ops::Bound;
+
+use bytes::Bytes;
+
+use super::harness::{
+    check_iter_result, compact_until_done, leveled_options, num_stored_entries,
+    open_in_memory_with_options, sst_exists,
+};
+use crate::compact::LeveledCompactionOptions;
+use crate::lsm_storage::{ColumnFamilyOptions, LsmStorage, LsmStorageOptions, DEFAULT_CF};
+
+fn storage_options() -> LsmStorageOptions {
+    LsmStorageOptions {
+        default_cf: ColumnFamilyOptions {
+            block_size: 128,
+            target_sst_size: 1024,
+            ..leveled_options(LeveledCompactionOptions {
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 3,
+                base_level_size: 4096,
+                level_size_multiplier: 2,
+            })
+        },
+        ..Default::default()
+    }
+}
+
+/// Check that every level is a sorted run of SSTs whose key ranges do not overlap.
+fn check_levels(storage: &LsmStorage) {
+    let snapshot = storage.snapshot_for_test(DEFAULT_CF);
+    assert_eq!(snapshot.levels().len(), 3);
+    for run in snapshot.levels() {
+        for pair in run.windows(2) {
+            assert!(pair[0].last_key() < pair[1].first_key());
+        }
+    }
+}
+
+#[test]
+fn test_leveled_compaction() {
+    let storage = open_in_memory_with_options(storage_options());
+    assert_eq!(storage.snapshot_for_test(DEFAULT_CF).levels().len(), 3);
+    for round in 0..10 {
+        for i in 0..100 {
+            let key = format!("key_{:03}", (i * 7 + round * 13) % 200);
+            storage
+                .put(key.as_bytes(), format!("value_{}", round).as_bytes())
+                .unwrap();
+        }
+        storage.sync().unwrap();
+        compact_until_done(&storage);
+        check_levels(&storage);
+        assert!(storage.snapshot_for_test(DEFAULT_CF).l0_sstables().len() < 2);
+    }
+    let snapshot = storage.snapshot_for_test(DEFAULT_CF);
+    // L1 grew past its target size, and was merged into L2
+    assert!(!snapshot.levels()[1].is_empty());
+    let l1_size: u64 = snapshot.levels()[0].iter().map(|x| x.table_size()).sum();
+    assert!(l1_size <= 4096);
+    for table in snapshot.levels().iter().flatten() {
+        assert!(sst_exists(&storage, table.sst_id()));
+    }
+
+    // every key holds the value of the last round that wrote it
+    let mut expected = Vec::new();
+    for k in 0..200 {
+        let round = (0..10)
+            .rev()
+            .find(|round| (0..100).any(|i| (i * 7 + round * 13) % 200 == k));
+        if let Some(round) = round {
+            let key = format!("key_{:03}", k);
+            let value = format!("value_{}", round);
+            assert_eq!(storage.get(key.as_bytes()).unwrap().unwrap(), value);
+            expected.push((Bytes::from(key), Bytes::from(value)));
+        }
+    }
+    check_iter_result(
+        storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        expected.clone(),
+    );
+    let lower = &expected[10].0;
+    check_iter_result(
+        storage
+            .scan(Bound::Excluded(lower), Bound::Unbounded)
+            .unwrap(),
+        expected[11..].to_vec(),
+    );
+}
+
+#[test]
+fn test_leveled_compaction_drops_tombstones() {
+    let storage = open_in_memory_with_options(storage_options());
+    for i in 0..100 {
+        let key = format!("key_{:03}", i);
+        storage.put(key.as_bytes(), b"value").unwrap();
+    }
+    storage.sync().unwrap();
+    for i in 0..100 {
+        let key = format!("key_{:03}", i);
+        storage.delete(key.as_bytes()).unwrap();
+    }
+    storage.sync().unwrap();
+    // nothing is older than L1, so the merge into it is bottommost
+    compact_until_done(&storage);
+    check_levels(&storage);
+    assert_eq!(num_stored_entries(&storage), 0);
+    assert_eq!(storage.stats().unwrap().num_sorted_runs, 0);
+    assert!(storage.get(b"key_000").unwrap().is_none());
+}
+
+#[test]
+fn test_leveled_compaction_trivial_move() {
+    let storage = open_in_memory_with_options(storage_options());
+    // sequential keys, so that no SST overlaps another
+    for round in 0..2 {
+        for i in 0..10 {
+            let key = format!("key_{}_{:03}", round, i);
+            storage.put(key.as_bytes(), b"value").unwrap();
+        }
+        storage.sync().unwrap();
+    }
+    let mut ids: Vec<_> = storage
+        .snapshot_for_test(DEFAULT_CF)
+        .l0_sstables()
+        .iter()
+        .map(|x| x.sst_id())
+        .collect();
+    compact_until_done(&storage);
+    let snapshot = storage.snapshot_for_test(DEFAULT_CF);
+    ids.sort_unstable();
+    let l1_ids: Vec<_> = snapshot.levels()[0].iter().map(|x| x.sst_id()).collect();
+    assert_eq!(l1_ids, ids);
+    assert_eq!(storage.stats().unwrap().compaction_bytes_rewritten, 0);
+}
+
+#[test]
+fn test_leveled_compact_range_keeps_levels() {
+    let storage = open_in_memory_with_options(storage_options());
+    for i in 0..100 {
+        let key = format!("key_{:03}", i);
+        storage.put(key.as_bytes(), b"value").unwrap();
+    }
+    storage.sync().unwrap();
+    compact_until_done(&storage);
+    storage.delete(b"key_000").unwrap();
+    storage
+        .compact_range(Bound::Unbounded, Bound::Unbounded)
+        .unwrap();
+    check_levels(&storage);
+    let snapshot = storage.snapshot_for_test(DEFAULT_CF);
+    // all data is in the last level
+    assert!(snapshot.levels()[..2].iter().all(|run| run.is_empty()));
+    assert_eq!(num_stored_entries(&storage), 99);
+}
@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use crate::comparator::BytewiseComparator;
+use crate::mem_table::MemTable;
+use crate::prefix::DelimiterPrefixExtractor;
+
+#[test]
+fn test_memtable_bloom_filter() {
+    let extractor = DelimiterPrefixExtractor::new(b':');
+    let memtable = MemTable::create_with_bloom_filter(
+        &BytewiseComparator,
+        1024,
+        true,
+        Some(Arc::new(DelimiterPrefixExtractor::new(b':'))),
+    );
+    for i in 0..100 {
+        memtable.put(format!("user:{:03}", i).as_bytes(), b"value");
+    }
+    let mut false_positives = 0;
+    for i in 0..100 {
+        let key = format!("user:{:03}", i);
+        assert!(memtable.may_contain_key(key.as_bytes()));
+        assert_eq!(&memtable.get(key.as_bytes()).unwrap()[..], b"value");
+        let missing = format!("user:{:03}", i + 100);
+        if memtable.may_contain_key(missing.as_bytes()) {
+            false_positives += 1;
+        }
+        assert!(memtable.get(missing.as_bytes()).is_none());
+    }
+    assert!(false_positives < 10);
+    assert!(memtable.may_contain_prefix(b"user:", &extractor));
+    assert!(!memtable.may_contain_prefix(b"order:", &extractor));
+    // a mem-table without a filter rules nothing out
+    assert!(MemTable::create().may_contain_key(b"user:100"));
+}
@@ -0,0 +1,66 @@
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use super::harness::{build_in_memory, check_iter_result};
+use crate::mem_table::MemTable;
+use crate::table::{SsTable, SsTableBuilder, SsTableIterator};
+
+#[test]
+fn test_memtable_read_at_seq() {
+    let memtable = MemTable::create();
+    memtable.put_with_seq(b"1", b"233", 1);
+    memtable.put_with_seq(b"2", b"2333", 1);
+    memtable.put_with_seq(b"1", b"", 2);
+    memtable.put_with_seq(b"2", b"23333", 3);
+    memtable.put_with_seq(b"3", b"233333", 3);
+
+    assert!(memtable.get_at_seq(b"1", 0).is_none());
+    assert_eq!(&memtable.get_at_seq(b"1", 1).unwrap()[..], b"233");
+    assert_eq!(&memtable.get_at_seq(b"1", 2).unwrap()[..], b"");
+    assert_eq!(&memtable.get_at_seq(b"2", 2).unwrap()[..], b"2333");
+    assert_eq!(&memtable.get(b"2").unwrap()[..], b"23333");
+
+    check_iter_result(
+        memtable.scan_at_seq(Bound::Unbounded, Bound::Unbounded, 1),
+        vec![
+            (Bytes::from("1"), Bytes::from("233")),
+            (Bytes::from("2"), Bytes::from("2333")),
+        ],
+    );
+    check_iter_result(
+        memtable.scan_at_seq(Bound::Included(b"1"), Bound::Included(b"2"), 2),
+        vec![
+            (Bytes::from("1"), Bytes::from("")),
+            (Bytes::from("2"), Bytes::from("2333")),
+        ],
+    );
+    check_iter_result(
+        memtable.scan_at_seq(Bound::Excluded(b"1"), Bound::Unbounded, 3),
+        vec![
+            (Bytes::from("2"), Bytes::from("23333")),
+            (Bytes::from("3"), Bytes::from("233333")),
+        ],
+    );
+}
+
+#[test]
+fn test_memtable_flush_newest_versions() {
+    let memtable = MemTable::create();
+    memtable.put_with_seq(b"1", b"233", 1);
+    memtable.put_with_seq(b"1", b"2333", 2);
+    memtable.put_with_seq(b"2", b"23333", 2);
+    let mut builder = SsTableBuilder::new(128);
+    memtable.flush(&mut builder).unwrap();
+    let sst = SsTable::open_for_test(build_in_memory(builder)).unwrap();
+    // only the newest version of a key is flushed
+    assert_eq!(sst.properties().num_entries, 2);
+    check_iter_result(
+        SsTableIterator::create_and_seek_to_first(Arc::new(sst)).unwrap(),
+        vec![
+            (Bytes::from("1"), Bytes::from("2333")),
+            (Bytes::from("2"), Bytes::from("23333")),
+        ],
+    );
+}
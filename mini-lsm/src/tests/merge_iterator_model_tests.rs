@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use bytes::Bytes;
+use proptest::collection::{btree_map, vec};
+use proptest::prelude::*;
+
+use super::harness::check_iter_result;
+use crate::iterators::merge_iterator::MergeIterator;
+use crate::iterators::two_merge_iterator::TwoMergeIterator;
+use crate::mem_table::{MemTable, MemTableIterator};
+
+/// Keys are never empty, since an empty key marks the end of a mem-table iterator.
+fn entries() -> impl Strategy<Value = BTreeMap<Vec<u8>, Vec<u8>>> {
+    btree_map(vec(any::<u8>(), 1..3), vec(any::<u8>(), 0..4), 0..16)
+}
+
+fn memtable_iter(data: &BTreeMap<Vec<u8>, Vec<u8>>) -> MemTableIterator {
+    let memtable = MemTable::create();
+    for (key, value) in data {
+        memtable.put(key, value);
+    }
+    memtable.scan(Bound::Unbounded, Bound::Unbounded)
+}
+
+fn expected_result(expected: BTreeMap<Vec<u8>, Vec<u8>>) -> Vec<(Bytes, Bytes)> {
+    expected
+        .into_iter()
+        .map(|(k, v)| (Bytes::from(k), Bytes::from(v)))
+        .collect()
+}
+
+proptest! {
+    /// The merge yields every key once, with the value of the first iterator that has it.
+    #[test]
+    fn test_merge_model(inputs in vec(entries(), 0..6)) {
+        let mut expected = BTreeMap::new();
+        for input in inputs.iter().rev() {
+            expected.extend(input.clone());
+        }
+        let iter = MergeIterator::create(inputs.iter().map(|x| Box::new(memtable_iter(x))).collect());
+        check_iter_result(iter, expected_result(expected));
+    }
+
+    /// The merge yields every key once, preferring the value of `a`.
+    #[test]
+    fn test_two_merge_model(a in entries(), b in entries()) {
+        let mut expected = b.clone();
+        expected.extend(a.clone());
+        let iter = TwoMergeIterator::create(memtable_iter(&a), memtable_iter(&b)).unwrap();
+        check_iter_result(iter, expected_result(expected));
+    }
+}
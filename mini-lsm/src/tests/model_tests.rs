@@ -0,0 +1,298 @@
+use std::collections::BTreeMap;
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use super::harness::{check_iter_result, open_in_memory_with_options, reopen};
+use crate::compact::{CompactionOptions, FifoCompactionOptions, TieredCompactionOptions};
+use crate::lsm_storage::{ColumnFamilyOptions, LsmStorageOptions, DEFAULT_CF};
+use crate::ttl::MockClock;
+use crate::write_batch::WriteBatch;
+
+/// The column families the operations run on. The second one can be dropped and created again.
+const CFS: [&str; 2] = [DEFAULT_CF, "cf1"];
+
+#[derive(Clone, Debug)]
+enum Op {
+    Put(usize, Vec<u8>, Vec<u8>),
+    /// Put into the default column family with a TTL in milliseconds.
+    PutWithTtl(Vec<u8>, Vec<u8>, u64),
+    Delete(usize, Vec<u8>),
+    /// Write a batch across column families. A `None` value is a delete.
+    WriteBatch(Vec<(usize, Vec<u8>, Option<Vec<u8>>)>),
+    Get(usize, Vec<u8>),
+    Scan(usize, Bound<Vec<u8>>, Bound<Vec<u8>>),
+    Sync,
+    /// Run a compaction task on every column family that needs one, like the background worker.
+    Compact,
+    CompactRange(usize, Bound<Vec<u8>>, Bound<Vec<u8>>),
+    /// Move the clock forward by some milliseconds.
+    AdvanceClock(u64),
+    /// Drop the second column family and create it again.
+    RecreateCf,
+    /// Kill the storage without syncing it, and open it again. A killed process does not lose
+    /// the writes in its WAL.
+    Reopen,
+}
+
+/// The compaction strategy of all column families. None of them loses data: FIFO compaction only
+/// merges L0 SSTs here.
+#[derive(Clone, Copy, Debug)]
+enum CompactionKind {
+    NoCompaction,
+    Tiered,
+    Fifo,
+}
+
+impl CompactionKind {
+    fn options(self) -> CompactionOptions {
+        match self {
+            CompactionKind::NoCompaction => CompactionOptions::NoCompaction,
+            CompactionKind::Tiered => CompactionOptions::Tiered(TieredCompactionOptions {
+                num_sorted_runs_trigger: 3,
+                deletion_percent_trigger: 50,
+                ..Default::default()
+            }),
+            CompactionKind::Fifo => CompactionOptions::Fifo(FifoCompactionOptions {
+                max_table_files_size: u64::MAX,
+                intra_l0_file_num_trigger: 2,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+fn compaction() -> impl Strategy<Value = CompactionKind> {
+    prop_oneof![
+        Just(CompactionKind::NoCompaction),
+        Just(CompactionKind::Tiered),
+        Just(CompactionKind::Fifo),
+    ]
+}
+
+/// Keys from a small space, so that operations often hit the same keys.
+fn key() -> impl Strategy<Value = Vec<u8>> {
+    (0..40u32).prop_map(|i| format!("key_{:02}", i).into_bytes())
+}
+
+fn value() -> impl Strategy<Value = Vec<u8>> {
+    vec(any::<u8>(), 1..32)
+}
+
+fn cf() -> impl Strategy<Value = usize> {
+    0..CFS.len()
+}
+
+fn bound() -> impl Strategy<Value = Bound<Vec<u8>>> {
+    prop_oneof![
+        Just(Bound::Unbounded),
+        key().prop_map(Bound::Included),
+        key().prop_map(Bound::Excluded),
+    ]
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        10 => (cf(), key(), value()).prop_map(|(cf, k, v)| Op::Put(cf, k, v)),
+        3 => (key(), value(), 1..100u64).prop_map(|(k, v, ttl)| Op::PutWithTtl(k, v, ttl)),
+        4 => (cf(), key()).prop_map(|(cf, k)| Op::Delete(cf, k)),
+        2 => vec((cf(), key(), proptest::option::of(value())), 1..8).prop_map(Op::WriteBatch),
+        4 => (cf(), key()).prop_map(|(cf, k)| Op::Get(cf, k)),
+        4 => (cf(), bound(), bound()).prop_map(|(cf, lower, upper)| Op::Scan(cf, lower, upper)),
+        2 => Just(Op::Sync),
+        1 => Just(Op::Compact),
+        1 => (cf(), bound(), bound()).prop_map(|(cf, lower, upper)| Op::CompactRange(cf, lower, upper)),
+        1 => (1..50u64).prop_map(Op::AdvanceClock),
+        1 => Just(Op::RecreateCf),
+        1 => Just(Op::Reopen),
+    ]
+}
+
+fn as_ref(bound: &Bound<Vec<u8>>) -> Bound<&[u8]> {
+    match bound {
+        Bound::Included(key) => Bound::Included(key),
+        Bound::Excluded(key) => Bound::Excluded(key),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// The value of every key of a column family, and when it expires.
+type Model = BTreeMap<Vec<u8>, (Vec<u8>, Option<u64>)>;
+
+/// Get a key from the model, unless it expired at `now`.
+fn get_model(model: &Model, now: u64, key: &[u8]) -> Option<Vec<u8>> {
+    match model.get(key) {
+        Some((value, expire_at)) if expire_at.map_or(true, |x| x > now) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+/// Scan the model. Unlike `BTreeMap::range`, this allows a lower bound above the upper one.
+fn scan_model(
+    model: &Model,
+    now: u64,
+    lower: &Bound<Vec<u8>>,
+    upper: &Bound<Vec<u8>>,
+) -> Vec<(Bytes, Bytes)> {
+    model
+        .iter()
+        .filter(|(key, _)| (lower.as_ref(), upper.as_ref()).contains(key))
+        .filter_map(|(key, _)| Some((Bytes::from(key.clone()), get_model(model, now, key)?.into())))
+        .collect()
+}
+
+/// Run the operations against the storage and a `BTreeMap` for each column family, and compare
+/// every read.
+fn check_against_model(compaction: CompactionKind, ops: Vec<Op>) {
+    let cf_options = ColumnFamilyOptions {
+        block_size: 64,
+        target_sst_size: 256,
+        compaction: compaction.options(),
+        ..Default::default()
+    };
+    let clock = Arc::new(MockClock::new(0));
+    let mut storage = open_in_memory_with_options(LsmStorageOptions {
+        clock: clock.clone(),
+        default_cf: cf_options.clone(),
+        column_families: [(CFS[1].to_string(), cf_options.clone())].into(),
+        ..Default::default()
+    });
+    storage
+        .create_column_family(CFS[1], cf_options.clone())
+        .unwrap();
+    let mut models = vec![Model::new(); CFS.len()];
+    let mut now = 0;
+    for op in ops {
+        match op {
+            Op::Put(cf, key, value) => {
+                storage.put_cf(CFS[cf], &key, &value).unwrap();
+                models[cf].insert(key, (value, None));
+            }
+            Op::PutWithTtl(key, value, ttl) => {
+                storage
+                    .put_with_ttl(&key, &value, Duration::from_millis(ttl))
+                    .unwrap();
+                models[0].insert(key, (value, Some(now + ttl)));
+            }
+            Op::Delete(cf, key) => {
+                storage.delete_cf(CFS[cf], &key).unwrap();
+                models[cf].remove(&key);
+            }
+            Op::WriteBatch(records) => {
+                let mut batch = WriteBatch::new();
+                for (cf, key, value) in records {
+                    match value {
+                        Some(value) => {
+                            batch.put_cf(CFS[cf], &key, &value);
+                            models[cf].insert(key, (value, None));
+                        }
+                        None => {
+                            batch.delete_cf(CFS[cf], &key);
+                            models[cf].remove(&key);
+                        }
+                    }
+                }
+                storage.write(&batch).unwrap();
+            }
+            Op::Get(cf, key) => {
+                let value = storage.get_cf(CFS[cf], &key).unwrap();
+                assert_eq!(
+                    value.as_deref(),
+                    get_model(&models[cf], now, &key).as_deref()
+                );
+            }
+            Op::Scan(cf, lower, upper) => {
+                let iter = storage
+                    .scan_cf(CFS[cf], as_ref(&lower), as_ref(&upper))
+                    .unwrap();
+                check_iter_result(iter, scan_model(&models[cf], now, &lower, &upper));
+            }
+            Op::Sync => storage.sync().unwrap(),
+            Op::Compact => storage.trigger_compaction().unwrap(),
+            Op::CompactRange(cf, lower, upper) => storage
+                .compact_range_cf(CFS[cf], as_ref(&lower), as_ref(&upper))
+                .unwrap(),
+            Op::AdvanceClock(millis) => {
+                clock.advance(Duration::from_millis(millis));
+                now += millis;
+            }
+            Op::RecreateCf => {
+                storage.drop_column_family(CFS[1]).unwrap();
+                storage
+                    .create_column_family(CFS[1], cf_options.clone())
+                    .unwrap();
+                models[1].clear();
+            }
+            Op::Reopen => storage = reopen(storage),
+        }
+    }
+    for (cf, model) in CFS.iter().zip(&models) {
+        let iter = storage
+            .scan_cf(cf, Bound::Unbounded, Bound::Unbounded)
+            .unwrap();
+        check_iter_result(
+            iter,
+            scan_model(model, now, &Bound::Unbounded, &Bound::Unbounded),
+        );
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(128))]
+
+    #[test]
+    fn test_storage_model(compaction in compaction(), ops in vec(op(), 0..200)) {
+        check_against_model(compaction, ops);
+    }
+}
+
+#[test]
+fn test_scan_empty_range() {
+    check_against_model(
+        CompactionKind::NoCompaction,
+        vec![
+            Op::Put(0, b"key_01".to_vec(), b"value".to_vec()),
+            Op::Put(0, b"key_02".to_vec(), b"value".to_vec()),
+            Op::Scan(
+                0,
+                Bound::Included(b"key_01".to_vec()),
+                Bound::Excluded(b"key_01".to_vec()),
+            ),
+            Op::Scan(
+                0,
+                Bound::Included(b"key_02".to_vec()),
+                Bound::Included(b"key_01".to_vec()),
+            ),
+            Op::Sync,
+            Op::Scan(
+                0,
+                Bound::Excluded(b"key_01".to_vec()),
+                Bound::Excluded(b"key_01".to_vec()),
+            ),
+        ],
+    );
+}
+
+#[test]
+fn test_reopen_recreated_cf() {
+    check_against_model(
+        CompactionKind::Tiered,
+        vec![
+            Op::Put(1, b"key_01".to_vec(), b"old".to_vec()),
+            Op::Sync,
+            Op::Put(1, b"key_02".to_vec(), b"old".to_vec()),
+            Op::RecreateCf,
+            Op::Put(1, b"key_03".to_vec(), b"new".to_vec()),
+            Op::PutWithTtl(b"key_04".to_vec(), b"value".to_vec(), 10),
+            Op::Reopen,
+            Op::Scan(1, Bound::Unbounded, Bound::Unbounded),
+            Op::AdvanceClock(10),
+            Op::Reopen,
+            Op::Get(0, b"key_04".to_vec()),
+        ],
+    );
+}
@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use super::harness::{
+    build_in_memory, check_iter_result, key_of, num_of_keys, open_in_memory_with_options, value_of,
+};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{ColumnFamilyOptions, LsmStorageOptions, DEFAULT_CF};
+use crate::prefix::{DelimiterPrefixExtractor, FixedPrefixExtractor, PrefixExtractor};
+use crate::table::{SsTable, SsTableBuilder};
+
+fn prefix_options() -> LsmStorageOptions {
+    LsmStorageOptions {
+        default_cf: ColumnFamilyOptions {
+            bloom_bits_per_key: 10,
+            prefix_extractor: Some(Arc::new(DelimiterPrefixExtractor::new(b':'))),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_scan_prefix_skips_tables() {
+    let storage = open_in_memory_with_options(prefix_options());
+    for i in 0..100 {
+        storage
+            .put(format!("order:{:03}", i).as_bytes(), b"order")
+            .unwrap();
+    }
+    storage.sync().unwrap();
+    storage.put(b"user:1", b"alice").unwrap();
+    storage.put(b"user:3", b"carol").unwrap();
+    storage.put(b"users", b"not a user").unwrap();
+    storage.sync().unwrap();
+    storage.put(b"user:2", b"bob").unwrap();
+    storage.delete(b"user:3").unwrap();
+
+    let tables = storage.snapshot_for_test(DEFAULT_CF).l0_sstables().to_vec();
+    let (orders, users) = (tables[0].sst_id(), tables[1].sst_id());
+    check_iter_result(
+        storage.scan_prefix(b"user:").unwrap(),
+        vec![
+            (Bytes::from("user:1"), Bytes::from("alice")),
+            (Bytes::from("user:2"), Bytes::from("bob")),
+        ],
+    );
+    // the SST of orders was ruled out by its filter and never read
+    assert!(!storage.block_cache.contains_key(&(orders, 0)));
+    assert!(storage.block_cache.contains_key(&(users, 0)));
+
+    // a prefix that is not a prefix of the extractor is scanned without filters
+    let mut iter = storage.scan_prefix(b"order:0").unwrap();
+    let mut count = 0;
+    while iter.is_valid() {
+        assert!(iter.key().starts_with(b"order:0"));
+        count += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(count, 100);
+    check_iter_result(
+        storage.scan_prefix(b"user").unwrap(),
+        vec![
+            (Bytes::from("user:1"), Bytes::from("alice")),
+            (Bytes::from("user:2"), Bytes::from("bob")),
+            (Bytes::from("users"), Bytes::from("not a user")),
+        ],
+    );
+    check_iter_result(storage.scan_prefix(b"item:").unwrap(), vec![]);
+}
+
+#[test]
+fn test_scan_prefix_without_upper_bound() {
+    let storage = open_in_memory_with_options(prefix_options());
+    storage.put(b"\xfe\xff", b"1").unwrap();
+    storage.put(b"\xff", b"2").unwrap();
+    storage.put(b"\xff\xff:", b"3").unwrap();
+    storage.sync().unwrap();
+    check_iter_result(
+        storage.scan_prefix(b"\xff").unwrap(),
+        vec![
+            (Bytes::from_static(b"\xff"), Bytes::from("2")),
+            (Bytes::from_static(b"\xff\xff:"), Bytes::from("3")),
+        ],
+    );
+    check_iter_result(
+        storage.scan_prefix(b"").unwrap(),
+        vec![
+            (Bytes::from_static(b"\xfe\xff"), Bytes::from("1")),
+            (Bytes::from_static(b"\xff"), Bytes::from("2")),
+            (Bytes::from_static(b"\xff\xff:"), Bytes::from("3")),
+        ],
+    );
+}
+
+#[test]
+fn test_get_with_bloom_filter() {
+    let storage = open_in_memory_with_options(prefix_options());
+    for i in 0..100 {
+        storage
+            .put(format!("key:{:03}", i).as_bytes(), b"value")
+            .unwrap();
+    }
+    storage.sync().unwrap();
+    let table = storage.snapshot_for_test(DEFAULT_CF).l0_sstables()[0].clone();
+    assert_eq!(table.properties().filter_policy, "mini-lsm.BloomFilter");
+    for i in 0..100 {
+        let key = format!("key:{:03}", i);
+        assert!(table.may_contain_key(key.as_bytes()));
+        assert_eq!(&storage.get(key.as_bytes()).unwrap().unwrap()[..], b"value");
+    }
+    assert!(storage.get(b"key:100").unwrap().is_none());
+}
+
+#[test]
+fn test_memtable_bloom_filter() {
+    let mut options = prefix_options();
+    options.default_cf.write_buffer_size = 1 << 20;
+    options.default_cf.memtable_prefix_bloom_size_ratio = 0.1;
+    options.default_cf.memtable_whole_key_filtering = true;
+    let storage = open_in_memory_with_options(options);
+    storage.put(b"order:1", b"book").unwrap();
+    storage.put(b"user:1", b"alice").unwrap();
+    let memtable = storage.snapshot_for_test(DEFAULT_CF).memtable.clone();
+    storage.sync().unwrap();
+    storage.put(b"user:2", b"bob").unwrap();
+    storage.delete(b"user:1").unwrap();
+
+    // the flushed memtable is replaced by one that has a filter as well
+    let snapshot = storage.snapshot_for_test(DEFAULT_CF);
+    let extractor = DelimiterPrefixExtractor::new(b':');
+    assert!(!snapshot.memtable.may_contain_prefix(b"order:", &extractor));
+    assert!(snapshot.memtable.may_contain_prefix(b"user:", &extractor));
+    assert!(memtable.may_contain_key(b"user:1"));
+    assert!(storage.get(b"user:1").unwrap().is_none());
+    assert_eq!(&storage.get(b"user:2").unwrap().unwrap()[..], b"bob");
+    assert_eq!(&storage.get(b"order:1").unwrap().unwrap()[..], b"book");
+    assert!(storage.get(b"order:2").unwrap().is_none());
+    check_iter_result(
+        storage.scan_prefix(b"order:").unwrap(),
+        vec![(Bytes::from("order:1"), Bytes::from("book"))],
+    );
+    check_iter_result(
+        storage.scan_prefix(b"user:").unwrap(),
+        vec![(Bytes::from("user:2"), Bytes::from("bob"))],
+    );
+}
+
+#[test]
+fn test_sst_bloom_filter() {
+    let extractor = FixedPrefixExtractor::new(5);
+    let mut builder = SsTableBuilder::new(128);
+    builder.set_bloom_filter(10, true);
+    builder.set_prefix_extractor(Arc::new(FixedPrefixExtractor::new(5)));
+    for idx in 0..num_of_keys() {
+        builder.add(&key_of(idx), &value_of(idx));
+    }
+    let sst = SsTable::open_for_test(build_in_memory(builder)).unwrap();
+    assert_eq!(sst.properties().filter_policy, "mini-lsm.BloomFilter");
+    assert!(sst.properties().whole_key_filtering);
+    assert_eq!(sst.properties().prefix_extractor, extractor.name());
+    let mut false_positives = 0;
+    for idx in 0..num_of_keys() {
+        assert!(sst.may_contain_key(&key_of(idx)));
+        if sst.may_contain_key(format!("key_{:03}", idx * 5 + 1).as_bytes()) {
+            false_positives += 1;
+        }
+    }
+    assert!(false_positives < num_of_keys() / 10);
+    for prefix in [b"key_0", b"key_1", b"key_4"] {
+        assert!(sst.may_contain_prefix(prefix, &extractor));
+    }
+    assert!(!sst.may_contain_prefix(b"abcde", &extractor));
+    // a filter built by another extractor rules nothing out
+    assert!(sst.may_contain_prefix(b"abcdef", &FixedPrefixExtractor::new(6)));
+}
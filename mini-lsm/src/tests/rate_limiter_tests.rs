@@ -0,0 +1,101 @@
+use std::ops::Bound;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::harness::open_in_memory_with_options;
+use crate::lsm_storage::{LsmStorageOptions, DEFAULT_CF};
+use crate::rate_limiter::{IoPriority, RateLimiter};
+
+#[test]
+fn test_rate_limiter_rate() {
+    // 10000 bytes are available right away, then 10000 more every 100ms
+    let rate_limiter = RateLimiter::new(100_000);
+    let start = Instant::now();
+    rate_limiter.request(25_000, IoPriority::Low);
+    assert!(start.elapsed() >= Duration::from_millis(150));
+    assert_eq!(rate_limiter.total_bytes_through(IoPriority::Low), 25_000);
+    assert_eq!(rate_limiter.total_bytes_through(IoPriority::High), 0);
+
+    rate_limiter.set_bytes_per_second(1 << 30);
+    let start = Instant::now();
+    rate_limiter.request(25_000, IoPriority::High);
+    assert!(start.elapsed() < Duration::from_millis(150));
+    assert_eq!(rate_limiter.total_bytes_through(IoPriority::High), 25_000);
+}
+
+#[test]
+fn test_rate_limiter_max_rate() {
+    let rate_limiter = RateLimiter::new(u64::MAX);
+    let start = Instant::now();
+    rate_limiter.request(1 << 30, IoPriority::Low);
+    std::thread::sleep(Duration::from_millis(200));
+    rate_limiter.request(1 << 30, IoPriority::Low);
+    rate_limiter.set_bytes_per_second(1000);
+    rate_limiter.set_bytes_per_second(u64::MAX);
+    rate_limiter.request(1 << 30, IoPriority::High);
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    let rate_limiter = RateLimiter::new_auto_tuned(u64::MAX);
+    rate_limiter.request(1 << 30, IoPriority::Low);
+}
+
+#[test]
+fn test_rate_limiter_high_priority_first() {
+    let rate_limiter = Arc::new(RateLimiter::new(100_000));
+    rate_limiter.request(10_000, IoPriority::Low);
+    let high = {
+        let rate_limiter = rate_limiter.clone();
+        std::thread::spawn(move || {
+            rate_limiter.request(20_000, IoPriority::High);
+            Instant::now()
+        })
+    };
+    // let the high priority request start waiting
+    std::thread::sleep(Duration::from_millis(20));
+    rate_limiter.request(10_000, IoPriority::Low);
+    let low_done = Instant::now();
+    assert!(high.join().unwrap() <= low_done);
+}
+
+#[test]
+fn test_rate_limiter_auto_tune() {
+    let rate_limiter = RateLimiter::new_auto_tuned(1 << 20);
+    rate_limiter.request(10, IoPriority::Low);
+    std::thread::sleep(Duration::from_millis(1100));
+    // no request had to wait, so the rate goes down
+    rate_limiter.request(10, IoPriority::Low);
+    assert!(rate_limiter.bytes_per_second() < 1 << 20);
+    rate_limiter.set_bytes_per_second(1000);
+    assert!(rate_limiter.bytes_per_second() <= 1000);
+}
+
+#[test]
+fn test_rate_limited_flush_and_compaction() {
+    let rate_limiter = Arc::new(RateLimiter::new(1 << 30));
+    let options = LsmStorageOptions {
+        rate_limiter: Some(rate_limiter.clone()),
+        ..Default::default()
+    };
+    let storage = open_in_memory_with_options(options);
+    for _ in 0..2 {
+        for i in 0..100 {
+            let key = format!("key_{:03}", i);
+            storage.put(key.as_bytes(), b"value").unwrap();
+        }
+        storage.sync().unwrap();
+    }
+    let flushed: u64 = storage
+        .snapshot_for_test(DEFAULT_CF)
+        .l0_sstables()
+        .iter()
+        .map(|x| x.table_size())
+        .sum();
+    assert_eq!(rate_limiter.total_bytes_through(IoPriority::High), flushed);
+    assert_eq!(rate_limiter.total_bytes_through(IoPriority::Low), 0);
+
+    storage
+        .compact_range(Bound::Unbounded, Bound::Unbounded)
+        .unwrap();
+    let compacted = storage.snapshot_for_test(DEFAULT_CF).l0_sstables()[0].table_size();
+    assert_eq!(rate_limiter.total_bytes_through(IoPriority::Low), compacted);
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::path::Path;
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use super::harness::{
+    check_iter_result, compact_until_done, l0_sst_ids, leveled_options, list_files, open_in_memory,
+    open_in_memory_with_options, reopen, DB_PATH,
+};
+use crate::compact::LeveledCompactionOptions;
+use crate::env::write_file;
+use crate::lsm_storage::{ColumnFamilyOptions, LsmStorage, LsmStorageOptions, DEFAULT_CF};
+use crate::write_batch::WriteBatch;
+
+#[test]
+fn test_reopen_replays_wal() {
+    let storage = open_in_memory();
+    storage.put(b"1", b"233").unwrap();
+    storage.put(b"2", b"2333").unwrap();
+    storage.sync().unwrap();
+    storage.put(b"3", b"23333").unwrap();
+    storage.delete(b"1").unwrap();
+    storage
+        .put_with_ttl(b"4", b"233333", Duration::from_secs(3600))
+        .unwrap();
+    let storage = reopen(storage);
+    check_iter_result(
+        storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        vec![
+            (Bytes::from("2"), Bytes::from("2333")),
+            (Bytes::from("3"), Bytes::from("23333")),
+            (Bytes::from("4"), Bytes::from("233333")),
+        ],
+    );
+    // The replayed writes were flushed, and the WAL files holding them deleted.
+    assert_eq!(l0_sst_ids(&storage).len(), 2);
+    assert_eq!(
+        list_files(&storage),
+        ["00001.sst", "00002.sst", "00004.wal", "MANIFEST", "OPTIONS"]
+    );
+
+    // New SSTs do not reuse the IDs of recovered ones.
+    storage.put(b"5", b"2333333").unwrap();
+    storage.sync().unwrap();
+    assert_eq!(l0_sst_ids(&storage), vec![1, 2, 3]);
+    let storage = reopen(storage);
+    assert_eq!(l0_sst_ids(&storage), vec![1, 2, 3]);
+    assert_eq!(&storage.get(b"5").unwrap().unwrap()[..], b"2333333");
+}
+
+#[test]
+fn test_reopen_replays_write_batch() {
+    let storage = open_in_memory();
+    storage
+        .create_column_family("meta", ColumnFamilyOptions::default())
+        .unwrap();
+    storage.put(b"1", b"233").unwrap();
+    let mut batch = WriteBatch::new();
+    batch.put(b"2", b"2333");
+    batch.delete(b"1");
+    batch.put_cf("meta", b"1", b"23333");
+    storage.write(&batch).unwrap();
+    let storage = reopen(storage);
+    assert!(storage.get(b"1").unwrap().is_none());
+    assert_eq!(&storage.get(b"2").unwrap().unwrap()[..], b"2333");
+    assert_eq!(
+        &storage.get_cf("meta", b"1").unwrap().unwrap()[..],
+        b"23333"
+    );
+}
+
+#[test]
+fn test_reopen_column_families() {
+    let storage = open_in_memory_with_options(LsmStorageOptions {
+        column_families: HashMap::from([(
+            "index".to_string(),
+            ColumnFamilyOptions {
+                block_size: 128,
+                ..Default::default()
+            },
+        )]),
+        ..Default::default()
+    });
+    for name in ["index", "meta", "tmp"] {
+        storage
+            .create_column_family(name, ColumnFamilyOptions::default())
+            .unwrap();
+        storage.put_cf(name, b"1", name.as_bytes()).unwrap();
+    }
+    storage.sync().unwrap();
+    storage.put_cf("tmp", b"2", b"tmp").unwrap();
+    storage.drop_column_family("tmp").unwrap();
+    storage.drop_column_family("meta").unwrap();
+    storage
+        .create_column_family("meta", ColumnFamilyOptions::default())
+        .unwrap();
+    storage.put_cf("meta", b"2", b"new meta").unwrap();
+
+    let storage = reopen(storage);
+    assert_eq!(
+        storage.list_column_families(),
+        vec![DEFAULT_CF, "index", "meta"]
+    );
+    // Column families named in the options are opened with them.
+    let index = storage.column_family("index").unwrap();
+    assert_eq!(index.options.block_size, 128);
+    assert_eq!(
+        &storage.get_cf("index", b"1").unwrap().unwrap()[..],
+        b"index"
+    );
+    // Dropped column families stay dropped, and the data of a dropped column family is not
+    // recovered into a new one with the same name.
+    assert!(storage.get_cf("meta", b"1").unwrap().is_none());
+    assert_eq!(
+        &storage.get_cf("meta", b"2").unwrap().unwrap()[..],
+        b"new meta"
+    );
+    assert!(storage.get_cf("tmp", b"1").is_err());
+    storage
+        .create_column_family("tmp", ColumnFamilyOptions::default())
+        .unwrap();
+    assert!(storage.get_cf("tmp", b"1").unwrap().is_none());
+}
+
+#[test]
+fn test_reopen_leveled_compaction() {
+    let storage = open_in_memory_with_options(LsmStorageOptions {
+        default_cf: ColumnFamilyOptions {
+            block_size: 128,
+            target_sst_size: 1024,
+            ..leveled_options(LeveledCompactionOptions {
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 3,
+                base_level_size: 4096,
+                level_size_multiplier: 2,
+            })
+        },
+        ..Default::default()
+    });
+    for round in 0..5 {
+        for i in 0..100 {
+            let key = format!("key_{:03}", (i * 7 + round * 13) % 200);
+            storage
+                .put(key.as_bytes(), format!("value_{}", round).as_bytes())
+                .unwrap();
+        }
+        storage.sync().unwrap();
+        compact_until_done(&storage);
+    }
+    let sst_ids = |storage: &LsmStorage| -> Vec<Vec<usize>> {
+        let snapshot = storage.snapshot_for_test(DEFAULT_CF);
+        snapshot
+            .levels()
+            .iter()
+            .map(|run| run.iter().map(|x| x.sst_id()).collect())
+            .collect()
+    };
+    let levels = sst_ids(&storage);
+    assert!(levels.iter().any(|run| !run.is_empty()));
+    let expected: Vec<_> = (0..200)
+        .map(|i| storage.get(format!("key_{:03}", i).as_bytes()).unwrap())
+        .collect();
+
+    let storage = reopen(storage);
+    assert_eq!(sst_ids(&storage), levels);
+    for (i, value) in expected.iter().enumerate() {
+        assert_eq!(
+            &storage.get(format!("key_{:03}", i).as_bytes()).unwrap(),
+            value
+        );
+    }
+}
+
+#[test]
+fn test_reopen_deletes_unreferenced_ssts() {
+    let storage = open_in_memory();
+    storage.put(b"1", b"233").unwrap();
+    storage.sync().unwrap();
+    // left behind by a flush or a compaction that crashed before recording its output
+    let fs = storage.options.file_system.clone();
+    write_file(
+        fs.as_ref(),
+        &Path::new(DB_PATH).join("00007.sst"),
+        b"garbage",
+    )
+    .unwrap();
+    let storage = reopen(storage);
+    assert_eq!(
+        list_files(&storage),
+        ["00001.sst", "00003.wal", "MANIFEST", "OPTIONS"]
+    );
+    assert_eq!(&storage.get(b"1").unwrap().unwrap()[..], b"233");
+    // The ID of the deleted SST is not reused either.
+    storage.put(b"2", b"2333").unwrap();
+    storage.sync().unwrap();
+    assert_eq!(l0_sst_ids(&storage), vec![1, 8]);
+}
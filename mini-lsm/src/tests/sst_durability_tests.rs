@@ -0,0 +1,155 @@
+//! Crash tests of recovery. The storage runs a random workload on a file system that crashes at
+//! some point, loses the data that was not synced, and is then opened again. The reopened storage
+//! must hold exactly the data acknowledged by its last sync, or, if the data written since then
+//! was torn instead of dropped, the data after some of the writes acknowledged since then.
+
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::harness::close;
+use crate::env::{FaultInjectionFileSystem, MemFileSystem};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{ColumnFamilyOptions, LsmStorage, LsmStorageOptions};
+
+type Data = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// How the data that was not synced is lost in a crash.
+#[derive(Clone, Copy, Debug)]
+enum CrashMode {
+    DropUnsynced,
+    TearUnsynced,
+}
+
+fn options(fs: Arc<FaultInjectionFileSystem>) -> LsmStorageOptions {
+    LsmStorageOptions {
+        file_system: fs,
+        default_cf: ColumnFamilyOptions {
+            block_size: 64,
+            target_sst_size: 512,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn read_all(storage: &LsmStorage) -> Data {
+    let mut data = Data::new();
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    while iter.is_valid() {
+        data.insert(iter.key().to_vec(), iter.value().to_vec());
+        iter.next().unwrap();
+    }
+    data
+}
+
+/// Run random puts, deletes, syncs and range compactions until the file system crashes after
+/// `crash_after` operations, or the workload ends. Then lose the unsynced data, reopen the
+/// storage, and check the data it recovered.
+fn run_durability_test(seed: u64, crash_after: usize, mode: CrashMode) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let fs = Arc::new(FaultInjectionFileSystem::new(
+        Arc::new(MemFileSystem::new()),
+    ));
+    let storage = LsmStorage::open_with_options("/db", options(fs.clone())).unwrap();
+    fs.crash_after(crash_after);
+
+    let mut current = Data::new();
+    // The data made durable by the last acknowledged sync, followed by the data after each write
+    // acknowledged since then. A write that fails is where the storage crashed.
+    let mut acknowledged = vec![Data::new()];
+    // Whether the storage crashed in a sync, which may or may not have made the writes before it
+    // durable.
+    let mut failed_sync = false;
+    for op in 0..200 {
+        let key = format!("key_{:03}", rng.gen_range(0..50)).into_bytes();
+        match rng.gen_range(0..100) {
+            0..=59 => {
+                // every value is unique, so that a value never written cannot be mistaken for one
+                let value = format!("value_{}_{}", seed, op).into_bytes();
+                if storage.put(&key, &value).is_err() {
+                    break;
+                }
+                current.insert(key, value);
+                acknowledged.push(current.clone());
+            }
+            60..=79 => {
+                if storage.delete(&key).is_err() {
+                    break;
+                }
+                current.remove(&key);
+                acknowledged.push(current.clone());
+            }
+            80..=94 => {
+                if storage.sync().is_err() {
+                    failed_sync = true;
+                    break;
+                }
+                acknowledged = vec![current.clone()];
+            }
+            _ => {
+                // the memtable is flushed before compacting
+                if storage
+                    .compact_range(Bound::Unbounded, Bound::Unbounded)
+                    .is_err()
+                {
+                    failed_sync = true;
+                    break;
+                }
+                acknowledged = vec![current.clone()];
+            }
+        }
+    }
+    fs.set_active(false);
+    close(storage);
+    let synced = acknowledged.first().unwrap().clone();
+    match mode {
+        CrashMode::DropUnsynced => {
+            fs.drop_unsynced_data().unwrap();
+            acknowledged.truncate(1);
+            if failed_sync {
+                acknowledged.push(current);
+            }
+        }
+        CrashMode::TearUnsynced => fs.tear_unsynced_data(seed).unwrap(),
+    }
+    fs.set_active(true);
+
+    let storage = LsmStorage::open_with_options("/db", options(fs.clone())).unwrap();
+    let recovered = read_all(&storage);
+    assert!(
+        acknowledged.contains(&recovered),
+        "seed {}, crash after {} ops, {:?}: recovered {} keys, synced {} keys",
+        seed,
+        crash_after,
+        mode,
+        recovered.len(),
+        synced.len(),
+    );
+
+    // The recovered storage keeps working, and a second reopen sees the same data.
+    storage.put(b"key_after_crash", b"value").unwrap();
+    storage.sync().unwrap();
+    close(storage);
+    let storage = LsmStorage::open_with_options("/db", options(fs)).unwrap();
+    let mut expected = recovered;
+    expected.insert(b"key_after_crash".to_vec(), b"value".to_vec());
+    assert_eq!(read_all(&storage), expected);
+}
+
+#[test]
+fn test_recover_after_dropped_data() {
+    for seed in 0..50 {
+        run_durability_test(seed, seed as usize * 3, CrashMode::DropUnsynced);
+    }
+}
+
+#[test]
+fn test_recover_after_torn_data() {
+    for seed in 0..50 {
+        run_durability_test(seed, seed as usize * 3 + 1, CrashMode::TearUnsynced);
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use super::harness::{build_in_memory, key_of, num_of_keys, value_of};
+use crate::comparator::{BytewiseComparator, Comparator};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::BlockCache;
+use crate::table::{SsTable, SsTableBuilder, SsTableIterator};
+
+#[test]
+fn test_sst_partitioned_index() {
+    let mut builder = SsTableBuilder::new(128);
+    builder.set_index_partition_size(64);
+    for idx in 0..num_of_keys() {
+        builder.add(&key_of(idx), &value_of(idx));
+    }
+    let block_cache = Arc::new(BlockCache::new(1024));
+    let sst =
+        Arc::new(SsTable::open(1, Some(block_cache.clone()), build_in_memory(builder)).unwrap());
+    assert!(sst.block_metas_for_test().is_empty());
+    assert!(sst.properties().index_partitions > 1);
+    assert_eq!(
+        sst.num_index_partitions_for_test() as u64,
+        sst.properties().index_partitions
+    );
+
+    let mut iter = SsTableIterator::create_and_seek_to_first(sst.clone()).unwrap();
+    for i in 0..num_of_keys() {
+        assert_eq!(iter.key(), key_of(i));
+        assert_eq!(iter.value(), value_of(i));
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+    for i in 0..num_of_keys() {
+        let iter = SsTableIterator::create_and_seek_to_key(
+            sst.clone(),
+            format!("key_{:03}", i * 5 - (i > 0) as usize).as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(iter.key(), key_of(i));
+    }
+    // the index partitions are read through the block cache
+    assert!(block_cache.contains_key(&(1, sst.num_of_blocks())));
+}
+
+#[test]
+fn test_sst_shortened_separators() {
+    let comparator = BytewiseComparator;
+    assert_eq!(comparator.find_shortest_separator(b"abc", b"abd"), b"abd");
+    assert_eq!(comparator.find_shortest_separator(b"abc", b"abzzz"), b"abz");
+    assert_eq!(comparator.find_shortest_separator(b"ab", b"abcd"), b"abc");
+
+    let long_key_of = |idx: usize| format!("{}_{:03}_suffix", "x".repeat(64), idx * 5).into_bytes();
+    for partition_size in [None, Some(64)] {
+        let mut builder = SsTableBuilder::new(256);
+        if let Some(partition_size) = partition_size {
+            builder.set_index_partition_size(partition_size);
+        }
+        for idx in 0..num_of_keys() {
+            builder.add(&long_key_of(idx), &value_of(idx));
+        }
+        let sst = Arc::new(SsTable::open_for_test(build_in_memory(builder)).unwrap());
+        assert!(sst.num_of_blocks() > 1);
+        if partition_size.is_none() {
+            let block_metas = sst.block_metas_for_test();
+            assert_eq!(block_metas[0].first_key, long_key_of(0));
+            for meta in &block_metas[1..] {
+                // the separators end at the first digit that differs, and drop the suffix
+                assert!(meta.first_key.len() <= long_key_of(0).len() - "_suffix".len());
+            }
+        }
+        for i in 0..num_of_keys() {
+            let iter =
+                SsTableIterator::create_and_seek_to_key(sst.clone(), &long_key_of(i)).unwrap();
+            assert_eq!(iter.key(), long_key_of(i));
+            // a key between two keys, which may fall between two blocks
+            let mut key = long_key_of(i);
+            key.push(0);
+            let iter = SsTableIterator::create_and_seek_to_key(sst.clone(), &key).unwrap();
+            if i + 1 < num_of_keys() {
+                assert_eq!(iter.key(), long_key_of(i + 1));
+            } else {
+                assert!(!iter.is_valid());
+            }
+        }
+    }
+}
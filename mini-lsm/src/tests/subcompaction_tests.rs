@@ -0,0 +1,55 @@
+use std::ops::Bound;
+
+use super::harness::{check_iter_result, open_in_memory_with_options};
+use crate::lsm_storage::{ColumnFamilyOptions, LsmStorageOptions, DEFAULT_CF};
+
+#[test]
+fn test_subcompaction() {
+    let options = LsmStorageOptions {
+        default_cf: ColumnFamilyOptions {
+            block_size: 128,
+            target_sst_size: 1024,
+            max_subcompactions: 4,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let storage = open_in_memory_with_options(options);
+    let mut expected = Vec::new();
+    for round in 0..3 {
+        // every round starts at a different key, so that the SSTs give different boundaries
+        for i in (round * 50..500).step_by(3 - round) {
+            let key = format!("key_{:03}", i);
+            storage
+                .put(key.as_bytes(), format!("value_{}", round).as_bytes())
+                .unwrap();
+        }
+        storage.sync().unwrap();
+    }
+    for i in 0..500 {
+        let key = format!("key_{:03}", i);
+        let round = (0..3)
+            .rev()
+            .find(|&round| i >= round * 50 && (i - round * 50) % (3 - round) == 0);
+        if let Some(round) = round {
+            expected.push((key.into_bytes(), format!("value_{}", round).into_bytes()));
+        }
+    }
+
+    storage
+        .compact_range(Bound::Unbounded, Bound::Unbounded)
+        .unwrap();
+    let snapshot = storage.snapshot_for_test(DEFAULT_CF);
+    let tables = snapshot.l0_sstables();
+    assert!(tables.len() > 4);
+    for pair in tables.windows(2) {
+        assert!(pair[0].last_key() < pair[1].first_key());
+    }
+    check_iter_result(
+        storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        expected
+            .iter()
+            .map(|(key, value)| (key.clone().into(), value.clone().into()))
+            .collect(),
+    );
+}
@@ -0,0 +1,163 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use super::harness::{build_in_memory, key_of, num_of_keys, open_in_memory_with_options, value_of};
+use crate::compact::{CompactionOptions, TieredCompactionOptions};
+use crate::env::{FileBackend, MemFileSystem};
+use crate::lsm_storage::{ColumnFamilyOptions, LsmStorageOptions, DEFAULT_CF};
+use crate::table::{
+    FileObject, SsTable, SsTableBuilder, TablePropertiesCollector, TablePropertiesCollectorFactory,
+};
+use crate::ttl::MockClock;
+
+struct CountingCollector(u64);
+
+impl TablePropertiesCollector for CountingCollector {
+    fn add(&mut self, _key: &[u8], value: &[u8]) {
+        if !value.is_empty() {
+            self.0 += 1;
+        }
+    }
+
+    fn finish(&mut self) -> Vec<(String, Bytes)> {
+        vec![("num.puts".to_string(), self.0.to_be_bytes().to_vec().into())]
+    }
+}
+
+#[test]
+fn test_sst_properties() {
+    let mut builder = SsTableBuilder::new(16);
+    builder.set_creation_time(233);
+    builder.set_level(2);
+    builder.add_properties_collector(Box::new(CountingCollector(0)));
+    builder.add(b"11", b"11");
+    builder.add(b"22", b"");
+    builder.add(b"33", b"");
+    builder.add(b"444", b"22");
+    let sst = SsTable::open_for_test(build_in_memory(builder)).unwrap();
+    let properties = sst.properties();
+    assert_eq!(properties.num_entries, 4);
+    assert_eq!(properties.num_deletions, 2);
+    assert_eq!(properties.deletion_percent(), 50);
+    assert_eq!(properties.raw_key_size, 9);
+    assert_eq!(properties.raw_value_size, 4);
+    assert_eq!(&properties.smallest_key[..], b"11");
+    assert_eq!(&properties.largest_key[..], b"444");
+    assert_eq!(properties.creation_time, 233);
+    assert_eq!(properties.level, 2);
+    assert_eq!(properties.compression, "none");
+    assert_eq!(properties.filter_policy, "");
+    assert_eq!(properties.user_properties.len(), 1);
+    assert_eq!(
+        &properties.user_properties["num.puts"][..],
+        2u64.to_be_bytes()
+    );
+}
+
+#[test]
+fn test_sst_decode_properties() {
+    let mut builder = SsTableBuilder::new(128);
+    for idx in 0..num_of_keys() {
+        builder.add(&key_of(idx), &value_of(idx));
+    }
+    let fs = Arc::new(MemFileSystem::new());
+    builder.set_file_system(fs.clone());
+    let sst = builder.build_for_test("1.sst").unwrap();
+    let properties = sst.properties().clone();
+    let file = FileObject::open_in(fs.as_ref(), Path::new("1.sst"), FileBackend::Pread).unwrap();
+    let new_sst = SsTable::open_for_test(file).unwrap();
+    assert_eq!(new_sst.first_key(), key_of(0));
+    assert_eq!(new_sst.last_key(), key_of(num_of_keys() - 1));
+    assert_eq!(new_sst.properties(), &properties);
+}
+
+/// Records the largest stored value length of every SST. Stored values have a one-byte header.
+struct MaxValueLenCollector(usize);
+
+impl TablePropertiesCollector for MaxValueLenCollector {
+    fn add(&mut self, _key: &[u8], value: &[u8]) {
+        self.0 = self.0.max(value.len());
+    }
+
+    fn finish(&mut self) -> Vec<(String, Bytes)> {
+        vec![("max.value.len".to_string(), self.0.to_string().into())]
+    }
+}
+
+struct MaxValueLenCollectorFactory;
+
+impl TablePropertiesCollectorFactory for MaxValueLenCollectorFactory {
+    fn create(&self) -> Box<dyn TablePropertiesCollector> {
+        Box::new(MaxValueLenCollector(0))
+    }
+}
+
+#[test]
+fn test_table_properties_of_flush_and_compaction() {
+    let clock = Arc::new(MockClock::new(1000));
+    let options = LsmStorageOptions {
+        clock: clock.clone(),
+        default_cf: ColumnFamilyOptions {
+            compaction: CompactionOptions::Tiered(TieredCompactionOptions {
+                num_sorted_runs_trigger: 2,
+                ..Default::default()
+            }),
+            table_properties_collectors: vec![Arc::new(MaxValueLenCollectorFactory)],
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let storage = open_in_memory_with_options(options);
+    storage.put(b"b", b"23").unwrap();
+    storage.put(b"c", b"233").unwrap();
+    storage.sync().unwrap();
+    let snapshot = storage.snapshot_for_test(DEFAULT_CF);
+    let properties = snapshot.levels()[0][0].properties();
+    assert_eq!(properties.num_entries, 2);
+    assert_eq!(properties.creation_time, 1000);
+    assert_eq!(properties.level, 0);
+    assert_eq!(&properties.user_properties["max.value.len"][..], b"4");
+
+    clock.set(2000);
+    storage.put(b"a", b"2333").unwrap();
+    storage.delete(b"b").unwrap();
+    storage.sync().unwrap();
+    storage.trigger_compaction().unwrap();
+    let snapshot = storage.snapshot_for_test(DEFAULT_CF);
+    assert_eq!(snapshot.levels().len(), 1);
+    let properties = snapshot.levels()[0][0].properties();
+    // the tombstone and the value it hides are dropped
+    assert_eq!(properties.num_entries, 2);
+    assert_eq!(properties.num_deletions, 0);
+    assert_eq!(&properties.smallest_key[..], b"a");
+    assert_eq!(&properties.largest_key[..], b"c");
+    assert_eq!(properties.creation_time, 2000);
+    assert_eq!(properties.level, 1);
+    assert_eq!(&properties.user_properties["max.value.len"][..], b"5");
+}
+
+/// Claims a built-in property name.
+struct ReservedNameCollector;
+
+impl TablePropertiesCollector for ReservedNameCollector {
+    fn add(&mut self, _key: &[u8], _value: &[u8]) {}
+
+    fn finish(&mut self) -> Vec<(String, Bytes)> {
+        vec![(
+            "mini-lsm.num.deletions".to_string(),
+            0u64.to_be_bytes().to_vec().into(),
+        )]
+    }
+}
+
+#[test]
+fn test_table_properties_reserved_name() {
+    let mut builder = SsTableBuilder::new(16);
+    builder.set_file_system(Arc::new(MemFileSystem::new()));
+    builder.add_properties_collector(Box::new(ReservedNameCollector));
+    builder.add(b"11", b"");
+    let error = builder.build_for_test("1.sst").err().unwrap().to_string();
+    assert!(error.contains("reserved prefix"), "{}", error);
+}
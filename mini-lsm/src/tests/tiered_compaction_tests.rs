@@ -0,0 +1,186 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::harness::{num_stored_entries, open_in_memory_with_options, sst_exists, tiered_options};
+use crate::compact::TieredCompactionOptions;
+use crate::lsm_storage::{ColumnFamilyOptions, LsmStorageOptions, DEFAULT_CF};
+use crate::ttl::MockClock;
+
+fn storage_options(clock: Arc<MockClock>) -> LsmStorageOptions {
+    LsmStorageOptions {
+        clock,
+        default_cf: ColumnFamilyOptions {
+            block_size: 128,
+            target_sst_size: 1024,
+            ..tiered_options(TieredCompactionOptions {
+                num_sorted_runs_trigger: 3,
+                ..Default::default()
+            })
+        },
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_tiered_compaction() {
+    let storage = open_in_memory_with_options(storage_options(Arc::default()));
+    for round in 0..7 {
+        for i in 0..100 {
+            let key = format!("key_{:03}", i);
+            if i % 7 == round {
+                storage.delete(key.as_bytes()).unwrap();
+            } else {
+                storage
+                    .put(key.as_bytes(), format!("value_{}", round).as_bytes())
+                    .unwrap();
+            }
+        }
+        storage.sync().unwrap();
+        storage.trigger_compaction().unwrap();
+        assert!(storage.snapshot_for_test(DEFAULT_CF).levels().len() < 3);
+    }
+    assert!(storage
+        .snapshot_for_test(DEFAULT_CF)
+        .l0_sstables()
+        .is_empty());
+    for i in 0..100 {
+        let key = format!("key_{:03}", i);
+        let value = storage.get(key.as_bytes()).unwrap();
+        if i % 7 == 6 {
+            // deleted in the last round
+            assert!(value.is_none());
+        } else {
+            assert_eq!(&value.unwrap()[..], b"value_6");
+        }
+    }
+}
+
+#[test]
+fn test_tiered_compaction_drops_expired() {
+    let clock = Arc::new(MockClock::new(1000));
+    let storage = open_in_memory_with_options(storage_options(clock.clone()));
+    for round in 0..3 {
+        for i in 0..100 {
+            let key = format!("key_{:03}_{}", i, round);
+            if i % 2 == 0 {
+                storage
+                    .put_with_ttl(key.as_bytes(), b"233", Duration::from_secs(10))
+                    .unwrap();
+            } else {
+                storage.put(key.as_bytes(), b"2333").unwrap();
+            }
+        }
+        storage.sync().unwrap();
+    }
+    assert_eq!(num_stored_entries(&storage), 300);
+    clock.advance(Duration::from_secs(10));
+    // three runs of the same size always trigger a full, bottommost merge
+    storage.trigger_compaction().unwrap();
+    assert_eq!(storage.snapshot_for_test(DEFAULT_CF).levels().len(), 1);
+    assert_eq!(num_stored_entries(&storage), 150);
+}
+
+#[test]
+fn test_tiered_compaction_trivial_move() {
+    let storage = open_in_memory_with_options(storage_options(Arc::default()));
+    // sequential keys, so that no sorted run overlaps another
+    for round in 0..3 {
+        for i in 0..10 {
+            let key = format!("key_{}_{:03}", round, i);
+            storage.put(key.as_bytes(), b"value").unwrap();
+        }
+        storage.sync().unwrap();
+    }
+    let tables: Vec<_> = storage
+        .snapshot_for_test(DEFAULT_CF)
+        .levels()
+        .iter()
+        .flatten()
+        .cloned()
+        .collect();
+    storage.trigger_compaction().unwrap();
+    let snapshot = storage.snapshot_for_test(DEFAULT_CF);
+    assert_eq!(snapshot.levels().len(), 1);
+    let ids: Vec<_> = snapshot.levels()[0].iter().map(|x| x.sst_id()).collect();
+    // the run is ordered by key, which is from the oldest to the newest flush here
+    let mut expected: Vec<_> = tables.iter().map(|x| x.sst_id()).collect();
+    expected.sort_unstable();
+    assert_eq!(ids, expected);
+    for table in &tables {
+        assert!(sst_exists(&storage, table.sst_id()));
+    }
+    let stats = storage.stats().unwrap();
+    assert_eq!(
+        stats.compaction_bytes_moved,
+        tables.iter().map(|x| x.table_size()).sum::<u64>()
+    );
+    assert_eq!(stats.compaction_bytes_rewritten, 0);
+    assert!(storage.get(b"key_0_000").unwrap().is_some());
+}
+
+#[test]
+fn test_tiered_compaction_no_move_with_expired() {
+    let clock = Arc::new(MockClock::new(1000));
+    let storage = open_in_memory_with_options(storage_options(clock.clone()));
+    // sequential keys, so that no sorted run overlaps another
+    for round in 0..3 {
+        for i in 0..10 {
+            let key = format!("key_{}_{:03}", round, i);
+            storage
+                .put_with_ttl(key.as_bytes(), b"value", Duration::from_secs(10))
+                .unwrap();
+        }
+        storage.sync().unwrap();
+    }
+    let properties = storage.snapshot_for_test(DEFAULT_CF).levels()[0][0]
+        .properties()
+        .clone();
+    assert_eq!(properties.earliest_expire_at, 11000);
+    clock.advance(Duration::from_secs(5));
+    storage
+        .put_with_ttl(b"key_3", b"value", Duration::MAX)
+        .unwrap();
+    storage.sync().unwrap();
+    clock.advance(Duration::from_secs(5));
+    // the expired SSTs are rewritten, and the one that has not expired is moved
+    storage.trigger_compaction().unwrap();
+    let snapshot = storage.snapshot_for_test(DEFAULT_CF);
+    assert_eq!(snapshot.levels().len(), 1);
+    assert_eq!(num_stored_entries(&storage), 1);
+    assert!(storage.get(b"key_0_000").unwrap().is_none());
+    assert!(storage.get(b"key_3").unwrap().is_some());
+    let stats = storage.stats().unwrap();
+    assert_eq!(
+        stats.compaction_bytes_moved,
+        snapshot.levels()[0][0].table_size()
+    );
+}
+
+#[test]
+fn test_tiered_compaction_background_error() {
+    let options = storage_options(Arc::default());
+    let other_options = options.default_cf.clone();
+    let storage = open_in_memory_with_options(options);
+    storage
+        .create_column_family("other", other_options)
+        .unwrap();
+    // a value with an unknown tag fails every compaction job that reads it
+    let memtable = storage.snapshot_for_test(DEFAULT_CF).memtable.clone();
+    memtable.put(b"0", &[7, 2, 3, 3]);
+    for _ in 0..3 {
+        storage.put(b"1", b"233").unwrap();
+        storage.put_cf("other", b"1", b"233").unwrap();
+        storage.sync().unwrap();
+    }
+    assert!(storage.trigger_compaction().is_err());
+    let error = storage.stats().unwrap().background_error.unwrap();
+    assert!(error.contains("unknown value tag"), "{}", error);
+    // a failure does not stop the compaction of other column families
+    assert_eq!(storage.snapshot_for_test("other").levels().len(), 1);
+    assert!(storage
+        .stats_cf("other")
+        .unwrap()
+        .background_error
+        .is_none());
+    assert_eq!(&storage.get(b"1").unwrap().unwrap()[..], b"233");
+}
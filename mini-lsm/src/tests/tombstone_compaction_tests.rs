@@ -0,0 +1,68 @@
+use std::ops::Bound;
+
+use super::harness::{num_stored_entries, open_in_memory_with_options, tiered_options};
+use crate::compact::TieredCompactionOptions;
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions, DEFAULT_CF};
+
+fn storage_options(deletion_percent_trigger: u64) -> LsmStorageOptions {
+    LsmStorageOptions {
+        default_cf: tiered_options(TieredCompactionOptions {
+            num_sorted_runs_trigger: 100,
+            deletion_percent_trigger,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Write 100 keys in one run, then delete the first `num_deleted` of them in a second run.
+fn write_and_delete(storage: &LsmStorage, num_deleted: usize) {
+    for i in 0..100 {
+        let key = format!("key_{:03}", i);
+        storage.put(key.as_bytes(), b"value").unwrap();
+    }
+    storage.sync().unwrap();
+    for i in 0..num_deleted {
+        let key = format!("key_{:03}", i);
+        storage.delete(key.as_bytes()).unwrap();
+    }
+    storage.sync().unwrap();
+}
+
+#[test]
+fn test_compact_deletion_dense_run() {
+    let storage = open_in_memory_with_options(storage_options(50));
+    write_and_delete(&storage, 60);
+    storage.trigger_compaction().unwrap();
+    let snapshot = storage.snapshot_for_test(DEFAULT_CF);
+    assert_eq!(snapshot.levels().len(), 1);
+    assert_eq!(num_stored_entries(&storage), 40);
+    assert_eq!(snapshot.levels()[0][0].properties().num_deletions, 0);
+}
+
+#[test]
+fn test_no_compaction_below_deletion_percent() {
+    let storage = open_in_memory_with_options(storage_options(0));
+    write_and_delete(&storage, 60);
+    storage.trigger_compaction().unwrap();
+    assert_eq!(storage.snapshot_for_test(DEFAULT_CF).levels().len(), 2);
+    assert_eq!(num_stored_entries(&storage), 160);
+}
+
+#[test]
+fn test_scan_counts_skipped_tombstones() {
+    let storage = open_in_memory_with_options(storage_options(0));
+    write_and_delete(&storage, 60);
+    // one more version of a deleted key in the memtable, which is not counted twice
+    storage.delete(b"key_000").unwrap();
+    let mut iter = storage
+        .scan(Bound::Unbounded, Bound::Included(b"key_069"))
+        .unwrap();
+    assert_eq!(iter.key(), b"key_060");
+    assert_eq!(iter.num_skipped_tombstones(), 60);
+    while iter.is_valid() {
+        iter.next().unwrap();
+    }
+    assert_eq!(iter.num_skipped_tombstones(), 60);
+}
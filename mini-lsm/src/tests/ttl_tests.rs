@@ -0,0 +1,132 @@
+use std::ops::Bound;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use proptest::prelude::*;
+
+use super::harness::{check_iter_result, open_in_memory_with_options};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions, DEFAULT_CF};
+use crate::ttl::{decode_value, encode_value, MockClock};
+
+fn open_with_clock() -> (Arc<LsmStorage>, Arc<MockClock>) {
+    let clock = Arc::new(MockClock::new(1000));
+    let storage = open_in_memory_with_options(LsmStorageOptions {
+        clock: clock.clone(),
+        ..Default::default()
+    });
+    (storage, clock)
+}
+
+#[test]
+fn test_storage_get_ttl() {
+    let (storage, clock) = open_with_clock();
+    storage.put(b"1", b"233").unwrap();
+    storage
+        .put_with_ttl(b"2", b"2333", Duration::from_secs(10))
+        .unwrap();
+    storage
+        .put_with_ttl(b"3", b"23333", Duration::from_secs(20))
+        .unwrap();
+    assert_eq!(&storage.get(b"2").unwrap().unwrap()[..], b"2333");
+    clock.advance(Duration::from_secs(10));
+    assert!(storage.get(b"2").unwrap().is_none());
+    assert_eq!(&storage.get(b"3").unwrap().unwrap()[..], b"23333");
+    clock.advance(Duration::from_secs(10));
+    assert!(storage.get(b"3").unwrap().is_none());
+    assert_eq!(&storage.get(b"1").unwrap().unwrap()[..], b"233");
+    storage.put(b"3", b"233333").unwrap();
+    assert_eq!(&storage.get(b"3").unwrap().unwrap()[..], b"233333");
+}
+
+#[test]
+fn test_storage_get_ttl_after_sync() {
+    let (storage, clock) = open_with_clock();
+    storage.put(b"1", b"233").unwrap();
+    storage.sync().unwrap();
+    storage
+        .put_with_ttl(b"1", b"2333", Duration::from_secs(10))
+        .unwrap();
+    storage
+        .put_with_ttl(b"2", b"23333", Duration::from_secs(10))
+        .unwrap();
+    storage.sync().unwrap();
+    assert_eq!(&storage.get(b"1").unwrap().unwrap()[..], b"2333");
+    assert_eq!(&storage.get(b"2").unwrap().unwrap()[..], b"23333");
+    clock.advance(Duration::from_secs(10));
+    // the expired value hides the older one
+    assert!(storage.get(b"1").unwrap().is_none());
+    assert!(storage.get(b"2").unwrap().is_none());
+    assert!(storage.get(b"0").unwrap().is_none());
+}
+
+#[test]
+fn test_storage_scan_ttl() {
+    let (storage, clock) = open_with_clock();
+    storage
+        .put_with_ttl(b"1", b"233", Duration::from_secs(10))
+        .unwrap();
+    storage.put(b"2", b"2333").unwrap();
+    storage.sync().unwrap();
+    storage
+        .put_with_ttl(b"3", b"23333", Duration::from_secs(20))
+        .unwrap();
+    check_iter_result(
+        storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        vec![
+            (Bytes::from("1"), Bytes::from("233")),
+            (Bytes::from("2"), Bytes::from("2333")),
+            (Bytes::from("3"), Bytes::from("23333")),
+        ],
+    );
+    clock.advance(Duration::from_secs(10));
+    check_iter_result(
+        storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        vec![
+            (Bytes::from("2"), Bytes::from("2333")),
+            (Bytes::from("3"), Bytes::from("23333")),
+        ],
+    );
+    clock.advance(Duration::from_secs(10));
+    check_iter_result(
+        storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        vec![(Bytes::from("2"), Bytes::from("2333"))],
+    );
+}
+
+#[test]
+fn test_storage_ttl_overflow() {
+    let (storage, clock) = open_with_clock();
+    storage.put_with_ttl(b"1", b"233", Duration::MAX).unwrap();
+    clock.set(u64::MAX - 1);
+    assert_eq!(&storage.get(b"1").unwrap().unwrap()[..], b"233");
+}
+
+#[test]
+fn test_storage_corrupted_value() {
+    let (storage, _) = open_with_clock();
+    storage.put(b"1", b"233").unwrap();
+    let memtable = storage.snapshot_for_test(DEFAULT_CF).memtable.clone();
+    // an unknown tag, and an expiry cut short
+    memtable.put(b"2", &[7, 2, 3, 3]);
+    memtable.put(b"3", &[1, 0, 0]);
+    assert!(storage.get(b"2").is_err());
+    assert!(storage.get(b"3").is_err());
+    assert!(storage
+        .scan(Bound::Included(b"3"), Bound::Unbounded)
+        .is_err());
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    assert_eq!(iter.value(), b"233");
+    assert!(iter.next().is_err());
+}
+
+proptest! {
+    #[test]
+    fn test_decode_arbitrary_value(raw in proptest::collection::vec(any::<u8>(), 0..16)) {
+        // arbitrary bytes must not panic, and whatever decodes must encode back to the same bytes
+        if let Ok((value, expire_at)) = decode_value(&raw) {
+            prop_assert_eq!(encode_value(value, expire_at), raw);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use super::harness::open_in_memory_with_options;
+use crate::compact::{CompactionOptions, TieredCompactionOptions};
+use crate::lsm_storage::{ColumnFamilyOptions, LsmStorage, LsmStorageOptions};
+use crate::write_stall::{WriteStallCondition, WriteStallOptions};
+
+fn stall_options(compaction: CompactionOptions) -> LsmStorageOptions {
+    LsmStorageOptions {
+        default_cf: ColumnFamilyOptions {
+            compaction,
+            write_stall: WriteStallOptions {
+                l0_slowdown_writes_trigger: 2,
+                l0_stop_writes_trigger: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn write_and_sync(storage: &LsmStorage, num_batches: usize) {
+    for _ in 0..num_batches {
+        for i in 0..10 {
+            let key = format!("key_{:03}", i);
+            storage.put(key.as_bytes(), b"value").unwrap();
+        }
+        storage.sync().unwrap();
+    }
+}
+
+#[test]
+fn test_write_stall_condition() {
+    let options = WriteStallOptions {
+        l0_slowdown_writes_trigger: 4,
+        l0_stop_writes_trigger: 8,
+        imm_memtable_slowdown_writes_trigger: 2,
+        imm_memtable_stop_writes_trigger: 0,
+        soft_pending_compaction_bytes_limit: 100,
+        hard_pending_compaction_bytes_limit: 200,
+        ..Default::default()
+    };
+    assert_eq!(options.condition(3, 1, 99), WriteStallCondition::Normal);
+    assert_eq!(options.condition(4, 1, 99), WriteStallCondition::Delayed);
+    assert_eq!(options.condition(3, 2, 99), WriteStallCondition::Delayed);
+    assert_eq!(options.condition(3, 1, 100), WriteStallCondition::Delayed);
+    assert_eq!(options.condition(8, 1, 0), WriteStallCondition::Stopped);
+    assert_eq!(options.condition(0, 0, 200), WriteStallCondition::Stopped);
+    // a limit of zero is disabled
+    assert_eq!(options.condition(0, 100, 0), WriteStallCondition::Delayed);
+}
+
+#[test]
+fn test_no_write_stall_without_compaction() {
+    let storage = open_in_memory_with_options(stall_options(CompactionOptions::NoCompaction));
+    write_and_sync(&storage, 3);
+    let stats = storage.stats().unwrap();
+    assert_eq!(stats.num_l0_sstables, 3);
+    assert_eq!(stats.write_stall, WriteStallCondition::Normal);
+    assert_eq!(stats.num_stopped_writes, 0);
+}
+
+#[test]
+fn test_write_stall_waits_for_compaction() {
+    let compaction = CompactionOptions::Tiered(TieredCompactionOptions {
+        num_sorted_runs_trigger: 2,
+        ..Default::default()
+    });
+    let storage = open_in_memory_with_options(stall_options(compaction));
+    write_and_sync(&storage, 2);
+    // blocked until the background worker merges the two sorted runs, unless it already did
+    storage.put(b"key", b"value").unwrap();
+    let stats = storage.stats().unwrap();
+    assert_eq!(stats.num_sorted_runs, 1);
+    assert_eq!(stats.pending_compaction_bytes, 0);
+    assert_eq!(stats.write_stall, WriteStallCondition::Normal);
+}
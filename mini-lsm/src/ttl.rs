@@ -0,0 +1,107 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use bytes::{Buf, BufMut};
+
+/// Value written without an expiry.
+const VALUE_PLAIN: u8 = 0;
+/// Value followed by an expiry timestamp.
+const VALUE_WITH_TTL: u8 = 1;
+
+const SIZEOF_U64: usize = std::mem::size_of::<u64>();
+
+/// A source of wall-clock time, in milliseconds since the UNIX epoch. The storage uses it to stamp
+/// and check the expiry of keys written with a TTL.
+pub trait Clock: Send + Sync {
+    /// Get the current time in milliseconds.
+    fn now(&self) -> u64;
+}
+
+/// The default clock, backed by the system time.
+#[derive(Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time before UNIX epoch")
+            .as_millis() as u64
+    }
+}
+
+/// A clock that only moves when told to, for deterministic tests.
+#[derive(Default)]
+pub struct MockClock(AtomicU64);
+
+impl MockClock {
+    pub fn new(now: u64) -> Self {
+        Self(AtomicU64::new(now))
+    }
+
+    /// Set the current time.
+    pub fn set(&self, now: u64) {
+        self.0.store(now, Ordering::SeqCst);
+    }
+
+    /// Move the clock forward.
+    pub fn advance(&self, duration: Duration) {
+        self.0
+            .fetch_add(duration.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Encode a user value as stored in the memtables and SSTs. The first byte tells whether an expiry
+/// timestamp follows. An empty value is reserved for tombstones.
+pub(crate) fn encode_value(value: &[u8], expire_at: Option<u64>) -> Vec<u8> {
+    match expire_at {
+        None => {
+            let mut buf = Vec::with_capacity(1 + value.len());
+            buf.put_u8(VALUE_PLAIN);
+            buf.put_slice(value);
+            buf
+        }
+        Some(expire_at) => {
+            let mut buf = Vec::with_capacity(1 + SIZEOF_U64 + value.len());
+            buf.put_u8(VALUE_WITH_TTL);
+            buf.put_u64(expire_at);
+            buf.put_slice(value);
+            buf
+        }
+    }
+}
+
+/// Decode a stored value into the user value and its expiry. Tombstones and values with an unknown
+/// tag or a truncated expiry, e.g. from a corrupted or newer SST, are rejected.
+pub(crate) fn decode_value(raw: &[u8]) -> Result<(&[u8], Option<u64>)> {
+    match raw.split_first() {
+        None => bail!("cannot decode a tombstone"),
+        Some((&VALUE_PLAIN, value)) => Ok((value, None)),
+        Some((&VALUE_WITH_TTL, rest)) => {
+            if rest.len() < SIZEOF_U64 {
+                bail!("value with TTL is too short: {} bytes", raw.len());
+            }
+            let (mut expire_at, value) = rest.split_at(SIZEOF_U64);
+            Ok((value, Some(expire_at.get_u64())))
+        }
+        Some((tag, _)) => bail!("unknown value tag {}", tag),
+    }
+}
+
+/// Check whether a stored value is a tombstone or has expired at `now`.
+pub(crate) fn is_deleted_or_expired(raw: &[u8], now: u64) -> Result<bool> {
+    if raw.is_empty() {
+        return Ok(true);
+    }
+    Ok(match decode_value(raw)?.1 {
+        Some(expire_at) => expire_at <= now,
+        None => false,
+    })
+}
@@ -0,0 +1,156 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::env::{FileSystem, WritableFile};
+
+/// Append a record to `buf`, framed by its length and followed by its CRC32 checksum.
+pub(crate) fn encode_record(payload: &[u8], buf: &mut Vec<u8>) {
+    buf.put_u32(payload.len() as u32);
+    buf.put_slice(payload);
+    buf.put_u32(crc32fast::hash(payload));
+}
+
+/// Split a log written with [`encode_record`] into record payloads. The log ends at the first
+/// record that is cut short or fails its checksum, which is where a crash interrupted a write.
+pub(crate) fn decode_records(mut data: &[u8]) -> Vec<&[u8]> {
+    let mut records = Vec::new();
+    loop {
+        if data.remaining() < 4 {
+            break;
+        }
+        let len = (&data[..4]).get_u32() as usize;
+        if data.len() - 4 < len || data.len() - 4 - len < 4 {
+            break;
+        }
+        let payload = &data[4..4 + len];
+        let checksum = (&data[4 + len..]).get_u32();
+        if crc32fast::hash(payload) != checksum {
+            break;
+        }
+        records.push(payload);
+        data = &data[8 + len..];
+    }
+    records
+}
+
+/// Take a length-prefixed slice from `buf`.
+pub(crate) fn get_slice<'a>(buf: &mut &'a [u8], len_size: usize) -> Result<&'a [u8]> {
+    if buf.remaining() < len_size {
+        bail!("truncated record");
+    }
+    let len = buf.get_uint(len_size) as usize;
+    if buf.remaining() < len {
+        bail!("truncated record");
+    }
+    let (slice, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(slice)
+}
+
+/// Take a big-endian integer of `size` bytes from `buf`.
+pub(crate) fn get_uint(buf: &mut &[u8], size: usize) -> Result<u64> {
+    if buf.remaining() < size {
+        bail!("truncated record");
+    }
+    Ok(buf.get_uint(size))
+}
+
+/// A write recorded in the WAL. An empty value is a tombstone.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WalWrite {
+    pub cf_id: u32,
+    pub key: Bytes,
+    pub value: Bytes,
+}
+
+/// The writes applied to the memtables with one sequence number.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WalRecord {
+    pub seq: u64,
+    pub writes: Vec<WalWrite>,
+}
+
+impl WalRecord {
+    fn encode(seq: u64, writes: &[(u32, &[u8], &[u8])], buf: &mut Vec<u8>) {
+        buf.put_u64(seq);
+        buf.put_u32(writes.len() as u32);
+        for (cf_id, key, value) in writes {
+            buf.put_u32(*cf_id);
+            buf.put_u16(key.len() as u16);
+            buf.put_slice(key);
+            buf.put_u32(value.len() as u32);
+            buf.put_slice(value);
+        }
+    }
+
+    fn decode(mut buf: &[u8]) -> Result<Self> {
+        let seq = get_uint(&mut buf, 8)?;
+        let num_writes = get_uint(&mut buf, 4)?;
+        let mut writes = Vec::new();
+        for _ in 0..num_writes {
+            let cf_id = get_uint(&mut buf, 4)? as u32;
+            let key = Bytes::copy_from_slice(get_slice(&mut buf, 2)?);
+            let value = Bytes::copy_from_slice(get_slice(&mut buf, 4)?);
+            writes.push(WalWrite { cf_id, key, value });
+        }
+        if buf.has_remaining() {
+            bail!("trailing bytes in WAL record");
+        }
+        Ok(Self { seq, writes })
+    }
+}
+
+/// The write-ahead log of a storage. Every write is appended to the WAL before it is applied to
+/// the memtables, so that writes not flushed to SSTs yet are replayed when the storage is opened
+/// again. Writes are not synced one by one: `sync` makes all earlier writes durable.
+pub struct Wal {
+    id: usize,
+    file: Box<dyn WritableFile>,
+    /// Set once an append failed, which may have left part of a record in the file. Records
+    /// appended after it would be cut off by replay, so all later appends fail too.
+    failed: bool,
+}
+
+impl Wal {
+    pub fn create(fs: &dyn FileSystem, path: &Path, id: usize) -> Result<Self> {
+        Ok(Self {
+            id,
+            file: fs.create(path)?,
+            failed: false,
+        })
+    }
+
+    /// The ID of the WAL file. WAL files are numbered in the order they are created.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Append the writes of a sequence number, given as column family ID, key and stored value.
+    pub fn append(&mut self, seq: u64, writes: &[(u32, &[u8], &[u8])]) -> Result<()> {
+        if self.failed {
+            bail!("an earlier write to the WAL failed");
+        }
+        let mut payload = Vec::new();
+        WalRecord::encode(seq, writes, &mut payload);
+        let mut buf = Vec::with_capacity(payload.len() + 8);
+        encode_record(&payload, &mut buf);
+        let result = self.file.append(&buf);
+        self.failed = result.is_err();
+        result
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        self.file.sync()
+    }
+
+    /// Decode the records of a WAL file. Replay stops at a record cut short by a crash, but a
+    /// record that passes its checksum and does not decode is an error.
+    pub fn replay(data: &[u8]) -> Result<Vec<WalRecord>> {
+        decode_records(data)
+            .into_iter()
+            .map(WalRecord::decode)
+            .collect()
+    }
+}
@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::lsm_storage::DEFAULT_CF;
+
+/// A single write in a [`WriteBatch`].
+pub enum WriteBatchRecord {
+    Put {
+        cf: String,
+        key: Bytes,
+        value: Bytes,
+        /// The key expires this long after the batch is written, if set.
+        ttl: Option<Duration>,
+    },
+    Delete {
+        cf: String,
+        key: Bytes,
+    },
+}
+
+impl WriteBatchRecord {
+    /// The column family this record writes to.
+    pub fn cf(&self) -> &str {
+        match self {
+            WriteBatchRecord::Put { cf, .. } => cf,
+            WriteBatchRecord::Delete { cf, .. } => cf,
+        }
+    }
+}
+
+/// A group of writes applied together by `LsmStorage::write`.
+#[derive(Default)]
+pub struct WriteBatch {
+    records: Vec<WriteBatchRecord>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Put a key-value pair into the default column family.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.put_cf(DEFAULT_CF, key, value);
+    }
+
+    /// Put a key-value pair into a column family.
+    pub fn put_cf(&mut self, cf: &str, key: &[u8], value: &[u8]) {
+        self.put_record(cf, key, value, None);
+    }
+
+    /// Put a key-value pair that expires after `ttl` into the default column family.
+    pub fn put_with_ttl(&mut self, key: &[u8], value: &[u8], ttl: Duration) {
+        self.put_with_ttl_cf(DEFAULT_CF, key, value, ttl);
+    }
+
+    /// Put a key-value pair that expires after `ttl` into a column family.
+    pub fn put_with_ttl_cf(&mut self, cf: &str, key: &[u8], value: &[u8], ttl: Duration) {
+        self.put_record(cf, key, value, Some(ttl));
+    }
+
+    fn put_record(&mut self, cf: &str, key: &[u8], value: &[u8], ttl: Option<Duration>) {
+        self.records.push(WriteBatchRecord::Put {
+            cf: cf.to_string(),
+            key: Bytes::copy_from_slice(key),
+            value: Bytes::copy_from_slice(value),
+            ttl,
+        });
+    }
+
+    /// Remove a key from the default column family.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.delete_cf(DEFAULT_CF, key);
+    }
+
+    /// Remove a key from a column family.
+    pub fn delete_cf(&mut self, cf: &str, key: &[u8]) {
+        self.records.push(WriteBatchRecord::Delete {
+            cf: cf.to_string(),
+            key: Bytes::copy_from_slice(key),
+        });
+    }
+
+    pub fn records(&self) -> &[WriteBatchRecord] {
+        &self.records
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+/// Limits that make writers wait when compaction of a column family cannot keep up. A limit of
+/// zero is disabled.
+///
+/// Writes are only held back while the compaction strategy has work to do, since waiting cannot
+/// help otherwise.
+#[derive(Clone, Debug)]
+pub struct WriteStallOptions {
+    /// Delay writes once there are this many L0 SSTs, or sorted runs with tiered compaction.
+    pub l0_slowdown_writes_trigger: usize,
+    /// Block writes once there are this many L0 SSTs, or sorted runs with tiered compaction.
+    pub l0_stop_writes_trigger: usize,
+    /// Delay writes once there are this many immutable memtables waiting to be flushed.
+    pub imm_memtable_slowdown_writes_trigger: usize,
+    /// Block writes once there are this many immutable memtables waiting to be flushed.
+    pub imm_memtable_stop_writes_trigger: usize,
+    /// Delay writes once the next compaction is estimated to rewrite this many bytes.
+    pub soft_pending_compaction_bytes_limit: u64,
+    /// Block writes once the next compaction is estimated to rewrite this many bytes.
+    pub hard_pending_compaction_bytes_limit: u64,
+    /// How long each delayed write sleeps.
+    pub slowdown_delay: Duration,
+}
+
+impl Default for WriteStallOptions {
+    fn default() -> Self {
+        Self {
+            l0_slowdown_writes_trigger: 20,
+            l0_stop_writes_trigger: 36,
+            imm_memtable_slowdown_writes_trigger: 0,
+            imm_memtable_stop_writes_trigger: 0,
+            soft_pending_compaction_bytes_limit: 64 << 30,
+            hard_pending_compaction_bytes_limit: 256 << 30,
+            slowdown_delay: Duration::from_millis(1),
+        }
+    }
+}
+
+/// Whether writes to a column family are currently held back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum WriteStallCondition {
+    Normal,
+    /// Every write sleeps for `slowdown_delay`.
+    Delayed,
+    /// Writes block until compaction catches up.
+    Stopped,
+}
+
+impl WriteStallCondition {
+    /// Convert back from the value stored in an atomic.
+    pub(crate) fn from_u8(value: u8) -> Self {
+        match value {
+            0 => WriteStallCondition::Normal,
+            1 => WriteStallCondition::Delayed,
+            _ => WriteStallCondition::Stopped,
+        }
+    }
+}
+
+impl WriteStallOptions {
+    pub(crate) fn condition(
+        &self,
+        num_l0: usize,
+        num_imm_memtables: usize,
+        pending_compaction_bytes: u64,
+    ) -> WriteStallCondition {
+        let reached = |value: u64, limit: u64| limit != 0 && value >= limit;
+        let (num_l0, num_imm_memtables) = (num_l0 as u64, num_imm_memtables as u64);
+        if reached(num_l0, self.l0_stop_writes_trigger as u64)
+            || reached(
+                num_imm_memtables,
+                self.imm_memtable_stop_writes_trigger as u64,
+            )
+            || reached(
+                pending_compaction_bytes,
+                self.hard_pending_compaction_bytes_limit,
+            )
+        {
+            WriteStallCondition::Stopped
+        } else if reached(num_l0, self.l0_slowdown_writes_trigger as u64)
+            || reached(
+                num_imm_memtables,
+                self.imm_memtable_slowdown_writes_trigger as u64,
+            )
+            || reached(
+                pending_compaction_bytes,
+                self.soft_pending_compaction_bytes_limit,
+            )
+        {
+            WriteStallCondition::Delayed
+        } else {
+            WriteStallCondition::Normal
+        }
+    }
+}